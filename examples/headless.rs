@@ -0,0 +1,150 @@
+#![allow(dead_code)]
+
+extern crate cgmath;
+extern crate render;
+extern crate vulkano;
+#[macro_use]
+extern crate vulkano_shader_derive;
+extern crate image;
+extern crate log;
+
+use std::sync::Arc;
+
+use cgmath::*;
+
+use vulkano::descriptor::DescriptorSet;
+use vulkano::sync::GpuFuture;
+
+use render::camera::*;
+use render::graphics::*;
+use render::scene::*;
+
+// Renders a single frame without a window and writes it to headless.png.
+// With VALIDATE set, fails if the validation layer reports any problems.
+fn main() {
+    let graphics = GraphicsBuilder::new()
+        .headless()
+        .dimensions([512, 512])
+        .validation(std::env::var("VALIDATE").is_ok())
+        .build()
+        .expect("failed to create graphics");
+
+    let (model, model_future) = graphics.load_model("resources/sphere.obj");
+
+    let mut scene = Scene::new();
+    let model_node = scene.add_node("model", None, Transform::identity());
+    scene.node_mut(model_node).mesh = Some(model.clone());
+    let camera_node = scene.add_node("camera", None, Transform::looking_at(
+        Point3 { x: 0.0, y: 0.5, z: 2.0 },
+        Point3 { x: 0.0, y: 0.0, z: 0.0 },
+        Vector3::unit_y()));
+    scene.node_mut(camera_node).camera = Some(Camera::perspective(Rad(std::f32::consts::FRAC_PI_2), 0.01, 100.0));
+    scene.follow_viewport(graphics.viewport.clone());
+
+    let uniform_buffer = vulkano::buffer::cpu_pool::CpuBufferPool::<vs::ty::Data>::new(
+        graphics.device.clone(),
+        vulkano::buffer::BufferUsage::all());
+
+    let vs = vs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+    let fs = fs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+
+    let pipeline = graphics.create_pipeline(vs.main_entry_point(), fs.main_entry_point());
+
+    let mut pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(pipeline.clone(), 0);
+
+    let view = scene.view_matrix(camera_node);
+    let camera = scene.camera(camera_node);
+    let proj = camera.projection();
+
+    // Headless graphics have a single framebuffer and nothing to acquire or present.
+    let mut command_buffer = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(graphics.device.clone(), graphics.queue.family()).unwrap()
+        .begin_render_pass(
+            graphics.framebuffers[0].clone(), false,
+            graphics.clear_values([0.0, 0.0, 1.0, 1.0], camera.depth_clear_value())).unwrap();
+
+    for (world, mesh) in scene.meshes() {
+        let uniform_data = vs::ty::Data {
+            world : world.into(),
+            view : view.into(),
+            proj : proj.into(),
+        };
+
+        let set: Arc<DescriptorSet + Send + Sync> = Arc::from(pool.next()
+            .add_buffer(uniform_buffer.next(uniform_data).unwrap()).unwrap()
+            .build().unwrap());
+
+        command_buffer = command_buffer
+            .draw(
+                pipeline.clone(),
+                &graphics.dynamic_state,
+                vec![mesh.clone()],
+                set.clone(),
+                ()).unwrap();
+    }
+
+    let command_buffer = command_buffer
+        .end_render_pass().unwrap()
+        .build().unwrap();
+
+    let future = model_future
+        .then_execute(graphics.queue.clone(), command_buffer).unwrap();
+
+    graphics.read_offscreen_image(future)
+        .save("headless.png").expect("failed to save image");
+
+    let problems: Vec<_> = graphics.take_validation_messages().into_iter()
+        .filter(|message| message.level <= log::Level::Warn)
+        .collect();
+    if !problems.is_empty() {
+        panic!("Frame produced validation messages: {:?}", problems);
+    }
+}
+
+mod vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in vec3 tangent_u;
+layout(location = 4) in vec3 tangent_v;
+
+layout(location = 0) out vec3 v_world_normal;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+} uniforms;
+
+void main() {
+    v_world_normal = transpose(inverse(mat3(uniforms.world))) * normal;
+    gl_Position = uniforms.proj * uniforms.view * uniforms.world * vec4(position, 1.0);
+}
+"]
+    struct Dummy;
+}
+
+mod fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 v_world_normal;
+
+layout(location = 0) out vec4 f_color;
+
+const vec3 LIGHT_DIRECTION = vec3(0.3, 1.0, 0.5);
+const vec3 AMBIENT_LIGHT = vec3(0.1, 0.1, 0.1);
+
+void main() {
+    float lambert = max(dot(normalize(v_world_normal), normalize(LIGHT_DIRECTION)), 0.0);
+    f_color = vec4(vec3(lambert) + AMBIENT_LIGHT, 1.0);
+}
+"]
+    struct Dummy;
+}
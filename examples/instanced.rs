@@ -0,0 +1,193 @@
+extern crate cgmath;
+extern crate winit;
+extern crate render;
+extern crate vulkano;
+
+use std::sync::Arc;
+
+use cgmath::*;
+
+use vulkano::buffer::BufferAccess;
+use vulkano::buffer::TypedBufferAccess;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::sync::GpuFuture;
+
+use render::camera::*;
+use render::geometry::InstanceData;
+use render::geometry::Vertex;
+use render::glsl::HotPipeline;
+use render::graphics::*;
+use render::pipeline::PipelineBuilder;
+use render::scene::*;
+
+// Copies of the cube along each side of the grid.
+const GRID_SIZE: usize = 100;
+const SPACING: f32 = 1.5;
+
+const VERTEX_SHADER: &str = "resources/shaders/instanced.vert";
+// Reads the instances from a storage buffer instead, with `--storage`.
+const STORAGE_VERTEX_SHADER: &str = "resources/shaders/instanced_storage.vert";
+const FRAGMENT_SHADER: &str = "resources/shaders/instanced.frag";
+
+// The `Data` block of the vertex shader.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Data {
+    view: [[f32; 4]; 4],
+    proj: [[f32; 4]; 4],
+}
+
+fn main() {
+    let mut graphics = match GraphicsBuilder::new().title("instanced").samples(4).build() {
+        Ok(graphics) => graphics,
+        Err(err) => {
+            println!("Failed to initialize graphics: {}", err);
+            return;
+        }
+    };
+
+    // A single draw call draws every cube, each with its own world matrix
+    // and color from the instance buffer. The buffer is bound either as a
+    // second vertex buffer, or with `--storage` as a storage buffer that the
+    // vertex shader indexes by instance.
+    let storage = std::env::args().any(|arg| arg == "--storage");
+    let (model, model_future) = graphics.load_model("resources/cube.obj");
+    let offset = (GRID_SIZE - 1) as f32 * SPACING / 2.0;
+    let instances: Vec<InstanceData> = (0..GRID_SIZE * GRID_SIZE).map(|i| {
+        let (x, z) = ((i % GRID_SIZE) as f32, (i / GRID_SIZE) as f32);
+        let world = Matrix4::from_translation(vec3(x * SPACING - offset, 0.0, z * SPACING - offset)) * Matrix4::from_scale(0.5);
+        let color = [x / GRID_SIZE as f32, 0.5, z / GRID_SIZE as f32, 1.0];
+        InstanceData::new(world, color)
+    }).collect();
+    // The pool's buffers can be bound both ways.
+    let instance_pool = graphics.create_instance_pool();
+    let indirect = graphics.create_instanced_draw(model.len() as u32, instances.len() as u32);
+
+    let mut scene = Scene::new();
+    let camera_node = scene.add_node("camera", None, Transform::identity());
+    scene.node_mut(camera_node).camera = Some(Camera::perspective(cgmath::Rad(std::f32::consts::FRAC_PI_2), 0.1, 500.0));
+    scene.follow_viewport(graphics.viewport.clone());
+
+    let mut controller = OrbitController::new(Point3 { x: 0.0, y: 0.0, z: 0.0 }, 80.0);
+    controller.pitch = cgmath::Rad(0.6);
+
+    let uniform_buffer = vulkano::buffer::cpu_pool::CpuBufferPool::<Data>::new(
+        graphics.device.clone(),
+        vulkano::buffer::BufferUsage::all());
+
+    let pipeline = if storage {
+        HotPipeline::with_builder::<Vertex>(&graphics, PipelineBuilder::new(), STORAGE_VERTEX_SHADER, FRAGMENT_SHADER)
+    } else {
+        HotPipeline::instanced::<Vertex, InstanceData>(&graphics, PipelineBuilder::new(), VERTEX_SHADER, FRAGMENT_SHADER)
+    };
+    let mut pipeline = match pipeline {
+        Ok(pipeline) => pipeline,
+        Err(err) => {
+            println!("Failed to build pipeline: {}", err);
+            return;
+        }
+    };
+    let mut pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(pipeline.pipeline(), 0);
+
+    let mut recreate_swapchain = false;
+
+    let mut previous_frame: Box<GpuFuture> = model_future;
+    let mut last_frame = std::time::Instant::now();
+
+    loop {
+        previous_frame.cleanup_finished();
+
+        if recreate_swapchain {
+            if !graphics.recreate_swapchain() {
+                continue;
+            }
+            recreate_swapchain = false;
+        }
+
+        if pipeline.update(&graphics) {
+            pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(pipeline.pipeline(), 0);
+        }
+
+        let dt = last_frame.elapsed();
+        last_frame = std::time::Instant::now();
+        scene.update_camera(camera_node, &mut controller, dt.as_secs() as f32 + dt.subsec_nanos() as f32 / 1_000_000_000.0);
+
+        let camera = scene.camera(camera_node);
+        let uniform_data = Data {
+            view: scene.view_matrix(camera_node).into(),
+            proj: camera.projection().into(),
+        };
+
+        let (image_num, acquire_future) = match vulkano::swapchain::acquire_next_image(graphics.swapchain(), None) {
+            Ok(r) => r,
+            Err(vulkano::swapchain::AcquireError::OutOfDate) => {
+                recreate_swapchain = true;
+                continue;
+            },
+            Err(err) => panic!("{:?}", err)
+        };
+
+        // Uploaded every frame, as instances that move would be.
+        let instance_buffer = instance_pool.chunk(instances.iter().cloned()).unwrap();
+
+        let command_buffer = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(graphics.device.clone(), graphics.queue.family()).unwrap()
+            .begin_render_pass(
+                graphics.framebuffers[image_num].clone(), false,
+                graphics.clear_values([0.1, 0.1, 0.1, 1.0], camera.depth_clear_value())).unwrap();
+        let command_buffer = if storage {
+            let set: Arc<DescriptorSet + Send + Sync> = Arc::new(pool.next()
+                .add_buffer(uniform_buffer.next(uniform_data).unwrap()).unwrap()
+                .add_buffer(instance_buffer).unwrap()
+                .build().unwrap());
+            command_buffer.draw_indirect(
+                pipeline.pipeline(),
+                &graphics.dynamic_state,
+                vec![model.clone() as Arc<BufferAccess + Send + Sync>],
+                indirect.clone(),
+                set,
+                ()).unwrap()
+        } else {
+            let set: Arc<DescriptorSet + Send + Sync> = Arc::new(pool.next()
+                .add_buffer(uniform_buffer.next(uniform_data).unwrap()).unwrap()
+                .build().unwrap());
+            command_buffer.draw(
+                pipeline.pipeline(),
+                &graphics.dynamic_state,
+                vec![model.clone() as Arc<BufferAccess + Send + Sync>, Arc::new(instance_buffer)],
+                set,
+                ()).unwrap()
+        };
+        let command_buffer = command_buffer
+            .end_render_pass().unwrap()
+            .build().unwrap();
+
+        let future = previous_frame.join(acquire_future)
+            .then_execute(graphics.queue.clone(), command_buffer).unwrap()
+            .then_swapchain_present(graphics.queue.clone(), graphics.swapchain(), image_num)
+            .then_signal_fence_and_flush();
+
+        match future {
+            Ok(future) => {
+                previous_frame = Box::new(future) as Box<_>;
+            }
+            Err(vulkano::sync::FlushError::OutOfDate) => {
+                recreate_swapchain = true;
+                previous_frame = Box::new(vulkano::sync::now(graphics.device.clone())) as Box<_>;
+            }
+            Err(e) => {
+                println!("{:?}", e);
+                previous_frame = Box::new(vulkano::sync::now(graphics.device.clone())) as Box<_>;
+            }
+        }
+
+        let mut done = false;
+        graphics.poll_events(|ev| {
+            controller.handle_event(&ev);
+            match ev {
+                winit::Event::WindowEvent { event: winit::WindowEvent::CloseRequested, .. } => done = true,
+                _ => ()
+            }
+        });
+        if done { return; }
+    }
+}
@@ -0,0 +1,249 @@
+#![allow(dead_code)]
+
+extern crate cgmath;
+extern crate winit;
+extern crate time;
+extern crate render;
+extern crate vulkano;
+#[macro_use]
+extern crate vulkano_shader_derive;
+extern crate vulkano_win;
+
+use std::sync::Arc;
+
+use cgmath::*;
+
+use vulkano::descriptor::DescriptorSet;
+use vulkano::sync::GpuFuture;
+
+use render::camera::*;
+use render::geometry::*;
+use render::graphics::*;
+use render::morph::*;
+use render::obj::ObjModel;
+use render::scene::*;
+
+// Pushes every vertex out along its normal, more towards the equator.
+fn bulge(base: &[Vertex]) -> MorphTarget {
+    MorphTarget::from_positions("bulge", base.iter().map(|vertex| {
+        let normal = Vector3 { x: vertex.normal.0, y: vertex.normal.1, z: vertex.normal.2 };
+        normal * (1.0 - vertex.position.1.abs()) * 0.5
+    }).collect())
+}
+
+// Flattens the sphere, tilting normals towards the poles to match.
+fn squash(base: &[Vertex]) -> MorphTarget {
+    let morphed: Vec<Vertex> = base.iter().map(|vertex| {
+        let normal = Vector3 { x: vertex.normal.0, y: vertex.normal.1 * 2.0, z: vertex.normal.2 }.normalize();
+        Vertex {
+            position: (vertex.position.0 * 1.25, vertex.position.1 * 0.5, vertex.position.2 * 1.25),
+            normal: (normal.x, normal.y, normal.z),
+            .. *vertex
+        }
+    }).collect();
+    MorphTarget::from_vertices("squash", base, &morphed)
+}
+
+fn main() {
+    let mut graphics = Graphics::new();
+
+    let mut mesh = MorphMesh::new(ObjModel::from_file("resources/sphere.obj").vertices());
+    let bulge = bulge(&mesh.base);
+    let bulge = mesh.add_target(bulge);
+    let squash = squash(&mesh.base);
+    let squash = mesh.add_target(squash);
+
+    let (model, model_future) = graphics.load_vertex_buffer(mesh.base.clone());
+    let deltas = graphics.load_morph_deltas(&mesh);
+    let weights_buffer = graphics.create_morph_weights_pool();
+
+    let mut scene = Scene::new();
+    let model_node = scene.add_node("model", None, Transform::identity());
+    let camera_node = scene.add_node("camera", None, Transform::identity());
+    scene.node_mut(camera_node).camera = Some(Camera::perspective(Rad(std::f32::consts::FRAC_PI_2), 0.01, 100.0));
+    scene.follow_viewport(graphics.viewport.clone());
+    let mut controller = OrbitController::new(Point3 { x: 0.0, y: 0.0, z: 0.0 }, 2.5);
+
+    let uniform_buffer = vulkano::buffer::cpu_pool::CpuBufferPool::<vs::ty::Data>::new(
+        graphics.device.clone(),
+        vulkano::buffer::BufferUsage::all());
+
+    let vs = vs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+    let fs = fs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+
+    let pipeline = graphics.create_pipeline(vs.main_entry_point(), fs.main_entry_point());
+
+    let mut pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(pipeline.clone(), 0);
+
+    let mut recreate_swapchain = false;
+
+    let mut previous_frame: Box<GpuFuture> = model_future;
+
+    let start = std::time::Instant::now();
+    let mut last_frame = std::time::Instant::now();
+
+    loop {
+        previous_frame.cleanup_finished();
+
+        if recreate_swapchain {
+            if !graphics.recreate_swapchain() {
+                continue;
+            }
+            recreate_swapchain = false;
+        }
+
+        let dt = last_frame.elapsed();
+        last_frame = std::time::Instant::now();
+        scene.update_camera(camera_node, &mut controller, dt.as_secs() as f32 + dt.subsec_nanos() as f32 / 1_000_000_000.0);
+
+        let elapsed = start.elapsed();
+        let time = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+        mesh.weights[bulge] = time.sin() * 0.5 + 0.5;
+        mesh.weights[squash] = (time * 0.7).cos() * 0.5 + 0.5;
+
+        let weights = weights_buffer.chunk(mesh.weights.iter().cloned()).unwrap();
+
+        let camera = scene.camera(camera_node);
+        let uniform_data = vs::ty::Data {
+            world : scene.world_matrix(model_node).into(),
+            view : scene.view_matrix(camera_node).into(),
+            proj : camera.projection().into(),
+        };
+
+        let set: Arc<DescriptorSet + Send + Sync> = Arc::from(pool.next()
+            .add_buffer(uniform_buffer.next(uniform_data).unwrap()).unwrap()
+            .add_buffer(deltas.clone()).unwrap()
+            .add_buffer(weights).unwrap()
+            .build().unwrap());
+
+        let (image_num, acquire_future) = match vulkano::swapchain::acquire_next_image(graphics.swapchain(),
+                                                                                       None) {
+            Ok(r) => r,
+            Err(vulkano::swapchain::AcquireError::OutOfDate) => {
+                recreate_swapchain = true;
+                continue;
+            },
+            Err(err) => panic!("{:?}", err)
+        };
+
+        let command_buffer = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(graphics.device.clone(), graphics.queue.family()).unwrap()
+            .begin_render_pass(
+                graphics.framebuffers[image_num].clone(), false,
+                graphics.clear_values([0.0, 0.0, 1.0, 1.0], camera.depth_clear_value())).unwrap()
+            .draw(
+                pipeline.clone(),
+                &graphics.dynamic_state,
+                vec![model.clone()],
+                set.clone(),
+                ()).unwrap()
+            .end_render_pass().unwrap()
+            .build().unwrap();
+
+        let future = previous_frame.join(acquire_future)
+            .then_execute(graphics.queue.clone(), command_buffer).unwrap()
+            .then_swapchain_present(graphics.queue.clone(), graphics.swapchain(), image_num)
+            .then_signal_fence_and_flush();
+
+        match future {
+            Ok(future) => {
+                previous_frame = Box::new(future) as Box<_>;
+            }
+            Err(vulkano::sync::FlushError::OutOfDate) => {
+                recreate_swapchain = true;
+                previous_frame = Box::new(vulkano::sync::now(graphics.device.clone())) as Box<_>;
+            }
+            Err(e) => {
+                println!("{:?}", e);
+                previous_frame = Box::new(vulkano::sync::now(graphics.device.clone())) as Box<_>;
+            }
+        }
+
+        let mut done = false;
+        graphics.poll_events(|ev| {
+            controller.handle_event(&ev);
+            match ev {
+                winit::Event::WindowEvent { event: winit::WindowEvent::CloseRequested, .. } => done = true,
+                _ => ()
+            }
+        });
+        if done { return; }
+    }
+}
+
+mod vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in vec3 tangent_u;
+layout(location = 4) in vec3 tangent_v;
+
+layout(location = 0) out vec3 v_world_normal;
+layout(location = 1) out vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+} uniforms;
+
+// Laid out as described by MorphMesh::delta_data.
+layout(set = 0, binding = 1) readonly buffer MorphDeltas {
+    vec4 deltas[];
+} morph;
+
+layout(set = 0, binding = 2) readonly buffer MorphWeights {
+    float weights[];
+} morph_weights;
+
+const int DELTA_VEC4S_PER_VERTEX = 4;
+
+void main() {
+    vec3 morphed_position = position;
+    vec3 morphed_normal = normal;
+
+    int targets = morph_weights.weights.length();
+    int vertex_count = morph.deltas.length() / (DELTA_VEC4S_PER_VERTEX * targets);
+    for (int t = 0; t < targets; t++) {
+        float weight = morph_weights.weights[t];
+        if (weight == 0.0) {
+            continue;
+        }
+        int start = (t * vertex_count + gl_VertexIndex) * DELTA_VEC4S_PER_VERTEX;
+        morphed_position += weight * morph.deltas[start].xyz;
+        morphed_normal += weight * morph.deltas[start + 1].xyz;
+    }
+
+    v_world_normal = transpose(inverse(mat3(uniforms.world))) * morphed_normal;
+    v_uv = uv;
+    gl_Position = uniforms.proj * uniforms.view * uniforms.world * vec4(morphed_position, 1.0);
+}
+"]
+    struct Dummy;
+}
+
+mod fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 v_world_normal;
+layout(location = 1) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+const vec3 LIGHT_DIRECTION = vec3(0.3, 1.0, 0.5);
+const vec3 AMBIENT_LIGHT = vec3(0.1, 0.1, 0.1);
+
+void main() {
+    float lambert = max(dot(normalize(v_world_normal), normalize(LIGHT_DIRECTION)), 0.0);
+    f_color = vec4(vec3(lambert) + AMBIENT_LIGHT, 1.0);
+}
+"]
+    struct Dummy;
+}
@@ -0,0 +1,284 @@
+#![allow(dead_code)]
+
+extern crate cgmath;
+extern crate winit;
+extern crate time;
+extern crate render;
+extern crate vulkano;
+#[macro_use]
+extern crate vulkano_shader_derive;
+extern crate vulkano_win;
+
+use std::sync::Arc;
+
+use cgmath::*;
+
+use vulkano::descriptor::DescriptorSet;
+use vulkano::sync::GpuFuture;
+
+use render::animation::*;
+use render::camera::*;
+use render::geometry::*;
+use render::graphics::*;
+use render::scene::*;
+
+const RINGS: usize = 16;
+const SEGMENTS: usize = 24;
+const HEIGHT: f32 = 2.0;
+const RADIUS: f32 = 0.25;
+
+// A tube along +Y, skinned to a root joint at its base and a joint half way up.
+fn tube_vertices() -> Vec<SkinnedVertex> {
+    let ring_vertex = |ring: usize, segment: usize| {
+        let angle = segment as f32 / SEGMENTS as f32 * 2.0 * std::f32::consts::PI;
+        let y = ring as f32 / RINGS as f32 * HEIGHT;
+        ModelVertex {
+            position: Point3 { x: RADIUS * angle.cos(), y, z: RADIUS * angle.sin() },
+            normal: Vector3 { x: angle.cos(), y: 0.0, z: angle.sin() },
+            uv: Point2 { x: segment as f32 / SEGMENTS as f32, y: ring as f32 / RINGS as f32 },
+        }
+    };
+    let skin = |vertex: Vertex| {
+        // Blend between the joints over the middle third of the tube.
+        let t = ((vertex.position.1 / HEIGHT - 1.0 / 3.0) * 3.0).max(0.0).min(1.0);
+        SkinnedVertex::new(vertex, (0, 1, 0, 0), (1.0 - t, t, 0.0, 0.0))
+    };
+
+    let mut verts = vec![];
+    for ring in 0..RINGS {
+        for segment in 0..SEGMENTS {
+            let tri_1 = compute_triangle(
+                ring_vertex(ring, segment),
+                ring_vertex(ring + 1, segment),
+                ring_vertex(ring + 1, segment + 1),
+            );
+            let tri_2 = compute_triangle(
+                ring_vertex(ring + 1, segment + 1),
+                ring_vertex(ring, segment + 1),
+                ring_vertex(ring, segment),
+            );
+            for vertex in &[tri_1.0, tri_1.1, tri_1.2, tri_2.0, tri_2.1, tri_2.2] {
+                verts.push(skin(*vertex));
+            }
+        }
+    }
+    verts
+}
+
+fn skeleton() -> Skeleton {
+    let mut skeleton = Skeleton::new(vec![
+        Joint {
+            name: "base".to_string(),
+            parent: None,
+            inverse_bind: Matrix4::identity(),
+            rest: Transform::identity(),
+        },
+        Joint {
+            name: "middle".to_string(),
+            parent: Some(0),
+            inverse_bind: Matrix4::identity(),
+            rest: Transform::from_translation(Vector3 { x: 0.0, y: HEIGHT / 2.0, z: 0.0 }),
+        },
+    ]);
+    skeleton.bind_rest_pose();
+    skeleton
+}
+
+fn rotation_clip(name: &str, axis: Vector3<f32>, degrees: f32) -> AnimationClip {
+    let rotation = |d: f32| Quaternion::from_axis_angle(axis, Deg(d));
+    AnimationClip::new(name, vec![Channel {
+        joint: 1,
+        values: ChannelValues::Rotation(Keyframes::new(
+            vec![0.0, 1.0, 2.0, 3.0, 4.0],
+            vec![rotation(0.0), rotation(degrees), rotation(0.0), rotation(-degrees), rotation(0.0)],
+            Interpolation::Linear)),
+    }])
+}
+
+fn main() {
+    let mut graphics = Graphics::new();
+
+    let (model, model_future) = graphics.load_vertex_buffer(tube_vertices());
+
+    let skeleton = skeleton();
+    let bend = rotation_clip("bend", Vector3::unit_z(), 60.0);
+    let twist = rotation_clip("twist", Vector3::unit_y(), 90.0);
+
+    let mut scene = Scene::new();
+    let model_node = scene.add_node("model", None, Transform::from_translation(Vector3 { x: 0.0, y: -1.0, z: 0.0 }));
+    let camera_node = scene.add_node("camera", None, Transform::identity());
+    scene.node_mut(camera_node).camera = Some(Camera::perspective(Rad(std::f32::consts::FRAC_PI_2), 0.01, 100.0));
+    scene.follow_viewport(graphics.viewport.clone());
+    let mut controller = OrbitController::new(Point3 { x: 0.0, y: 0.0, z: 0.0 }, 3.0);
+
+    let uniform_buffer = vulkano::buffer::cpu_pool::CpuBufferPool::<vs::ty::Data>::new(
+        graphics.device.clone(),
+        vulkano::buffer::BufferUsage::all());
+    let palette_buffer = graphics.create_joint_palette_pool();
+
+    let vs = vs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+    let fs = fs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+
+    let pipeline = graphics.create_pipeline_for_vertex::<SkinnedVertex, _, _>(vs.main_entry_point(), fs.main_entry_point());
+
+    let mut pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(pipeline.clone(), 0);
+
+    let mut recreate_swapchain = false;
+
+    let mut previous_frame: Box<GpuFuture> = model_future;
+
+    let start = std::time::Instant::now();
+    let mut last_frame = std::time::Instant::now();
+
+    loop {
+        previous_frame.cleanup_finished();
+
+        if recreate_swapchain {
+            if !graphics.recreate_swapchain() {
+                continue;
+            }
+            recreate_swapchain = false;
+        }
+
+        let dt = last_frame.elapsed();
+        last_frame = std::time::Instant::now();
+        scene.update_camera(camera_node, &mut controller, dt.as_secs() as f32 + dt.subsec_nanos() as f32 / 1_000_000_000.0);
+
+        // Fade between bending and twisting every few seconds.
+        let elapsed = start.elapsed();
+        let time = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+        let twist_weight = (time / 3.0).sin() * 0.5 + 0.5;
+        let pose = blend_clips(&skeleton, &[(&bend, time, 1.0 - twist_weight), (&twist, time, twist_weight)]);
+
+        let palette = palette_buffer.chunk(
+            skeleton.joint_palette(&pose).into_iter().map(|matrix| matrix.into())).unwrap();
+
+        let camera = scene.camera(camera_node);
+        let uniform_data = vs::ty::Data {
+            world : scene.world_matrix(model_node).into(),
+            view : scene.view_matrix(camera_node).into(),
+            proj : camera.projection().into(),
+        };
+
+        let set: Arc<DescriptorSet + Send + Sync> = Arc::from(pool.next()
+            .add_buffer(uniform_buffer.next(uniform_data).unwrap()).unwrap()
+            .add_buffer(palette).unwrap()
+            .build().unwrap());
+
+        let (image_num, acquire_future) = match vulkano::swapchain::acquire_next_image(graphics.swapchain(),
+                                                                                       None) {
+            Ok(r) => r,
+            Err(vulkano::swapchain::AcquireError::OutOfDate) => {
+                recreate_swapchain = true;
+                continue;
+            },
+            Err(err) => panic!("{:?}", err)
+        };
+
+        let command_buffer = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(graphics.device.clone(), graphics.queue.family()).unwrap()
+            .begin_render_pass(
+                graphics.framebuffers[image_num].clone(), false,
+                graphics.clear_values([0.0, 0.0, 1.0, 1.0], camera.depth_clear_value())).unwrap()
+            .draw(
+                pipeline.clone(),
+                &graphics.dynamic_state,
+                vec![model.clone()],
+                set.clone(),
+                ()).unwrap()
+            .end_render_pass().unwrap()
+            .build().unwrap();
+
+        let future = previous_frame.join(acquire_future)
+            .then_execute(graphics.queue.clone(), command_buffer).unwrap()
+            .then_swapchain_present(graphics.queue.clone(), graphics.swapchain(), image_num)
+            .then_signal_fence_and_flush();
+
+        match future {
+            Ok(future) => {
+                previous_frame = Box::new(future) as Box<_>;
+            }
+            Err(vulkano::sync::FlushError::OutOfDate) => {
+                recreate_swapchain = true;
+                previous_frame = Box::new(vulkano::sync::now(graphics.device.clone())) as Box<_>;
+            }
+            Err(e) => {
+                println!("{:?}", e);
+                previous_frame = Box::new(vulkano::sync::now(graphics.device.clone())) as Box<_>;
+            }
+        }
+
+        let mut done = false;
+        graphics.poll_events(|ev| {
+            controller.handle_event(&ev);
+            match ev {
+                winit::Event::WindowEvent { event: winit::WindowEvent::CloseRequested, .. } => done = true,
+                _ => ()
+            }
+        });
+        if done { return; }
+    }
+}
+
+mod vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in vec3 tangent_u;
+layout(location = 4) in vec3 tangent_v;
+layout(location = 5) in uvec4 joints;
+layout(location = 6) in vec4 weights;
+
+layout(location = 0) out vec3 v_world_normal;
+layout(location = 1) out vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+} uniforms;
+
+layout(set = 0, binding = 1) readonly buffer JointPalette {
+    mat4 matrices[];
+} palette;
+
+void main() {
+    mat4 skin = weights.x * palette.matrices[joints.x]
+        + weights.y * palette.matrices[joints.y]
+        + weights.z * palette.matrices[joints.z]
+        + weights.w * palette.matrices[joints.w];
+    mat4 skinned_world = uniforms.world * skin;
+    v_world_normal = transpose(inverse(mat3(skinned_world))) * normal;
+    v_uv = uv;
+    gl_Position = uniforms.proj * uniforms.view * skinned_world * vec4(position, 1.0);
+}
+"]
+    struct Dummy;
+}
+
+mod fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 v_world_normal;
+layout(location = 1) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+const vec3 LIGHT_DIRECTION = vec3(0.3, 1.0, 0.5);
+const vec3 AMBIENT_LIGHT = vec3(0.1, 0.1, 0.1);
+
+void main() {
+    float stripes = step(0.5, fract(v_uv.y * 8.0)) * 0.3 + 0.7;
+    float lambert = max(dot(normalize(v_world_normal), normalize(LIGHT_DIRECTION)), 0.0);
+    f_color = vec4(vec3(lambert * stripes) + AMBIENT_LIGHT, 1.0);
+}
+"]
+    struct Dummy;
+}
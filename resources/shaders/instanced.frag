@@ -0,0 +1,14 @@
+#version 450
+
+layout(location = 0) in vec3 v_world_normal;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+const vec3 LIGHT = normalize(vec3(0.5, 1.0, 0.3));
+const float AMBIENT = 0.2;
+
+void main() {
+    float lambert = max(dot(normalize(v_world_normal), LIGHT), 0.0);
+    f_color = vec4(v_color.rgb * (AMBIENT + (1.0 - AMBIENT) * lambert), v_color.a);
+}
@@ -0,0 +1,22 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+
+// Per-instance attributes from the second vertex buffer.
+layout(location = 5) in mat4 world;
+layout(location = 9) in vec4 color;
+
+layout(location = 0) out vec3 v_world_normal;
+layout(location = 1) out vec4 v_color;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 view;
+    mat4 proj;
+} uniforms;
+
+void main() {
+    v_world_normal = transpose(inverse(mat3(world))) * normal;
+    v_color = color;
+    gl_Position = uniforms.proj * uniforms.view * world * vec4(position, 1.0);
+}
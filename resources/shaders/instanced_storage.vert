@@ -0,0 +1,31 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+
+layout(location = 0) out vec3 v_world_normal;
+layout(location = 1) out vec4 v_color;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 view;
+    mat4 proj;
+} uniforms;
+
+// Laid out like `InstanceData`.
+struct Instance {
+    mat4 world;
+    vec4 color;
+};
+
+// Per-instance data, indexed by the instance instead of read from a second
+// vertex buffer.
+layout(set = 0, binding = 1) readonly buffer Instances {
+    Instance instances[];
+};
+
+void main() {
+    Instance instance = instances[gl_InstanceIndex];
+    v_world_normal = transpose(inverse(mat3(instance.world))) * normal;
+    v_color = instance.color;
+    gl_Position = uniforms.proj * uniforms.view * instance.world * vec4(position, 1.0);
+}
@@ -0,0 +1,106 @@
+#version 450
+
+layout(location = 0) in vec3 v_world_normal;
+layout(location = 1) in vec2 v_uv;
+layout(location = 2) in vec3 v_world_pos;
+layout(location = 3) in vec3 v_view_pos;
+layout(location = 4) in vec3 v_world_tangent_u;
+layout(location = 5) in vec3 v_world_tangent_v;
+layout(location = 6) in vec3 v_world_view;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D color;
+layout(set = 0, binding = 1) uniform sampler2D normal;
+layout(set = 0, binding = 2) uniform samplerCube irradiance;
+layout(set = 0, binding = 3) uniform samplerCube prefiltered;
+layout(set = 0, binding = 4) uniform sampler2D brdf_lut;
+
+const vec3 LIGHT = vec3(0.0, 0.0, 1.0);
+const vec3 POINT_LIGHT_POSITION = vec3(1.0, 1.0, 4.0);
+const vec3 POINT_LIGHT_INTENSITY = vec3(10.0, 10.0, 10.0);
+
+const float LAMBERT_COEFFICIENT = 1.0;
+const float SPECULAR_COEFFICIENT = 1.0;
+
+const float ROUGHNESS = 0.12;
+const float REFRACTION = 0.5;
+
+const vec4 MATERIAL_COLOR = vec4(1.0, 1.0, 1.0, 1.0);
+
+float schlick(vec3 v, vec3 h, float refraction) {
+    float r0_sqrt = (1 - refraction) / (1 + refraction);
+    float r0 = r0_sqrt * r0_sqrt;
+    return r0 + (1 - r0) * pow(1 - dot(v, h), 5);
+}
+
+// all vec arguments must be normalized
+float geometric_attenuation(vec3 v, vec3 n, vec3 h, vec3 l) {
+    float vh = dot(v, h);
+    float hn = dot(h, n);
+    float ln = dot(l, n);
+    float vn = dot(v, n);
+    return min(1, min(2 * hn * vn / vh, 2 * hn * ln / vh));
+}
+
+float ggx_chi(float x) {
+    return x > 0 ? 1 : 0;
+}
+
+// all vec arguments must be normalized
+float ggx_distribution(vec3 h, vec3 n, float roughness) {
+    float r2 = roughness * roughness;
+    float nh = dot(n, h);
+    float denom = nh * nh * r2 + (1 - nh * nh);
+    return r2 * ggx_chi(nh) / (3.14 * denom * denom);
+}
+
+// all vec arguments must be normalized
+float cook_torrance(vec3 v, vec3 n, vec3 l, float refraction, float roughness) {
+    vec3 h = normalize((v + l) / 2);
+    float d = ggx_distribution(h, n, roughness);
+    float g = geometric_attenuation(v, n, h, l);
+    float s = schlick(v, h, refraction);
+
+    return d * g * s / (4 * dot(v, n) * dot(n, l));
+}
+
+vec4 point_light(vec3 pos, vec3 intensity, vec3 normal) {
+    vec3 l = pos - v_world_pos;
+    float d2 = dot(l, l);
+    vec3 v = -v_view_pos;
+
+    vec4 lambert_component = MATERIAL_COLOR;
+    vec4 lambert = LAMBERT_COEFFICIENT * lambert_component;
+
+    float specular_component = cook_torrance(normalize(v), normalize(normal), normalize(l), REFRACTION, ROUGHNESS);
+    vec4 specular = (SPECULAR_COEFFICIENT * specular_component).xxxx;
+
+    vec4 brdf_value = lambert + specular;
+
+    float c = max(dot(normalize(normal), normalize(l)), 0.0);
+    vec4 irradiance = vec4(intensity / d2, 1.0);
+    return irradiance * c.xxxx * brdf_value;
+}
+
+// Split-sum image-based lighting, see `ibl::Environment`. All vectors must be normalized.
+vec3 environment_light(vec3 n, vec3 v, vec3 albedo) {
+    float r0_sqrt = (1 - REFRACTION) / (1 + REFRACTION);
+    vec3 f0 = vec3(r0_sqrt * r0_sqrt);
+    vec2 lut = texture(brdf_lut, vec2(max(dot(n, v), 0.0), ROUGHNESS)).rg;
+    float lod = ROUGHNESS * float(textureQueryLevels(prefiltered) - 1);
+    vec3 specular = textureLod(prefiltered, reflect(-v, n), lod).rgb * (f0 * lut.x + lut.y);
+    vec3 diffuse = texture(irradiance, n).rgb * albedo;
+    return LAMBERT_COEFFICIENT * diffuse + SPECULAR_COEFFICIENT * specular;
+}
+
+void main() {
+    vec4 normal_map_color = texture(normal, v_uv);
+    vec4 normals = normal_map_color * 2.0 - vec4(1.0, 1.0, 1.0, 1.0);
+    vec3 adjusted_normal = normals.x * normalize(v_world_tangent_u) + normals.y * normalize(v_world_tangent_v) + normals.z * normalize(v_world_normal);
+    adjusted_normal = normalize(adjusted_normal);
+    vec4 lighting_color = point_light(POINT_LIGHT_POSITION, POINT_LIGHT_INTENSITY, adjusted_normal);
+    vec4 texture_color = texture(color, v_uv);
+    vec3 ambient = environment_light(adjusted_normal, normalize(v_world_view), texture_color.rgb * MATERIAL_COLOR.rgb);
+    f_color = vec4((texture_color * lighting_color).rgb + ambient, texture_color.a);
+}
@@ -0,0 +1,33 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+layout(location = 3) in vec3 tangent_u;
+layout(location = 4) in vec3 tangent_v;
+
+layout(location = 0) out vec3 v_world_normal;
+layout(location = 1) out vec2 v_uv;
+layout(location = 2) out vec3 v_world_pos;
+layout(location = 3) out vec3 v_view_pos;
+layout(location = 4) out vec3 v_world_tangent_u;
+layout(location = 5) out vec3 v_world_tangent_v;
+layout(location = 6) out vec3 v_world_view;
+
+layout(set = 1, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+} uniforms;
+
+void main() {
+    mat4 worldview = uniforms.view * uniforms.world;
+    v_world_normal = transpose(inverse(mat3(uniforms.world))) * normal;
+    v_world_tangent_u = transpose(inverse(mat3(uniforms.world))) * tangent_u;
+    v_world_tangent_v = transpose(inverse(mat3(uniforms.world))) * tangent_v;
+    v_uv = uv;
+    gl_Position = uniforms.proj * worldview * vec4(position, 1.0);
+    v_view_pos = (worldview * vec4(position, 1.0)).xyz;
+    v_world_pos = (uniforms.world * vec4(position, 1.0)).xyz;
+    v_world_view = inverse(uniforms.view)[3].xyz - v_world_pos;
+}
@@ -0,0 +1,378 @@
+use cgmath::*;
+
+use scene::Transform;
+
+pub struct Joint {
+    pub name: String,
+
+    /// Index of the parent joint, which must come earlier in the skeleton.
+    pub parent: Option<usize>,
+
+    /// Transforms from model space into the joint's space in the bind pose.
+    pub inverse_bind: Matrix4<f32>,
+
+    /// Local transform used for joints that no animation channel drives.
+    pub rest: Transform,
+}
+
+/// A hierarchy of joints, ordered so that parents come before their children.
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Skeleton {
+        for (i, joint) in joints.iter().enumerate() {
+            if joint.parent.map_or(false, |parent| parent >= i) {
+                panic!("Joints must come after their parents!");
+            }
+        }
+        Skeleton { joints }
+    }
+
+    /// Sets every joint's inverse bind matrix from its rest transform, for
+    /// skeletons whose bind pose is the rest pose.
+    pub fn bind_rest_pose(&mut self) {
+        let rest = self.rest_pose();
+        let world = self.world_matrices(&rest);
+        for (joint, world) in self.joints.iter_mut().zip(world) {
+            joint.inverse_bind = world.invert().expect("Joint has a degenerate rest transform!");
+        }
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// Model-space transforms of every joint, given their local transforms.
+    pub fn world_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        let mut world: Vec<Matrix4<f32>> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(pose) {
+            let local = local.matrix();
+            let matrix = match joint.parent {
+                Some(parent) => world[parent] * local,
+                None => local,
+            };
+            world.push(matrix);
+        }
+        world
+    }
+
+    /// The skinning matrices uploaded to the GPU, which take bind-pose
+    /// vertices to their posed positions.
+    pub fn joint_palette(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        self.world_matrices(pose).into_iter()
+            .zip(self.joints.iter())
+            .map(|(world, joint)| world * joint.inverse_bind)
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Step,
+
+    /// Linear for vectors, spherical linear for rotations.
+    Linear,
+
+    /// Cubic Hermite spline. Every keyframe has three values: its in-tangent,
+    /// its value and its out-tangent, as in glTF.
+    CubicSpline,
+}
+
+/// Values that can be interpolated between keyframes.
+pub trait Interpolate: Copy {
+    fn scale(value: Self, factor: f32) -> Self;
+
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+
+    /// Evaluates a cubic Hermite spline from `p0` with tangent `m0` to `p1` with tangent `m1`.
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32) -> Self;
+}
+
+fn hermite_weights(t: f32) -> (f32, f32, f32, f32) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0, t3 - 2.0 * t2 + t, -2.0 * t3 + 3.0 * t2, t3 - t2)
+}
+
+impl Interpolate for Vector3<f32> {
+    fn scale(value: Self, factor: f32) -> Self {
+        value * factor
+    }
+
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32) -> Self {
+        let (h00, h10, h01, h11) = hermite_weights(t);
+        p0 * h00 + m0 * h10 + p1 * h01 + m1 * h11
+    }
+}
+
+impl Interpolate for Quaternion<f32> {
+    fn scale(value: Self, factor: f32) -> Self {
+        value * factor
+    }
+
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        // q and -q are the same rotation; pick the one that takes the short way round.
+        let b = if a.dot(b) < 0.0 { -b } else { b };
+        a.slerp(b, t)
+    }
+
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32) -> Self {
+        let (h00, h10, h01, h11) = hermite_weights(t);
+        (p0 * h00 + m0 * h10 + p1 * h01 + m1 * h11).normalize()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframes<T> {
+    /// Keyframe times in seconds, in increasing order.
+    pub times: Vec<f32>,
+
+    /// One value per keyframe, or three for `CubicSpline`.
+    pub values: Vec<T>,
+
+    pub interpolation: Interpolation,
+}
+
+impl<T> Keyframes<T> where T: Interpolate {
+    pub fn new(times: Vec<f32>, values: Vec<T>, interpolation: Interpolation) -> Keyframes<T> {
+        let values_per_key = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+        if times.is_empty() || times.len() * values_per_key != values.len() {
+            panic!("Invalid keyframes!");
+        }
+        Keyframes { times, values, interpolation }
+    }
+
+    fn value(&self, key: usize) -> T {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[key * 3 + 1],
+            _ => self.values[key],
+        }
+    }
+
+    /// Samples the keyframes, holding the first and last values outside their range.
+    pub fn sample(&self, time: f32) -> T {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return self.value(0);
+        }
+        if time >= self.times[last] {
+            return self.value(last);
+        }
+
+        // The first key after `time`; it's never 0 because of the checks above.
+        let next = match self.times.binary_search_by(|t| t.partial_cmp(&time).unwrap()) {
+            Ok(key) => return self.value(key),
+            Err(key) => key,
+        };
+        let key = next - 1;
+        let dt = self.times[next] - self.times[key];
+        let t = (time - self.times[key]) / dt;
+
+        match self.interpolation {
+            Interpolation::Step => self.value(key),
+            Interpolation::Linear => T::lerp(self.value(key), self.value(next), t),
+            Interpolation::CubicSpline => {
+                let out_tangent = self.values[key * 3 + 2];
+                let in_tangent = self.values[next * 3];
+                // Tangents are stored per second; the spline wants them per segment.
+                T::hermite(
+                    self.value(key), T::scale(out_tangent, dt),
+                    self.value(next), T::scale(in_tangent, dt),
+                    t)
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelValues {
+    Translation(Keyframes<Vector3<f32>>),
+    Rotation(Keyframes<Quaternion<f32>>),
+    Scale(Keyframes<Vector3<f32>>),
+}
+
+/// Animates one property of one joint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub joint: usize,
+    pub values: ChannelValues,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+
+    /// Length of the clip in seconds, i.e. the time of its last keyframe.
+    pub duration: f32,
+
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, channels: Vec<Channel>) -> AnimationClip {
+        let duration = channels.iter().map(|channel| {
+            let times = match channel.values {
+                ChannelValues::Translation(ref keyframes) => &keyframes.times,
+                ChannelValues::Rotation(ref keyframes) => &keyframes.times,
+                ChannelValues::Scale(ref keyframes) => &keyframes.times,
+            };
+            *times.last().unwrap()
+        }).fold(0.0, f32::max);
+        AnimationClip { name: name.to_string(), duration, channels }
+    }
+
+    /// Writes the clip's values at `time` into a pose. Joints and properties
+    /// without a channel keep their current values.
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        for channel in &self.channels {
+            let transform = &mut pose[channel.joint];
+            match channel.values {
+                ChannelValues::Translation(ref keyframes) => transform.translation = keyframes.sample(time),
+                ChannelValues::Rotation(ref keyframes) => transform.rotation = keyframes.sample(time),
+                ChannelValues::Scale(ref keyframes) => transform.scale = keyframes.sample(time),
+            }
+        }
+    }
+
+    /// Like `sample`, but wraps `time` so that the clip loops.
+    pub fn sample_looped(&self, time: f32, pose: &mut [Transform]) {
+        let time = if self.duration > 0.0 { time % self.duration } else { 0.0 };
+        self.sample(time, pose);
+    }
+}
+
+/// Blends two poses of the same skeleton. A weight of 0.0 gives `a`, 1.0 gives `b`.
+pub fn blend_poses(a: &[Transform], b: &[Transform], weight: f32) -> Vec<Transform> {
+    a.iter().zip(b).map(|(a, b)| Transform {
+        translation: Interpolate::lerp(a.translation, b.translation, weight),
+        rotation: Interpolate::lerp(a.rotation, b.rotation, weight),
+        scale: Interpolate::lerp(a.scale, b.scale, weight),
+    }).collect()
+}
+
+/// Blends any number of clips, each sampled at its own time, by normalized weights.
+pub fn blend_clips(skeleton: &Skeleton, clips: &[(&AnimationClip, f32, f32)]) -> Vec<Transform> {
+    let mut result = skeleton.rest_pose();
+    let mut total_weight = 0.0;
+    for &(clip, time, weight) in clips {
+        if weight <= 0.0 {
+            continue;
+        }
+        let mut pose = skeleton.rest_pose();
+        clip.sample_looped(time, &mut pose);
+        total_weight += weight;
+        // Accumulating as a running average keeps every step a two-pose blend.
+        result = if total_weight == weight {
+            pose
+        } else {
+            blend_poses(&result, &pose, weight / total_weight)
+        };
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vector_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!((actual - expected).magnitude() < 1e-5, "{:?} != {:?}", actual, expected);
+    }
+
+    fn two_bone_skeleton() -> Skeleton {
+        let mut skeleton = Skeleton::new(vec![
+            Joint {
+                name: "root".to_string(),
+                parent: None,
+                inverse_bind: Matrix4::identity(),
+                rest: Transform::identity(),
+            },
+            Joint {
+                name: "tip".to_string(),
+                parent: Some(0),
+                inverse_bind: Matrix4::identity(),
+                rest: Transform::from_translation(Vector3::new(0.0, 1.0, 0.0)),
+            },
+        ]);
+        skeleton.bind_rest_pose();
+        skeleton
+    }
+
+    #[test]
+    fn test_keyframes() {
+        let keyframes = Keyframes::new(
+            vec![0.0, 1.0, 3.0],
+            vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(1.0, 2.0, 0.0)],
+            Interpolation::Linear);
+        assert_vector_close(keyframes.sample(-1.0), Vector3::new(0.0, 0.0, 0.0));
+        assert_vector_close(keyframes.sample(0.5), Vector3::new(0.5, 0.0, 0.0));
+        assert_vector_close(keyframes.sample(1.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_vector_close(keyframes.sample(2.0), Vector3::new(1.0, 1.0, 0.0));
+        assert_vector_close(keyframes.sample(4.0), Vector3::new(1.0, 2.0, 0.0));
+
+        let step = Keyframes { interpolation: Interpolation::Step, .. keyframes };
+        assert_vector_close(step.sample(2.9), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_cubic_spline() {
+        // With tangents matching the slope of the line, the spline is the line.
+        let slope = Vector3::new(2.0, 0.0, 0.0);
+        let keyframes = Keyframes::new(
+            vec![0.0, 0.5],
+            vec![slope, Vector3::new(0.0, 0.0, 0.0), slope, slope, Vector3::new(1.0, 0.0, 0.0), slope],
+            Interpolation::CubicSpline);
+        assert_vector_close(keyframes.sample(0.25), Vector3::new(0.5, 0.0, 0.0));
+        assert_vector_close(keyframes.sample(0.5), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_slerp_short_way() {
+        let a = Quaternion::from_angle_y(Deg(10.0));
+        // The same rotation as 30 degrees, but in the opposite hemisphere.
+        let b = -Quaternion::from_angle_y(Deg(30.0));
+        let mid = Interpolate::lerp(a, b, 0.5);
+        let expected = Quaternion::from_angle_y(Deg(20.0));
+        assert!(mid.dot(expected).abs() > 0.99999);
+    }
+
+    #[test]
+    fn test_joint_palette() {
+        let skeleton = two_bone_skeleton();
+        let mut pose = skeleton.rest_pose();
+        for matrix in skeleton.joint_palette(&pose) {
+            assert_eq!(matrix, Matrix4::identity());
+        }
+
+        // Bending the root by 90 degrees swings a vertex at the tip around it.
+        pose[0].rotation = Quaternion::from_angle_z(Deg(90.0));
+        let palette = skeleton.joint_palette(&pose);
+        let moved = palette[1] * Vector4::new(0.0, 1.0, 0.0, 1.0);
+        assert_vector_close(moved.truncate(), Vector3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_blend_clips() {
+        let skeleton = two_bone_skeleton();
+        let clip = |name, x| AnimationClip::new(name, vec![Channel {
+            joint: 0,
+            values: ChannelValues::Translation(Keyframes::new(
+                vec![0.0, 1.0],
+                vec![Vector3::new(x, 0.0, 0.0), Vector3::new(x, 0.0, 0.0)],
+                Interpolation::Linear)),
+        }]);
+        let walk = clip("walk", 1.0);
+        let run = clip("run", 4.0);
+        assert_eq!(walk.duration, 1.0);
+
+        let pose = blend_clips(&skeleton, &[(&walk, 0.0, 1.0), (&run, 0.0, 3.0)]);
+        assert_vector_close(pose[0].translation, Vector3::new(3.25, 0.0, 0.0));
+        assert_vector_close(pose[1].translation, Vector3::new(0.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::ops::Deref;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
+
+use vulkano;
+use vulkano::buffer::BufferAccess;
+use vulkano::buffer::ImmutableBuffer;
+use vulkano::format::Format;
+use vulkano::image::ImageViewAccess;
+use vulkano::sync::FenceSignalFuture;
+use vulkano::sync::GpuFuture;
+
+use compressed;
+use geometry::Vertex;
+use geometry::subdivision::SubdivisionScheme;
+use graphics::Graphics;
+use texture;
+use texture::ColorSpace;
+
+/// A shared reference to a loaded asset. The asset, and the GPU resources it
+/// owns, are freed when the last handle to it is dropped.
+#[derive(Debug)]
+pub struct Handle<T> {
+    asset: Arc<T>,
+}
+
+impl<T> Handle<T> {
+    /// A handle to an asset that isn't in any cache.
+    pub fn new(asset: T) -> Handle<T> {
+        Handle { asset: Arc::new(asset) }
+    }
+
+    /// Whether both handles refer to the same loaded asset.
+    pub fn ptr_eq(&self, other: &Handle<T>) -> bool {
+        Arc::ptr_eq(&self.asset, &other.asset)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle { asset: self.asset.clone() }
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.asset
+    }
+}
+
+/// Assets by key, without keeping them alive.
+pub struct AssetCache<K, T> {
+    assets: HashMap<K, Weak<T>>,
+}
+
+impl<K: Hash + Eq + Clone, T> AssetCache<K, T> {
+    pub fn new() -> AssetCache<K, T> {
+        AssetCache { assets: HashMap::new() }
+    }
+
+    /// A new handle to the asset loaded for `key`, if it is still alive.
+    pub fn get(&self, key: &K) -> Option<Handle<T>> {
+        self.assets.get(key).and_then(|asset| asset.upgrade()).map(|asset| Handle { asset })
+    }
+
+    pub fn insert(&mut self, key: K, asset: T) -> Handle<T> {
+        let asset = Arc::new(asset);
+        self.assets.insert(key, Arc::downgrade(&asset));
+        Handle { asset }
+    }
+
+    /// Forgets the assets whose handles have all been dropped.
+    pub fn prune(&mut self) {
+        self.assets.retain(|_, asset| asset.upgrade().is_some());
+    }
+
+    /// Every live asset with the number of handles to it.
+    pub fn loaded(&self) -> Vec<(K, Handle<T>, usize)> {
+        self.assets.iter()
+            .filter_map(|(key, asset)| asset.upgrade().map(|asset| {
+                // Not counting the one we just made.
+                let handles = Arc::strong_count(&asset) - 1;
+                (key.clone(), Handle { asset }, handles)
+            }))
+            .collect()
+    }
+}
+
+/// What a texture was loaded from. The same file loaded in another color
+/// space is a different texture.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureKey {
+    pub path: PathBuf,
+    pub color_space: ColorSpace,
+}
+
+impl TextureKey {
+    pub fn new(path: &str, color_space: ColorSpace) -> TextureKey {
+        TextureKey { path: canonical_path(path), color_space }
+    }
+}
+
+/// What a mesh was loaded from, and how many levels of which subdivision
+/// scheme were applied to it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshKey {
+    pub path: PathBuf,
+    pub subdivision: Option<(SubdivisionScheme, usize)>,
+}
+
+impl MeshKey {
+    pub fn new(path: &str, subdivision: Option<(SubdivisionScheme, usize)>) -> MeshKey {
+        MeshKey { path: canonical_path(path), subdivision }
+    }
+}
+
+pub struct Texture {
+    pub image: Arc<ImageViewAccess + Send + Sync>,
+    /// Size of the image with every level and layer, in bytes.
+    pub memory: usize,
+}
+
+impl Texture {
+    pub fn new(image: Arc<ImageViewAccess + Send + Sync>) -> Texture {
+        let memory = {
+            let parent = image.parent();
+            let dimensions = parent.dimensions();
+            texture_memory(parent.format(), dimensions.width(), dimensions.height(),
+                           dimensions.array_layers_with_cube(), parent.mipmap_levels())
+        };
+        Texture { image, memory }
+    }
+}
+
+pub struct Mesh {
+    pub vertices: Arc<ImmutableBuffer<[Vertex]>>,
+    pub memory: usize,
+}
+
+impl Mesh {
+    pub fn new(vertices: Arc<ImmutableBuffer<[Vertex]>>) -> Mesh {
+        let memory = vertices.size();
+        Mesh { vertices, memory }
+    }
+}
+
+/// Bytes taken by an image with every level of its mip chain.
+pub fn texture_memory(format: Format, width: u32, height: u32, layers: u32, levels: u32) -> usize {
+    (0..levels).map(|level| {
+        let dimensions = texture::mip_dimensions(width, height, level);
+        compressed::level_size(format, dimensions[0], dimensions[1])
+            .or_else(|| format.size().map(|texel| texel * (dimensions[0] * dimensions[1]) as usize))
+            .expect("unknown texel size")
+    }).sum::<usize>() * layers as usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Texture,
+    Mesh,
+}
+
+/// A loaded asset, as listed by `AssetManager::assets`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssetInfo {
+    pub kind: AssetKind,
+    pub path: PathBuf,
+    /// Import settings, e.g. the color space of a texture.
+    pub settings: String,
+    pub handles: usize,
+    pub memory: usize,
+}
+
+// An upload that later loads of the same asset may have to wait for.
+type Upload = Arc<FenceSignalFuture<Box<GpuFuture>>>;
+
+/// Loads textures and meshes once per path and import settings, and hands
+/// out handles to them.
+pub struct AssetManager {
+    textures: AssetCache<TextureKey, Texture>,
+    meshes: AssetCache<MeshKey, Mesh>,
+    texture_uploads: HashMap<TextureKey, Upload>,
+    mesh_uploads: HashMap<MeshKey, Upload>,
+}
+
+impl AssetManager {
+    pub fn new() -> AssetManager {
+        AssetManager {
+            textures: AssetCache::new(),
+            meshes: AssetCache::new(),
+            texture_uploads: HashMap::new(),
+            mesh_uploads: HashMap::new(),
+        }
+    }
+
+    /// Loads a texture like `Graphics::load_texture`, unless it is already
+    /// loaded. The future completes once the texture can be used, which may
+    /// be right away.
+    pub fn load_texture(&mut self, graphics: &Graphics, path: &str, color_space: ColorSpace) -> (Handle<Texture>, Box<GpuFuture>) {
+        self.collect_garbage();
+        let key = TextureKey::new(path, color_space);
+        if let Some(texture) = self.textures.get(&key) {
+            return (texture, pending_upload(graphics, &self.texture_uploads, &key));
+        }
+
+        let (image, future) = graphics.load_texture(path, color_space);
+        let upload = Arc::new(future.then_signal_fence_and_flush().expect("failed to upload texture"));
+        self.texture_uploads.insert(key.clone(), upload.clone());
+        (self.textures.insert(key, Texture::new(image)), Box::new(upload))
+    }
+
+    /// Loads a model like `Graphics::load_model`, unless it is already
+    /// loaded. The future completes once the mesh can be drawn.
+    pub fn load_mesh(&mut self, graphics: &Graphics, path: &str) -> (Handle<Mesh>, Box<GpuFuture>) {
+        self.load_mesh_with(graphics, path, None)
+    }
+
+    /// Loads a model like `Graphics::load_subdivided_model`, unless it is
+    /// already loaded with the same subdivision.
+    pub fn load_subdivided_mesh(&mut self, graphics: &Graphics, path: &str, scheme: SubdivisionScheme, levels: usize) -> (Handle<Mesh>, Box<GpuFuture>) {
+        self.load_mesh_with(graphics, path, Some((scheme, levels)))
+    }
+
+    fn load_mesh_with(&mut self, graphics: &Graphics, path: &str, subdivision: Option<(SubdivisionScheme, usize)>) -> (Handle<Mesh>, Box<GpuFuture>) {
+        self.collect_garbage();
+        let key = MeshKey::new(path, subdivision);
+        if let Some(mesh) = self.meshes.get(&key) {
+            return (mesh, pending_upload(graphics, &self.mesh_uploads, &key));
+        }
+        let (vertices, future) = match subdivision {
+            Some((scheme, levels)) => graphics.load_subdivided_model(path, scheme, levels),
+            None => graphics.load_model(path),
+        };
+        let upload = Arc::new(future.then_signal_fence_and_flush().expect("failed to upload mesh"));
+        self.mesh_uploads.insert(key.clone(), upload.clone());
+        (self.meshes.insert(key, Mesh::new(vertices)), Box::new(upload))
+    }
+
+    /// Forgets assets that no longer have handles, and uploads that have
+    /// finished. Loading does this too.
+    pub fn collect_garbage(&mut self) {
+        self.textures.prune();
+        self.meshes.prune();
+        let textures = &self.textures;
+        self.texture_uploads.retain(|key, upload| textures.get(key).is_some() && !is_finished(upload));
+        let meshes = &self.meshes;
+        self.mesh_uploads.retain(|key, upload| meshes.get(key).is_some() && !is_finished(upload));
+    }
+
+    /// Every loaded asset, with its memory size.
+    pub fn assets(&self) -> Vec<AssetInfo> {
+        let textures = self.textures.loaded().into_iter().map(|(key, texture, handles)| AssetInfo {
+            kind: AssetKind::Texture,
+            path: key.path,
+            settings: format!("{:?}", key.color_space),
+            handles,
+            memory: texture.memory,
+        });
+        let meshes = self.meshes.loaded().into_iter().map(|(key, mesh, handles)| AssetInfo {
+            kind: AssetKind::Mesh,
+            path: key.path,
+            settings: match key.subdivision {
+                Some((scheme, levels)) => format!("{:?} x{}", scheme, levels),
+                None => String::new(),
+            },
+            handles,
+            memory: mesh.memory,
+        });
+        let mut assets: Vec<AssetInfo> = textures.chain(meshes).collect();
+        assets.sort_by(|a, b| a.path.cmp(&b.path));
+        assets
+    }
+
+    /// Total memory of every loaded asset, in bytes.
+    pub fn memory(&self) -> usize {
+        self.assets().iter().map(|asset| asset.memory).sum()
+    }
+}
+
+// What a cache hit has to wait for: the upload of the asset, if it may still
+// be running.
+fn pending_upload<K: Hash + Eq>(graphics: &Graphics, uploads: &HashMap<K, Upload>, key: &K) -> Box<GpuFuture> {
+    match uploads.get(key) {
+        Some(upload) => Box::new(upload.clone()),
+        None => Box::new(vulkano::sync::now(graphics.device.clone())),
+    }
+}
+
+fn is_finished(upload: &Upload) -> bool {
+    upload.wait(Some(Duration::from_secs(0))).is_ok()
+}
+
+// Resolves `.`, `..` and links, so different spellings of a path share assets.
+fn canonical_path(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| Path::new(path).to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_dedupes_until_dropped() {
+        let mut cache = AssetCache::new();
+        let first = cache.insert("a", 1);
+        let second = cache.get(&"a").unwrap();
+        assert!(first.ptr_eq(&second));
+        assert_eq!(*second, 1);
+        assert!(cache.get(&"b").is_none());
+        assert_eq!(cache.loaded().len(), 1);
+        assert_eq!(cache.loaded()[0].2, 2);
+
+        drop(first);
+        assert!(cache.get(&"a").is_some());
+        drop(second);
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.loaded().is_empty());
+        cache.prune();
+        assert!(cache.assets.is_empty());
+    }
+
+    #[test]
+    fn test_settings_are_part_of_the_key() {
+        let mut cache = AssetCache::new();
+        let key = |color_space| TextureKey { path: PathBuf::from("a.png"), color_space };
+        let srgb = cache.insert(key(ColorSpace::Srgb), "srgb");
+        let linear = cache.insert(key(ColorSpace::Linear), "linear");
+        assert!(!srgb.ptr_eq(&linear));
+        assert_eq!(*cache.get(&key(ColorSpace::Srgb)).unwrap(), "srgb");
+        assert_eq!(*cache.get(&key(ColorSpace::Linear)).unwrap(), "linear");
+    }
+
+    #[test]
+    fn test_texture_memory() {
+        // 4x4 + 2x2 + 1x1 RGBA8 texels.
+        assert_eq!(texture_memory(Format::R8G8B8A8Srgb, 4, 4, 1, 3), (16 + 4 + 1) * 4);
+        assert_eq!(texture_memory(Format::R8G8B8A8Srgb, 4, 4, 6, 1), 16 * 4 * 6);
+        // BC1 blocks are 8 bytes and at least one per level.
+        assert_eq!(texture_memory(Format::BC1_RGBAUnormBlock, 8, 8, 1, 4), (4 + 1 + 1 + 1) * 8);
+    }
+}
@@ -0,0 +1,443 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
+use std::rc::Rc;
+
+use cgmath::*;
+use winit::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use scene::Transform;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective {
+        /// Vertical field of view.
+        fovy: Rad<f32>,
+        near: f32,
+        /// `None` puts the far plane at infinity.
+        far: Option<f32>,
+    },
+    Orthographic {
+        /// Height of the view volume, in world units. Its width follows the aspect ratio.
+        height: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+/// Size of a render target, shared between whoever resizes it and the
+/// cameras drawing into it. `Graphics::viewport` follows the swapchain, so a
+/// `Scene` following it keeps its cameras' aspect ratios up to date.
+#[derive(Clone, Debug)]
+pub struct SharedViewport(Rc<Cell<[u32; 2]>>);
+
+impl SharedViewport {
+    pub fn new(dimensions: [u32; 2]) -> SharedViewport {
+        SharedViewport(Rc::new(Cell::new(dimensions)))
+    }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.0.get()
+    }
+
+    pub fn set(&self, dimensions: [u32; 2]) {
+        self.0.set(dimensions);
+    }
+}
+
+/// A camera that looks down its node's -Z axis, with +Y up.
+///
+/// Projections target Vulkan's clip space: Y points down and depth goes from
+/// 0.0 at the near plane to 1.0 at the far plane, or the other way around
+/// with `reversed_z`. Reversed-Z needs the depth buffer cleared to 0.0 and a
+/// `Greater` depth test; see `depth_clear_value`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub projection: Projection,
+    pub reversed_z: bool,
+
+    /// Width over height of the image. Kept in sync by `set_viewport`, or by
+    /// the scene when it follows a `SharedViewport`.
+    pub aspect: f32,
+}
+
+impl Camera {
+    pub fn perspective(fovy: Rad<f32>, near: f32, far: f32) -> Camera {
+        Camera::new(Projection::Perspective { fovy, near, far: Some(far) })
+    }
+
+    pub fn infinite_perspective(fovy: Rad<f32>, near: f32) -> Camera {
+        Camera::new(Projection::Perspective { fovy, near, far: None })
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Camera {
+        Camera::new(Projection::Orthographic { height, near, far })
+    }
+
+    fn new(projection: Projection) -> Camera {
+        Camera { projection, reversed_z: false, aspect: 1.0 }
+    }
+
+    /// Updates the aspect ratio for a new swapchain or render target size.
+    pub fn set_viewport(&mut self, dimensions: [u32; 2]) {
+        if dimensions[1] > 0 {
+            self.aspect = dimensions[0] as f32 / dimensions[1] as f32;
+        }
+    }
+
+    pub fn depth_clear_value(&self) -> f32 {
+        if self.reversed_z { 0.0 } else { 1.0 }
+    }
+
+    pub fn projection(&self) -> Matrix4<f32> {
+        // Depth is mapped as `depth = (z * m22 + m32) / w`, with `w = -z` for perspective
+        // and `w = 1` for orthographic projections.
+        match self.projection {
+            Projection::Perspective { fovy, near, far } => {
+                let f = 1.0 / (fovy / 2.0).tan();
+                let (m22, m32) = match (far, self.reversed_z) {
+                    (Some(far), false) => (far / (near - far), near * far / (near - far)),
+                    (Some(far), true) => (near / (far - near), near * far / (far - near)),
+                    (None, false) => (-1.0, -near),
+                    (None, true) => (0.0, near),
+                };
+                Matrix4::new(
+                    f / self.aspect, 0.0, 0.0, 0.0,
+                    0.0, -f, 0.0, 0.0,
+                    0.0, 0.0, m22, -1.0,
+                    0.0, 0.0, m32, 0.0,
+                )
+            },
+            Projection::Orthographic { height, near, far } => {
+                let width = height * self.aspect;
+                let (m22, m32) = if self.reversed_z {
+                    (1.0 / (far - near), far / (far - near))
+                } else {
+                    (-1.0 / (far - near), -near / (far - near))
+                };
+                Matrix4::new(
+                    2.0 / width, 0.0, 0.0, 0.0,
+                    0.0, -2.0 / height, 0.0, 0.0,
+                    0.0, 0.0, m22, 0.0,
+                    0.0, 0.0, m32, 1.0,
+                )
+            },
+        }
+    }
+}
+
+/// Moves a camera in response to input.
+pub trait CameraController {
+    /// Feeds a winit event to the controller. Events it doesn't care about are ignored.
+    fn handle_event(&mut self, event: &Event);
+
+    /// Advances the controller by `dt` seconds and applies it to the camera and its node's transform.
+    fn update(&mut self, dt: f32, transform: &mut Transform, camera: &mut Camera);
+}
+
+// Scroll deltas in pixels are converted to lines with this factor.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+// Keeps orbit and fly cameras from flipping over at the poles.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+    match *delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+    }
+}
+
+/// Tracks mouse buttons and accumulates mouse motion between updates.
+#[derive(Default)]
+struct MouseState {
+    pressed: HashSet<MouseButton>,
+    motion: (f32, f32),
+    scroll: f32,
+}
+
+impl MouseState {
+    fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::WindowEvent { event: WindowEvent::MouseInput { state, button, .. }, .. } => {
+                match state {
+                    ElementState::Pressed => self.pressed.insert(button),
+                    ElementState::Released => self.pressed.remove(&button),
+                };
+            },
+            Event::WindowEvent { event: WindowEvent::MouseWheel { ref delta, .. }, .. } => {
+                self.scroll += scroll_lines(delta);
+            },
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
+                self.motion.0 += delta.0 as f32;
+                self.motion.1 += delta.1 as f32;
+            },
+            _ => {},
+        }
+    }
+
+    /// Returns and resets the motion and scroll accumulated since the last call.
+    fn take(&mut self) -> ((f32, f32), f32) {
+        let taken = (self.motion, self.scroll);
+        self.motion = (0.0, 0.0);
+        self.scroll = 0.0;
+        taken
+    }
+}
+
+/// Orbits around a target point. Dragging with the left button rotates, the
+/// wheel moves closer or further away.
+pub struct OrbitController {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+
+    /// Radians per pixel of mouse motion.
+    pub rotate_speed: f32,
+    /// Fraction of the distance moved per line scrolled.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+
+    mouse: MouseState,
+}
+
+impl OrbitController {
+    pub fn new(target: Point3<f32>, distance: f32) -> OrbitController {
+        OrbitController {
+            target,
+            distance,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            rotate_speed: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.01,
+            mouse: MouseState::default(),
+        }
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        let offset = Vector3 {
+            x: self.pitch.cos() * self.yaw.sin(),
+            y: self.pitch.sin(),
+            z: self.pitch.cos() * self.yaw.cos(),
+        };
+        self.target + offset * self.distance
+    }
+}
+
+impl CameraController for OrbitController {
+    fn handle_event(&mut self, event: &Event) {
+        self.mouse.handle_event(event);
+    }
+
+    fn update(&mut self, _dt: f32, transform: &mut Transform, _camera: &mut Camera) {
+        let (motion, scroll) = self.mouse.take();
+        if self.mouse.pressed.contains(&MouseButton::Left) {
+            self.yaw -= Rad(motion.0 * self.rotate_speed);
+            self.pitch = Rad((self.pitch.0 + motion.1 * self.rotate_speed).max(-MAX_PITCH).min(MAX_PITCH));
+        }
+        self.distance = (self.distance * (1.0 - scroll * self.zoom_speed)).max(self.min_distance);
+        *transform = Transform {
+            scale: transform.scale,
+            .. Transform::looking_at(self.eye(), self.target, Vector3::unit_y())
+        };
+    }
+}
+
+/// First-person flight. WASD moves, Space and Left Shift move up and down,
+/// and dragging with the right button looks around.
+pub struct FlyController {
+    pub position: Point3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+
+    /// World units per second.
+    pub move_speed: f32,
+    /// Radians per pixel of mouse motion.
+    pub look_speed: f32,
+
+    keys: HashSet<VirtualKeyCode>,
+    mouse: MouseState,
+}
+
+impl FlyController {
+    pub fn new(position: Point3<f32>) -> FlyController {
+        FlyController {
+            position,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            move_speed: 2.0,
+            look_speed: 0.003,
+            keys: HashSet::new(),
+            mouse: MouseState::default(),
+        }
+    }
+
+    pub fn rotation(&self) -> Quaternion<f32> {
+        Quaternion::from_angle_y(self.yaw) * Quaternion::from_angle_x(self.pitch)
+    }
+
+    fn axis(&self, positive: VirtualKeyCode, negative: VirtualKeyCode) -> f32 {
+        let mut value = 0.0;
+        if self.keys.contains(&positive) { value += 1.0; }
+        if self.keys.contains(&negative) { value -= 1.0; }
+        value
+    }
+}
+
+impl CameraController for FlyController {
+    fn handle_event(&mut self, event: &Event) {
+        self.mouse.handle_event(event);
+        if let Event::WindowEvent { event: WindowEvent::KeyboardInput { input, .. }, .. } = *event {
+            if let Some(key) = input.virtual_keycode {
+                match input.state {
+                    ElementState::Pressed => self.keys.insert(key),
+                    ElementState::Released => self.keys.remove(&key),
+                };
+            }
+        }
+    }
+
+    fn update(&mut self, dt: f32, transform: &mut Transform, _camera: &mut Camera) {
+        let (motion, _) = self.mouse.take();
+        if self.mouse.pressed.contains(&MouseButton::Right) {
+            self.yaw -= Rad(motion.0 * self.look_speed);
+            self.pitch = Rad((self.pitch.0 - motion.1 * self.look_speed).max(-MAX_PITCH).min(MAX_PITCH));
+        }
+
+        let rotation = self.rotation();
+        let direction = Vector3 {
+            x: self.axis(VirtualKeyCode::D, VirtualKeyCode::A),
+            y: self.axis(VirtualKeyCode::Space, VirtualKeyCode::LShift),
+            z: self.axis(VirtualKeyCode::S, VirtualKeyCode::W),
+        };
+        if direction.magnitude2() > 0.0 {
+            self.position += rotation.rotate_vector(direction.normalize()) * self.move_speed * dt;
+        }
+
+        transform.translation = self.position.to_vec();
+        transform.rotation = rotation;
+    }
+}
+
+/// Pans parallel to the view plane and zooms, for 2D views and orthographic
+/// cameras. Dragging with the middle button pans, the wheel zooms: it scales
+/// the view height of orthographic cameras and dollies perspective ones.
+pub struct PanZoomController {
+    pub position: Point3<f32>,
+    pub rotation: Quaternion<f32>,
+
+    /// World units per pixel of mouse motion, at a view height of 1.0.
+    pub pan_speed: f32,
+    /// Fraction of the view height (or world units, for perspective cameras) per line scrolled.
+    pub zoom_speed: f32,
+
+    mouse: MouseState,
+}
+
+impl PanZoomController {
+    pub fn new(position: Point3<f32>, rotation: Quaternion<f32>) -> PanZoomController {
+        PanZoomController {
+            position,
+            rotation,
+            pan_speed: 0.002,
+            zoom_speed: 0.1,
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl CameraController for PanZoomController {
+    fn handle_event(&mut self, event: &Event) {
+        self.mouse.handle_event(event);
+    }
+
+    fn update(&mut self, _dt: f32, transform: &mut Transform, camera: &mut Camera) {
+        let (motion, scroll) = self.mouse.take();
+
+        let view_height = match camera.projection {
+            Projection::Orthographic { ref mut height, .. } => {
+                *height = (*height * (1.0 - scroll * self.zoom_speed)).max(1e-3);
+                *height
+            },
+            Projection::Perspective { .. } => {
+                self.position += self.rotation.rotate_vector(-Vector3::unit_z()) * scroll * self.zoom_speed;
+                1.0
+            },
+        };
+
+        if self.mouse.pressed.contains(&MouseButton::Middle) {
+            // Drag the scene along with the cursor. Screen Y points down, so
+            // moving the mouse down moves the camera up.
+            let pan = Vector3 { x: -motion.0, y: motion.1, z: 0.0 } * self.pan_speed * view_height;
+            self.position += self.rotation.rotate_vector(pan);
+        }
+
+        transform.translation = self.position.to_vec();
+        transform.rotation = self.rotation;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(matrix: Matrix4<f32>, point: Vector3<f32>) -> Vector3<f32> {
+        let clip = matrix * point.extend(1.0);
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn test_perspective_depth() {
+        let mut camera = Camera::perspective(Rad(FRAC_PI_2), 0.1, 100.0);
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -0.1)).z - 0.0).abs() < 1e-5);
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -100.0)).z - 1.0).abs() < 1e-5);
+
+        camera.reversed_z = true;
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -0.1)).z - 1.0).abs() < 1e-5);
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -100.0)).z - 0.0).abs() < 1e-5);
+        assert_eq!(camera.depth_clear_value(), 0.0);
+    }
+
+    #[test]
+    fn test_infinite_perspective() {
+        let mut camera = Camera::infinite_perspective(Rad(FRAC_PI_2), 0.1);
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -0.1)).z - 0.0).abs() < 1e-5);
+        let far = project(camera.projection(), Vector3::new(0.0, 0.0, -1e6)).z;
+        assert!(far < 1.0 && far > 0.999);
+
+        camera.reversed_z = true;
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -0.1)).z - 1.0).abs() < 1e-5);
+        assert!(project(camera.projection(), Vector3::new(0.0, 0.0, -1e6)).z < 1e-6);
+    }
+
+    #[test]
+    fn test_aspect_and_y_flip() {
+        let mut camera = Camera::perspective(Rad(FRAC_PI_2), 0.1, 100.0);
+        camera.set_viewport([200, 100]);
+        let corner = project(camera.projection(), Vector3::new(2.0, 1.0, -1.0));
+        assert!((corner.x - 1.0).abs() < 1e-5);
+        assert!((corner.y + 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_orthographic() {
+        let mut camera = Camera::orthographic(4.0, 1.0, 11.0);
+        camera.set_viewport([100, 100]);
+        let point = project(camera.projection(), Vector3::new(2.0, -2.0, -6.0));
+        assert!((point - Vector3::new(1.0, 1.0, 0.5)).magnitude() < 1e-5);
+
+        camera.reversed_z = true;
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -1.0)).z - 1.0).abs() < 1e-5);
+        assert!((project(camera.projection(), Vector3::new(0.0, 0.0, -11.0)).z - 0.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_orbit_eye() {
+        let mut controller = OrbitController::new(Point3::new(1.0, 0.0, 0.0), 2.0);
+        assert!((controller.eye() - Point3::new(1.0, 0.0, 2.0)).magnitude() < 1e-5);
+        controller.yaw = Rad(FRAC_PI_2);
+        assert!((controller.eye() - Point3::new(3.0, 0.0, 0.0)).magnitude() < 1e-5);
+    }
+}
@@ -0,0 +1,596 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use texture;
+use texture::ColorSpace;
+use texture::mip_levels;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::instance::Features;
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX2_IDENTIFIER: &[u8] = &[0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a];
+
+// DDS header flags and sizes.
+const DDS_HEADER_SIZE: usize = 124;
+const DDS_DX10_HEADER_SIZE: usize = 20;
+const DDPF_FOURCC: u32 = 0x4;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+// Larger than any device supports, and small enough that level sizes can't overflow.
+const MAX_DIMENSION: u32 = 1 << 16;
+
+/// Error that can happen when loading a texture.
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    /// An image other than DDS or KTX2 couldn't be read or decoded.
+    Image(String),
+    /// The file isn't a DDS or KTX2 container, or is truncated.
+    InvalidContainer(&'static str),
+    /// The container is valid, but holds something we can't load.
+    UnsupportedContent(String),
+    /// The device can't sample the format of the texture.
+    UnsupportedByDevice(Format),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextureError::Io(ref err) => write!(f, "failed to read texture: {}", err),
+            TextureError::Image(ref err) => write!(f, "{}", err),
+            TextureError::InvalidContainer(reason) => write!(f, "invalid texture container: {}", reason),
+            TextureError::UnsupportedContent(ref what) => write!(f, "unsupported texture: {}", what),
+            TextureError::UnsupportedByDevice(format) => write!(f, "the device doesn't support sampling {:?} textures", format),
+        }
+    }
+}
+
+impl error::Error for TextureError {
+    fn description(&self) -> &str {
+        "failed to load texture"
+    }
+}
+
+impl From<io::Error> for TextureError {
+    fn from(err: io::Error) -> TextureError { TextureError::Io(err) }
+}
+
+/// A texture with its mip chain, ready to be copied into an image as is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressedTexture {
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    /// 6 for cubemaps, with faces in +X, -X, +Y, -Y, +Z, -Z order, and 1 otherwise.
+    pub faces: u32,
+    /// The data of every face of every mip level, indexed by level then face.
+    pub levels: Vec<Vec<Vec<u8>>>,
+}
+
+/// Width and height of the blocks of `format` in texels, and their size in
+/// bytes, for the formats that can be loaded from a container. Uncompressed
+/// formats have 1x1 blocks.
+pub fn block_layout(format: Format) -> Option<([u32; 2], usize)> {
+    let layout = match format {
+        Format::R8G8B8A8Unorm | Format::R8G8B8A8Srgb => ([1, 1], 4),
+        Format::R16G16B16A16Sfloat => ([1, 1], 8),
+        Format::R32G32B32A32Sfloat => ([1, 1], 16),
+        Format::BC1_RGBUnormBlock | Format::BC1_RGBSrgbBlock |
+        Format::BC1_RGBAUnormBlock | Format::BC1_RGBASrgbBlock |
+        Format::BC4UnormBlock | Format::BC4SnormBlock => ([4, 4], 8),
+        Format::BC2UnormBlock | Format::BC2SrgbBlock |
+        Format::BC3UnormBlock | Format::BC3SrgbBlock |
+        Format::BC5UnormBlock | Format::BC5SnormBlock |
+        Format::BC6HUfloatBlock | Format::BC6HSfloatBlock |
+        Format::BC7UnormBlock | Format::BC7SrgbBlock => ([4, 4], 16),
+        Format::ETC2_R8G8B8UnormBlock | Format::ETC2_R8G8B8SrgbBlock |
+        Format::ETC2_R8G8B8A1UnormBlock | Format::ETC2_R8G8B8A1SrgbBlock |
+        Format::EAC_R11UnormBlock | Format::EAC_R11SnormBlock => ([4, 4], 8),
+        Format::ETC2_R8G8B8A8UnormBlock | Format::ETC2_R8G8B8A8SrgbBlock |
+        Format::EAC_R11G11UnormBlock | Format::EAC_R11G11SnormBlock => ([4, 4], 16),
+        Format::ASTC_4x4UnormBlock | Format::ASTC_4x4SrgbBlock => ([4, 4], 16),
+        Format::ASTC_5x4UnormBlock | Format::ASTC_5x4SrgbBlock => ([5, 4], 16),
+        Format::ASTC_5x5UnormBlock | Format::ASTC_5x5SrgbBlock => ([5, 5], 16),
+        Format::ASTC_6x5UnormBlock | Format::ASTC_6x5SrgbBlock => ([6, 5], 16),
+        Format::ASTC_6x6UnormBlock | Format::ASTC_6x6SrgbBlock => ([6, 6], 16),
+        Format::ASTC_8x5UnormBlock | Format::ASTC_8x5SrgbBlock => ([8, 5], 16),
+        Format::ASTC_8x6UnormBlock | Format::ASTC_8x6SrgbBlock => ([8, 6], 16),
+        Format::ASTC_8x8UnormBlock | Format::ASTC_8x8SrgbBlock => ([8, 8], 16),
+        Format::ASTC_10x5UnormBlock | Format::ASTC_10x5SrgbBlock => ([10, 5], 16),
+        Format::ASTC_10x6UnormBlock | Format::ASTC_10x6SrgbBlock => ([10, 6], 16),
+        Format::ASTC_10x8UnormBlock | Format::ASTC_10x8SrgbBlock => ([10, 8], 16),
+        Format::ASTC_10x10UnormBlock | Format::ASTC_10x10SrgbBlock => ([10, 10], 16),
+        Format::ASTC_12x10UnormBlock | Format::ASTC_12x10SrgbBlock => ([12, 10], 16),
+        Format::ASTC_12x12UnormBlock | Format::ASTC_12x12SrgbBlock => ([12, 12], 16),
+        _ => return None,
+    };
+    Some(layout)
+}
+
+/// Size in bytes of one face of a mip level of the given size.
+pub fn level_size(format: Format, width: u32, height: u32) -> Option<usize> {
+    block_layout(format).map(|(block, bytes)| {
+        let blocks_x = (width as usize + block[0] as usize - 1) / block[0] as usize;
+        let blocks_y = (height as usize + block[1] as usize - 1) / block[1] as usize;
+        blocks_x * blocks_y * bytes
+    })
+}
+
+/// Whether `device` can sample `format` with optimal tiling, according to the
+/// format properties of its physical device. Compressed formats also need
+/// the matching `texture_compression_*` feature enabled on the device.
+pub fn is_supported(format: Format, device: &Device) -> bool {
+    is_enabled(format, device.enabled_features()) && texture::can_sample(device.physical_device(), format)
+}
+
+// Whether `features` include the feature `format` needs, if any.
+fn is_enabled(format: Format, features: &Features) -> bool {
+    match format {
+        Format::BC1_RGBUnormBlock | Format::BC1_RGBSrgbBlock |
+        Format::BC1_RGBAUnormBlock | Format::BC1_RGBASrgbBlock |
+        Format::BC2UnormBlock | Format::BC2SrgbBlock |
+        Format::BC3UnormBlock | Format::BC3SrgbBlock |
+        Format::BC4UnormBlock | Format::BC4SnormBlock |
+        Format::BC5UnormBlock | Format::BC5SnormBlock |
+        Format::BC6HUfloatBlock | Format::BC6HSfloatBlock |
+        Format::BC7UnormBlock | Format::BC7SrgbBlock => features.texture_compression_bc,
+        Format::ETC2_R8G8B8UnormBlock | Format::ETC2_R8G8B8SrgbBlock |
+        Format::ETC2_R8G8B8A1UnormBlock | Format::ETC2_R8G8B8A1SrgbBlock |
+        Format::ETC2_R8G8B8A8UnormBlock | Format::ETC2_R8G8B8A8SrgbBlock |
+        Format::EAC_R11UnormBlock | Format::EAC_R11SnormBlock |
+        Format::EAC_R11G11UnormBlock | Format::EAC_R11G11SnormBlock => features.texture_compression_etc2,
+        format => match block_layout(format) {
+            Some(([1, 1], _)) => true,
+            Some(_) => features.texture_compression_astc_ldr,
+            None => false,
+        },
+    }
+}
+
+/// Loads a DDS or KTX2 file, recognized by its header. Legacy DDS files
+/// don't say whether they hold sRGB data, so `color_space` picks the format
+/// for them; DX10 DDS and KTX2 files always say.
+pub fn load_compressed(filename: &str, color_space: ColorSpace) -> Result<CompressedTexture, TextureError> {
+    let mut bytes = vec![];
+    File::open(filename)?.read_to_end(&mut bytes)?;
+    if bytes.starts_with(KTX2_IDENTIFIER) {
+        parse_ktx2(&bytes)
+    } else if bytes.starts_with(DDS_MAGIC) {
+        parse_dds(&bytes, color_space)
+    } else {
+        Err(TextureError::InvalidContainer("not a DDS or KTX2 file"))
+    }
+}
+
+pub fn parse_dds(bytes: &[u8], color_space: ColorSpace) -> Result<CompressedTexture, TextureError> {
+    let header = DDS_MAGIC.len();
+    if read_u32(bytes, header)? as usize != DDS_HEADER_SIZE {
+        return Err(TextureError::InvalidContainer("bad DDS header size"));
+    }
+    let height = read_u32(bytes, header + 8)?;
+    let width = read_u32(bytes, header + 12)?;
+    let depth = read_u32(bytes, header + 20)?;
+    let levels = read_u32(bytes, header + 24)?.max(1);
+    let pixel_format_flags = read_u32(bytes, header + 76)?;
+    let four_cc = read_bytes(bytes, header + 80, 4)?;
+    let caps2 = read_u32(bytes, header + 108)?;
+
+    if pixel_format_flags & DDPF_FOURCC == 0 {
+        return Err(TextureError::UnsupportedContent("DDS files without a FourCC".to_string()));
+    }
+    let mut faces = if caps2 & DDSCAPS2_CUBEMAP != 0 { 6 } else { 1 };
+    let mut offset = header + DDS_HEADER_SIZE;
+    let format = if four_cc == b"DX10" {
+        let dxgi = read_u32(bytes, offset)?;
+        let misc_flags = read_u32(bytes, offset + 8)?;
+        let array_size = read_u32(bytes, offset + 12)?;
+        offset += DDS_DX10_HEADER_SIZE;
+        if misc_flags & DDS_RESOURCE_MISC_TEXTURECUBE != 0 {
+            faces = 6;
+        }
+        if array_size > 1 {
+            return Err(TextureError::UnsupportedContent("DDS texture arrays".to_string()));
+        }
+        dxgi_format(dxgi).ok_or_else(|| TextureError::UnsupportedContent(format!("DXGI format {}", dxgi)))?
+    } else {
+        let format = match four_cc {
+            b"DXT1" => Format::BC1_RGBAUnormBlock,
+            b"DXT2" | b"DXT3" => Format::BC2UnormBlock,
+            b"DXT4" | b"DXT5" => Format::BC3UnormBlock,
+            b"ATI1" | b"BC4U" => Format::BC4UnormBlock,
+            b"BC4S" => Format::BC4SnormBlock,
+            b"ATI2" | b"BC5U" => Format::BC5UnormBlock,
+            b"BC5S" => Format::BC5SnormBlock,
+            _ => return Err(TextureError::UnsupportedContent(
+                format!("DDS FourCC {:?}", String::from_utf8_lossy(four_cc)))),
+        };
+        match color_space {
+            ColorSpace::Srgb => srgb_format(format),
+            ColorSpace::Linear => format,
+        }
+    };
+    if depth > 1 {
+        return Err(TextureError::UnsupportedContent("3D textures".to_string()));
+    }
+    check_size(width, height, levels)?;
+
+    // DDS stores each face with its whole mip chain, one face after the other.
+    let mut data = vec![vec![]; levels as usize];
+    for _ in 0..faces {
+        for level in 0..levels {
+            let size = level_size(format, (width >> level).max(1), (height >> level).max(1)).unwrap();
+            data[level as usize].push(read_bytes(bytes, offset, size)?.to_vec());
+            offset += size;
+        }
+    }
+    texture(format, width, height, faces, data)
+}
+
+pub fn parse_ktx2(bytes: &[u8]) -> Result<CompressedTexture, TextureError> {
+    let header = KTX2_IDENTIFIER.len();
+    let vk_format = read_u32(bytes, header)?;
+    let width = read_u32(bytes, header + 8)?;
+    let height = read_u32(bytes, header + 12)?.max(1);
+    let depth = read_u32(bytes, header + 16)?;
+    let layers = read_u32(bytes, header + 20)?;
+    let faces = read_u32(bytes, header + 24)?;
+    // Zero levels means the loader should generate the chain, which we can't
+    // for block-compressed formats, so only the base level is used.
+    let levels = read_u32(bytes, header + 28)?.max(1);
+    let supercompression = read_u32(bytes, header + 32)?;
+
+    if vk_format == 0 {
+        return Err(TextureError::UnsupportedContent("KTX2 files without a Vulkan format, like Basis Universal".to_string()));
+    }
+    let format = vk_format_from_num(vk_format)
+        .ok_or_else(|| TextureError::UnsupportedContent(format!("VkFormat {}", vk_format)))?;
+    if supercompression != 0 {
+        return Err(TextureError::UnsupportedContent(format!("KTX2 supercompression scheme {}", supercompression)));
+    }
+    if depth > 1 {
+        return Err(TextureError::UnsupportedContent("3D textures".to_string()));
+    }
+    if layers > 1 {
+        return Err(TextureError::UnsupportedContent("KTX2 texture arrays".to_string()));
+    }
+    if faces != 1 && faces != 6 {
+        return Err(TextureError::InvalidContainer("KTX2 face count must be 1 or 6"));
+    }
+    check_size(width, height, levels)?;
+
+    // The level index follows the 48 byte header and the 32 byte index of
+    // the data format descriptor, key/value and supercompression data.
+    let level_index = header + 68;
+    let mut data = vec![];
+    for level in 0..levels as usize {
+        let offset = read_offset(bytes, level_index + level * 24)?;
+        let length = read_offset(bytes, level_index + level * 24 + 8)?;
+        let size = level_size(format, (width >> level).max(1), (height >> level).max(1)).unwrap();
+        if length != size * faces as usize {
+            return Err(TextureError::InvalidContainer("KTX2 level has the wrong size"));
+        }
+        let level_data = read_bytes(bytes, offset, length)?;
+        data.push(level_data.chunks(size).map(|face| face.to_vec()).collect());
+    }
+    texture(format, width, height, faces, data)
+}
+
+// Checks the size before anything is allocated for the levels.
+fn check_size(width: u32, height: u32, levels: u32) -> Result<(), TextureError> {
+    if width == 0 || height == 0 {
+        return Err(TextureError::InvalidContainer("empty texture"));
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(TextureError::InvalidContainer("texture is too large"));
+    }
+    if levels > mip_levels(width, height) {
+        return Err(TextureError::InvalidContainer("more mip levels than the texture size allows"));
+    }
+    Ok(())
+}
+
+fn texture(format: Format, width: u32, height: u32, faces: u32, levels: Vec<Vec<Vec<u8>>>) -> Result<CompressedTexture, TextureError> {
+    if faces == 6 && width != height {
+        return Err(TextureError::InvalidContainer("cubemap faces aren't square"));
+    }
+    Ok(CompressedTexture { format, width, height, faces, levels })
+}
+
+// The sRGB variant of a format, if it has one.
+fn srgb_format(format: Format) -> Format {
+    match format {
+        Format::BC1_RGBAUnormBlock => Format::BC1_RGBASrgbBlock,
+        Format::BC2UnormBlock => Format::BC2SrgbBlock,
+        Format::BC3UnormBlock => Format::BC3SrgbBlock,
+        format => format,
+    }
+}
+
+fn dxgi_format(dxgi: u32) -> Option<Format> {
+    let format = match dxgi {
+        2 => Format::R32G32B32A32Sfloat,
+        10 => Format::R16G16B16A16Sfloat,
+        28 => Format::R8G8B8A8Unorm,
+        29 => Format::R8G8B8A8Srgb,
+        71 => Format::BC1_RGBAUnormBlock,
+        72 => Format::BC1_RGBASrgbBlock,
+        74 => Format::BC2UnormBlock,
+        75 => Format::BC2SrgbBlock,
+        77 => Format::BC3UnormBlock,
+        78 => Format::BC3SrgbBlock,
+        80 => Format::BC4UnormBlock,
+        81 => Format::BC4SnormBlock,
+        83 => Format::BC5UnormBlock,
+        84 => Format::BC5SnormBlock,
+        95 => Format::BC6HUfloatBlock,
+        96 => Format::BC6HSfloatBlock,
+        98 => Format::BC7UnormBlock,
+        99 => Format::BC7SrgbBlock,
+        _ => return None,
+    };
+    Some(format)
+}
+
+// The formats of `block_layout`, by `VkFormat` value.
+fn vk_format_from_num(value: u32) -> Option<Format> {
+    let format = match value {
+        37 => Format::R8G8B8A8Unorm,
+        43 => Format::R8G8B8A8Srgb,
+        97 => Format::R16G16B16A16Sfloat,
+        109 => Format::R32G32B32A32Sfloat,
+        131 => Format::BC1_RGBUnormBlock,
+        132 => Format::BC1_RGBSrgbBlock,
+        133 => Format::BC1_RGBAUnormBlock,
+        134 => Format::BC1_RGBASrgbBlock,
+        135 => Format::BC2UnormBlock,
+        136 => Format::BC2SrgbBlock,
+        137 => Format::BC3UnormBlock,
+        138 => Format::BC3SrgbBlock,
+        139 => Format::BC4UnormBlock,
+        140 => Format::BC4SnormBlock,
+        141 => Format::BC5UnormBlock,
+        142 => Format::BC5SnormBlock,
+        143 => Format::BC6HUfloatBlock,
+        144 => Format::BC6HSfloatBlock,
+        145 => Format::BC7UnormBlock,
+        146 => Format::BC7SrgbBlock,
+        147 => Format::ETC2_R8G8B8UnormBlock,
+        148 => Format::ETC2_R8G8B8SrgbBlock,
+        149 => Format::ETC2_R8G8B8A1UnormBlock,
+        150 => Format::ETC2_R8G8B8A1SrgbBlock,
+        151 => Format::ETC2_R8G8B8A8UnormBlock,
+        152 => Format::ETC2_R8G8B8A8SrgbBlock,
+        153 => Format::EAC_R11UnormBlock,
+        154 => Format::EAC_R11SnormBlock,
+        155 => Format::EAC_R11G11UnormBlock,
+        156 => Format::EAC_R11G11SnormBlock,
+        157 => Format::ASTC_4x4UnormBlock,
+        158 => Format::ASTC_4x4SrgbBlock,
+        159 => Format::ASTC_5x4UnormBlock,
+        160 => Format::ASTC_5x4SrgbBlock,
+        161 => Format::ASTC_5x5UnormBlock,
+        162 => Format::ASTC_5x5SrgbBlock,
+        163 => Format::ASTC_6x5UnormBlock,
+        164 => Format::ASTC_6x5SrgbBlock,
+        165 => Format::ASTC_6x6UnormBlock,
+        166 => Format::ASTC_6x6SrgbBlock,
+        167 => Format::ASTC_8x5UnormBlock,
+        168 => Format::ASTC_8x5SrgbBlock,
+        169 => Format::ASTC_8x6UnormBlock,
+        170 => Format::ASTC_8x6SrgbBlock,
+        171 => Format::ASTC_8x8UnormBlock,
+        172 => Format::ASTC_8x8SrgbBlock,
+        173 => Format::ASTC_10x5UnormBlock,
+        174 => Format::ASTC_10x5SrgbBlock,
+        175 => Format::ASTC_10x6UnormBlock,
+        176 => Format::ASTC_10x6SrgbBlock,
+        177 => Format::ASTC_10x8UnormBlock,
+        178 => Format::ASTC_10x8SrgbBlock,
+        179 => Format::ASTC_10x10UnormBlock,
+        180 => Format::ASTC_10x10SrgbBlock,
+        181 => Format::ASTC_12x10UnormBlock,
+        182 => Format::ASTC_12x10SrgbBlock,
+        183 => Format::ASTC_12x12UnormBlock,
+        184 => Format::ASTC_12x12SrgbBlock,
+        _ => return None,
+    };
+    Some(format)
+}
+
+fn read_bytes(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8], TextureError> {
+    offset.checked_add(length)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or(TextureError::InvalidContainer("file is truncated"))
+}
+
+// Both containers are little endian.
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    let b = read_bytes(bytes, offset, 4)?;
+    Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    Ok(read_u32(bytes, offset)? as u64 | (read_u32(bytes, offset + 4)? as u64) << 32)
+}
+
+// A 64-bit offset or length into the file, which can't be valid if it doesn't fit a `usize`.
+fn read_offset(bytes: &[u8], offset: usize) -> Result<usize, TextureError> {
+    let value = read_u64(bytes, offset)?;
+    if value > usize::max_value() as u64 {
+        return Err(TextureError::InvalidContainer("file is truncated"));
+    }
+    Ok(value as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        for i in 0..4 {
+            bytes.push((value >> (8 * i)) as u8);
+        }
+    }
+
+    fn dds_header(four_cc: &[u8], width: u32, height: u32, levels: u32, caps2: u32) -> Vec<u8> {
+        let mut bytes = DDS_MAGIC.to_vec();
+        let mut header = [0u32; 31];
+        header[0] = DDS_HEADER_SIZE as u32;
+        header[2] = height;
+        header[3] = width;
+        header[6] = levels;
+        header[18] = 32;
+        header[19] = DDPF_FOURCC;
+        header[27] = caps2;
+        for (i, &value) in header.iter().enumerate() {
+            if i == 20 {
+                bytes.extend_from_slice(four_cc);
+            } else {
+                push_u32(&mut bytes, value);
+            }
+        }
+        bytes
+    }
+
+    fn ktx2(vk_format: u32, width: u32, height: u32, faces: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for &value in &[vk_format, 1, width, height, 0, 0, faces, levels.len() as u32, 0] {
+            push_u32(&mut bytes, value);
+        }
+        // Empty data format descriptor, key/value and supercompression data.
+        bytes.extend_from_slice(&[0; 32]);
+        // Levels are stored smallest first, after the level index.
+        let mut offset = bytes.len() + levels.len() * 24 + levels.iter().map(|level| level.len()).sum::<usize>();
+        for level in levels {
+            offset -= level.len();
+            for &value in &[offset as u64, level.len() as u64, level.len() as u64] {
+                push_u32(&mut bytes, value as u32);
+                push_u32(&mut bytes, (value >> 32) as u32);
+            }
+        }
+        for level in levels.iter().rev() {
+            bytes.extend_from_slice(level);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_level_size() {
+        assert_eq!(level_size(Format::BC1_RGBAUnormBlock, 8, 8), Some(32));
+        assert_eq!(level_size(Format::BC1_RGBAUnormBlock, 1, 1), Some(8));
+        assert_eq!(level_size(Format::BC7SrgbBlock, 5, 4), Some(32));
+        assert_eq!(level_size(Format::ASTC_6x5UnormBlock, 12, 11), Some(2 * 3 * 16));
+        assert_eq!(level_size(Format::R16G16B16A16Sfloat, 3, 2), Some(48));
+        assert_eq!(level_size(Format::D16Unorm, 1, 1), None);
+    }
+
+    #[test]
+    fn test_is_enabled() {
+        let mut features = Features::none();
+        assert!(!is_enabled(Format::BC7UnormBlock, &features));
+        assert!(!is_enabled(Format::ETC2_R8G8B8A8SrgbBlock, &features));
+        assert!(!is_enabled(Format::ASTC_8x8SrgbBlock, &features));
+        assert!(is_enabled(Format::R8G8B8A8Srgb, &features));
+        features.texture_compression_bc = true;
+        assert!(is_enabled(Format::BC7UnormBlock, &features));
+        assert!(!is_enabled(Format::ASTC_4x4UnormBlock, &features));
+        features.texture_compression_astc_ldr = true;
+        assert!(is_enabled(Format::ASTC_4x4UnormBlock, &features));
+    }
+
+    #[test]
+    fn test_dds() {
+        let mut bytes = dds_header(b"DXT1", 8, 4, 4, 0);
+        let data: Vec<u8> = (0..16 + 8 + 8 + 8).collect();
+        bytes.extend_from_slice(&data);
+
+        let texture = parse_dds(&bytes, ColorSpace::Linear).unwrap();
+        assert_eq!(texture.format, Format::BC1_RGBAUnormBlock);
+        assert_eq!((texture.width, texture.height, texture.faces), (8, 4, 1));
+        assert_eq!(texture.levels.len(), 4);
+        assert_eq!(texture.levels[0], vec![data[0..16].to_vec()]);
+        assert_eq!(texture.levels[3], vec![data[32..40].to_vec()]);
+
+        assert_eq!(parse_dds(&bytes, ColorSpace::Srgb).unwrap().format, Format::BC1_RGBASrgbBlock);
+        match parse_dds(&bytes[..bytes.len() - 1], ColorSpace::Linear) {
+            Err(TextureError::InvalidContainer("file is truncated")) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_dds_dx10_cubemap() {
+        let mut bytes = dds_header(b"DX10", 4, 4, 2, DDSCAPS2_CUBEMAP);
+        for &value in &[99, 3, DDS_RESOURCE_MISC_TEXTURECUBE, 1, 0] {
+            push_u32(&mut bytes, value);
+        }
+        // Each face has two levels of one BC7 block each.
+        let data: Vec<u8> = (0..6 * 2 * 16).map(|i| (i / 16) as u8).collect();
+        bytes.extend_from_slice(&data);
+
+        let texture = parse_dds(&bytes, ColorSpace::Linear).unwrap();
+        assert_eq!(texture.format, Format::BC7SrgbBlock);
+        assert_eq!(texture.faces, 6);
+        assert_eq!(texture.levels.len(), 2);
+        assert_eq!(texture.levels[0][1], vec![2; 16]);
+        assert_eq!(texture.levels[1][5], vec![11; 16]);
+    }
+
+    #[test]
+    fn test_ktx2() {
+        let base: Vec<u8> = (0..32).collect();
+        let bytes = ktx2(146, 8, 4, 1, &[base.clone(), vec![7; 16], vec![8; 16], vec![9; 16]]);
+        let texture = parse_ktx2(&bytes).unwrap();
+        assert_eq!(texture.format, Format::BC7SrgbBlock);
+        assert_eq!((texture.width, texture.height, texture.faces), (8, 4, 1));
+        assert_eq!(texture.levels, vec![vec![base], vec![vec![7; 16]], vec![vec![8; 16]], vec![vec![9; 16]]]);
+
+        let faces: Vec<u8> = (0..6 * 16).map(|i| (i / 16) as u8).collect();
+        let cubemap = parse_ktx2(&ktx2(157, 4, 4, 6, &[faces])).unwrap();
+        assert_eq!(cubemap.format, Format::ASTC_4x4UnormBlock);
+        assert_eq!(cubemap.levels[0].len(), 6);
+        assert_eq!(cubemap.levels[0][4], vec![4; 16]);
+
+        match parse_ktx2(&ktx2(0, 4, 4, 1, &[vec![0; 16]])) {
+            Err(TextureError::UnsupportedContent(_)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match parse_ktx2(&ktx2(146, 8, 4, 1, &[vec![0; 16]])) {
+            Err(TextureError::InvalidContainer(_)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_malformed_headers() {
+        let invalid = |result: Result<CompressedTexture, TextureError>, reason: &str| match result {
+            Err(TextureError::InvalidContainer(r)) if r == reason => (),
+            result => panic!("unexpected result: {:?}", result),
+        };
+
+        let levels = dds_header(b"DXT1", 8, 4, 0xffff_ffff, 0);
+        invalid(parse_dds(&levels, ColorSpace::Linear), "more mip levels than the texture size allows");
+        let size = dds_header(b"DXT1", 0xffff_ffff, 4, 1, 0);
+        invalid(parse_dds(&size, ColorSpace::Linear), "texture is too large");
+
+        // The offset of the only level points just short of the end of the address space.
+        let mut offset = ktx2(146, 4, 4, 1, &[vec![0; 16]]);
+        let index = KTX2_IDENTIFIER.len() + 68;
+        for byte in &mut offset[index..index + 8] {
+            *byte = 0xff;
+        }
+        invalid(parse_ktx2(&offset), "file is truncated");
+
+        let mut levels = ktx2(146, 4, 4, 1, &[vec![0; 16]]);
+        let count = KTX2_IDENTIFIER.len() + 28;
+        for byte in &mut levels[count..count + 4] {
+            *byte = 0xff;
+        }
+        invalid(parse_ktx2(&levels), "more mip levels than the texture size allows");
+    }
+}
@@ -0,0 +1,134 @@
+use texture::ImageData;
+use texture::LoadedImage;
+
+// Where each face is in a cross layout, as [column, row] in units of faces,
+// in cubemap face order: +X, -X, +Y, -Y, +Z, -Z.
+const HORIZONTAL_CROSS: [[u32; 2]; 6] = [[2, 1], [0, 1], [1, 0], [1, 2], [1, 1], [3, 1]];
+const VERTICAL_CROSS: [[u32; 2]; 6] = [[2, 1], [0, 1], [1, 0], [1, 2], [1, 1], [1, 3]];
+
+/// Cuts the faces out of a horizontal (4x3 faces) or vertical (3x4 faces)
+/// cross and stacks them, returning the size of a face. In a vertical cross
+/// -Z hangs below -Y, so it is upside down. `None` if the image isn't shaped
+/// like a cross.
+pub fn unfold_cross_data<T: Copy>(width: u32, height: u32, channels: usize, data: &[T]) -> Option<(u32, Vec<T>)> {
+    let (layout, size) = if width * 3 == height * 4 {
+        (&HORIZONTAL_CROSS, width / 4)
+    } else if width * 4 == height * 3 {
+        (&VERTICAL_CROSS, width / 3)
+    } else {
+        return None;
+    };
+    if size == 0 {
+        return None;
+    }
+    let vertical = height > width;
+    let mut faces = Vec::with_capacity((6 * size * size) as usize * channels);
+    for (face, position) in layout.iter().enumerate() {
+        let upside_down = vertical && face == 5;
+        for y in 0..size {
+            for x in 0..size {
+                let (x, y) = if upside_down { (size - 1 - x, size - 1 - y) } else { (x, y) };
+                let start = ((position[1] * size + y) * width + position[0] * size + x) as usize * channels;
+                faces.extend_from_slice(&data[start..start + channels]);
+            }
+        }
+    }
+    Some((size, faces))
+}
+
+/// Like `unfold_cross_data`, returning the faces as one image, `size` wide and
+/// `6 * size` high.
+pub fn unfold_cross(image: &LoadedImage) -> Option<LoadedImage> {
+    let (width, height, channels) = (image.width, image.height, image.channels);
+    let (size, data) = match image.data {
+        ImageData::U8(ref data) => unfold_cross_data(width, height, channels, data).map(|(size, faces)| (size, ImageData::U8(faces))),
+        ImageData::U16(ref data) => unfold_cross_data(width, height, channels, data).map(|(size, faces)| (size, ImageData::U16(faces))),
+        ImageData::F16(ref data) => unfold_cross_data(width, height, channels, data).map(|(size, faces)| (size, ImageData::F16(faces))),
+        ImageData::F32(ref data) => unfold_cross_data(width, height, channels, data).map(|(size, faces)| (size, ImageData::F32(faces))),
+    }?;
+    Some(LoadedImage { width: size, height: 6 * size, channels, data })
+}
+
+/// Stacks six face images, in cubemap face order, into one image like
+/// `unfold_cross` does. Panics unless the faces are square and of the same
+/// size and precision.
+pub fn stack_faces(faces: Vec<LoadedImage>) -> LoadedImage {
+    if faces.len() != 6 {
+        panic!("A cubemap needs 6 faces, got {}!", faces.len());
+    }
+    let mut faces = faces.into_iter();
+    let mut stacked = faces.next().unwrap();
+    let (size, channels) = (stacked.width, stacked.channels);
+    if stacked.height != size {
+        panic!("Cubemap faces must be square!");
+    }
+    for face in faces {
+        if face.width != size || face.height != size || face.channels != channels {
+            panic!("Cubemap faces must all have the same size and channels!");
+        }
+        match (&mut stacked.data, face.data) {
+            (&mut ImageData::U8(ref mut data), ImageData::U8(face)) => data.extend(face),
+            (&mut ImageData::U16(ref mut data), ImageData::U16(face)) => data.extend(face),
+            (&mut ImageData::F16(ref mut data), ImageData::F16(face)) => data.extend(face),
+            (&mut ImageData::F32(ref mut data), ImageData::F32(face)) => data.extend(face),
+            _ => panic!("Cubemap faces must all have the same precision!"),
+        }
+    }
+    stacked.height = 6 * size;
+    stacked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_horizontal_cross() {
+        // One texel per face, named after the face, with empty texels as 0.
+        let data: Vec<u8> = vec![
+            0, 3, 0, 0,
+            2, 5, 1, 6,
+            0, 4, 0, 0,
+        ];
+        assert_eq!(unfold_cross_data(4, 3, 1, &data), Some((1, vec![1, 2, 3, 4, 5, 6])));
+        assert_eq!(unfold_cross_data(4, 4, 1, &data), None);
+    }
+
+    #[test]
+    fn test_vertical_cross() {
+        // 2x2 texels per face; the -Z face hangs upside down.
+        let data: Vec<u8> = vec![
+            0, 0,  30, 31,  0, 0,
+            0, 0,  32, 33,  0, 0,
+            20, 21,  50, 51,  10, 11,
+            22, 23,  52, 53,  12, 13,
+            0, 0,  40, 41,  0, 0,
+            0, 0,  42, 43,  0, 0,
+            0, 0,  63, 62,  0, 0,
+            0, 0,  61, 60,  0, 0,
+        ];
+        let (size, faces) = unfold_cross_data(6, 8, 1, &data).unwrap();
+        assert_eq!(size, 2);
+        assert_eq!(faces, vec![
+            10, 11, 12, 13,
+            20, 21, 22, 23,
+            30, 31, 32, 33,
+            40, 41, 42, 43,
+            50, 51, 52, 53,
+            60, 61, 62, 63,
+        ]);
+    }
+
+    #[test]
+    fn test_stack_faces() {
+        let face = |value| LoadedImage { width: 1, height: 1, channels: 2, data: ImageData::U16(vec![value, value]) };
+        let stacked = stack_faces((0..6).map(face).collect());
+        assert_eq!((stacked.width, stacked.height, stacked.channels), (1, 6, 2));
+        assert_eq!(stacked.data, ImageData::U16(vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5]));
+
+        let cross = LoadedImage { width: 4, height: 3, channels: 1, data: ImageData::F32((0..12).map(|i| i as f32).collect()) };
+        let unfolded = unfold_cross(&cross).unwrap();
+        assert_eq!((unfolded.width, unfolded.height), (1, 6));
+        assert_eq!(unfolded.data, ImageData::F32(vec![6.0, 4.0, 1.0, 9.0, 5.0, 7.0]));
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::Level;
+use vulkano::instance;
+use vulkano::instance::Instance;
+use vulkano::instance::debug::DebugCallback;
+use vulkano::instance::debug::DebugCallbackCreationError;
+use vulkano::instance::debug::MessageTypes;
+
+/// Validation layers we know of, newest first.
+const VALIDATION_LAYERS: &[&str] = &["VK_LAYER_KHRONOS_validation", "VK_LAYER_LUNARG_standard_validation"];
+
+/// The first known validation layer that is installed, if any.
+pub fn available_validation_layer() -> Option<&'static str> {
+    let installed: Vec<String> = match instance::layers_list() {
+        Ok(layers) => layers.map(|layer| layer.name().to_string()).collect(),
+        Err(_) => return None,
+    };
+    VALIDATION_LAYERS.iter().cloned().find(|layer| installed.iter().any(|name| name == layer))
+}
+
+/// The `log` level of a debug report message.
+pub fn message_level(ty: &MessageTypes) -> Level {
+    if ty.error {
+        Level::Error
+    } else if ty.warning || ty.performance_warning {
+        Level::Warn
+    } else if ty.information {
+        Level::Info
+    } else {
+        Level::Debug
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationMessage {
+    pub level: Level,
+    pub layer_prefix: String,
+    pub description: String,
+}
+
+/// Messages collected by a `DebugReport`. Clones share the same messages, so
+/// tests can hold on to them while `Graphics` owns the report.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationMessages {
+    messages: Arc<Mutex<Vec<ValidationMessage>>>,
+}
+
+impl ValidationMessages {
+    pub fn new() -> ValidationMessages {
+        ValidationMessages::default()
+    }
+
+    pub fn push(&self, message: ValidationMessage) {
+        self.messages.lock().unwrap().push(message);
+    }
+
+    /// Removes and returns every message collected so far.
+    pub fn take(&self) -> Vec<ValidationMessage> {
+        let mut messages = self.messages.lock().unwrap();
+        messages.drain(..).collect()
+    }
+
+    /// Number of collected messages at least as severe as `level`.
+    pub fn count(&self, level: Level) -> usize {
+        self.messages.lock().unwrap().iter().filter(|message| message.level <= level).count()
+    }
+}
+
+/// Routes the debug report messages of an instance into `log`, under the
+/// `vulkan` target, and collects them.
+pub struct DebugReport {
+    pub messages: ValidationMessages,
+    _callback: DebugCallback,
+}
+
+impl DebugReport {
+    /// The instance must have been created with the `ext_debug_report` extension.
+    pub fn new(instance: &Arc<Instance>) -> Result<DebugReport, DebugCallbackCreationError> {
+        let messages = ValidationMessages::new();
+        let collected = messages.clone();
+        let types = MessageTypes {
+            error: true,
+            warning: true,
+            performance_warning: true,
+            information: true,
+            debug: true,
+        };
+        let callback = DebugCallback::new(instance, types, move |message| {
+            let level = message_level(&message.ty);
+            log!(target: "vulkan", level, "{}: {}", message.layer_prefix, message.description);
+            collected.push(ValidationMessage {
+                level,
+                layer_prefix: message.layer_prefix.to_string(),
+                description: message.description.to_string(),
+            });
+        })?;
+        Ok(DebugReport { messages, _callback: callback })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(level: Level) -> ValidationMessage {
+        ValidationMessage {
+            level,
+            layer_prefix: "Validation".to_string(),
+            description: format!("{:?} message", level),
+        }
+    }
+
+    #[test]
+    fn test_message_level() {
+        let ty = |error, warning, performance_warning, information| MessageTypes {
+            error,
+            warning,
+            performance_warning,
+            information,
+            debug: true,
+        };
+        assert_eq!(message_level(&ty(true, true, false, false)), Level::Error);
+        assert_eq!(message_level(&ty(false, true, false, false)), Level::Warn);
+        assert_eq!(message_level(&ty(false, false, true, false)), Level::Warn);
+        assert_eq!(message_level(&ty(false, false, false, true)), Level::Info);
+        assert_eq!(message_level(&ty(false, false, false, false)), Level::Debug);
+    }
+
+    #[test]
+    fn test_collect_messages() {
+        let messages = ValidationMessages::new();
+        let shared = messages.clone();
+        shared.push(message(Level::Info));
+        shared.push(message(Level::Warn));
+        shared.push(message(Level::Error));
+
+        assert_eq!(messages.count(Level::Error), 1);
+        assert_eq!(messages.count(Level::Warn), 2);
+        assert_eq!(messages.count(Level::Trace), 3);
+
+        let taken = messages.take();
+        assert_eq!(taken, vec![message(Level::Info), message(Level::Warn), message(Level::Error)]);
+        assert_eq!(shared.count(Level::Trace), 0);
+    }
+}
@@ -0,0 +1,221 @@
+use std::env;
+use std::sync::Arc;
+
+use vulkano::device::DeviceExtensions;
+use vulkano::instance::Features;
+use vulkano::instance::PhysicalDevice;
+use vulkano::instance::PhysicalDeviceType;
+use vulkano::swapchain::Surface;
+use winit::Window;
+
+/// Environment variable that overrides device selection, either with the
+/// index of a device or with part of its name, e.g. `RENDER_DEVICE=1` or
+/// `RENDER_DEVICE=nvidia`.
+pub const DEVICE_OVERRIDE_VAR: &str = "RENDER_DEVICE";
+
+/// What we know about a physical device when choosing which one to use.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceCandidate {
+    pub index: usize,
+    pub name: String,
+    pub ty: PhysicalDeviceType,
+    /// Size of the largest device-local memory heap, in bytes.
+    pub device_local_memory: u64,
+    pub has_graphics_queue: bool,
+    /// Whether a graphics queue can present to the surface. Always true when headless.
+    pub can_present: bool,
+    pub supports_features: bool,
+    pub supports_extensions: bool,
+}
+
+impl DeviceCandidate {
+    pub fn from_physical(physical: PhysicalDevice, features: &Features, extensions: &DeviceExtensions,
+                         surface: Option<&Arc<Surface<Window>>>) -> DeviceCandidate {
+        let supported_extensions = DeviceExtensions::supported_by_device(physical);
+        DeviceCandidate {
+            index: physical.index(),
+            name: physical.name(),
+            ty: physical.ty(),
+            device_local_memory: physical.memory_heaps()
+                .filter(|heap| heap.is_device_local())
+                .map(|heap| heap.size() as u64)
+                .max().unwrap_or(0),
+            has_graphics_queue: physical.queue_families().any(|q| q.supports_graphics()),
+            can_present: surface.map_or(true, |surface| physical.queue_families().any(|q| {
+                q.supports_graphics() && surface.is_supported(q).unwrap_or(false)
+            })),
+            supports_features: physical.supported_features().superset_of(features),
+            supports_extensions: supported_extensions.intersection(extensions) == *extensions,
+        }
+    }
+
+    /// Why the device can't be used, if it can't.
+    pub fn rejection(&self) -> Option<&'static str> {
+        if !self.has_graphics_queue {
+            Some("no graphics queue")
+        } else if !self.can_present {
+            Some("can't present to the window")
+        } else if !self.supports_features {
+            Some("missing required features")
+        } else if !self.supports_extensions {
+            Some("missing required extensions")
+        } else {
+            None
+        }
+    }
+
+    /// Higher is better: discrete GPUs first, then integrated, virtual and
+    /// software devices, with ties broken by device-local memory.
+    pub fn score(&self) -> (u32, u64) {
+        let type_score = match self.ty {
+            PhysicalDeviceType::DiscreteGpu => 3,
+            PhysicalDeviceType::IntegratedGpu => 2,
+            PhysicalDeviceType::VirtualGpu => 1,
+            PhysicalDeviceType::Cpu | PhysicalDeviceType::Other => 0,
+        };
+        (type_score, self.device_local_memory)
+    }
+}
+
+/// Forces the choice of a device.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceOverride {
+    Index(usize),
+    /// Case-insensitive part of the device name.
+    Name(String),
+}
+
+impl DeviceOverride {
+    /// Reads the override from `DEVICE_OVERRIDE_VAR`, if set.
+    pub fn from_env() -> Option<DeviceOverride> {
+        env::var(DEVICE_OVERRIDE_VAR).ok().map(|value| DeviceOverride::parse(&value))
+    }
+
+    pub fn parse(value: &str) -> DeviceOverride {
+        match value.trim().parse() {
+            Ok(index) => DeviceOverride::Index(index),
+            Err(_) => DeviceOverride::Name(value.trim().to_string()),
+        }
+    }
+
+    pub fn matches(&self, candidate: &DeviceCandidate) -> bool {
+        match *self {
+            DeviceOverride::Index(index) => candidate.index == index,
+            DeviceOverride::Name(ref name) => candidate.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+/// Picks the usable candidate with the highest score, or the first usable
+/// candidate matching `device_override`, and returns its index. Rejected
+/// devices are reported along with the reason.
+pub fn select_device(candidates: &[DeviceCandidate], device_override: Option<&DeviceOverride>) -> Option<usize> {
+    let usable: Vec<&DeviceCandidate> = candidates.iter().filter(|candidate| {
+        match candidate.rejection() {
+            Some(reason) => {
+                info!("Rejecting device {}: {} (type: {:?}): {}", candidate.index, candidate.name, candidate.ty, reason);
+                false
+            },
+            None => true,
+        }
+    }).collect();
+
+    if let Some(device_override) = device_override {
+        match usable.iter().find(|candidate| device_override.matches(candidate)) {
+            Some(candidate) => return Some(candidate.index),
+            None => warn!("No usable device matches {:?}, choosing one automatically", device_override),
+        }
+    }
+
+    usable.iter()
+        .max_by_key(|candidate| candidate.score())
+        .map(|candidate| candidate.index)
+}
+
+/// The highest sample count that is at most `requested` and set in
+/// `supported`, a mask of `VkSampleCountFlagBits`. One sample is always supported.
+pub fn clamp_sample_count(requested: u32, supported: u32) -> u32 {
+    let mut samples = 64;
+    while samples > 1 && (samples > requested || supported & samples == 0) {
+        samples /= 2;
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(index: usize, name: &str, ty: PhysicalDeviceType, device_local_memory: u64) -> DeviceCandidate {
+        DeviceCandidate {
+            index,
+            name: name.to_string(),
+            ty,
+            device_local_memory,
+            has_graphics_queue: true,
+            can_present: true,
+            supports_features: true,
+            supports_extensions: true,
+        }
+    }
+
+    fn candidates() -> Vec<DeviceCandidate> {
+        vec![
+            candidate(0, "Intel(R) UHD Graphics 630", PhysicalDeviceType::IntegratedGpu, 256 << 20),
+            candidate(1, "llvmpipe (LLVM 15.0.7, 256 bits)", PhysicalDeviceType::Cpu, 0),
+            candidate(2, "NVIDIA GeForce GTX 1060", PhysicalDeviceType::DiscreteGpu, 6 << 30),
+        ]
+    }
+
+    #[test]
+    fn test_prefers_discrete_gpu() {
+        assert_eq!(select_device(&candidates(), None), Some(2));
+
+        let mut candidates = candidates();
+        candidates[2].supports_features = false;
+        assert_eq!(candidates[2].rejection(), Some("missing required features"));
+        assert_eq!(select_device(&candidates, None), Some(0));
+
+        candidates[0].can_present = false;
+        assert_eq!(select_device(&candidates, None), Some(1));
+
+        candidates[1].has_graphics_queue = false;
+        assert_eq!(select_device(&candidates, None), None);
+    }
+
+    #[test]
+    fn test_memory_breaks_ties() {
+        let candidates = vec![
+            candidate(0, "small", PhysicalDeviceType::DiscreteGpu, 2 << 30),
+            candidate(1, "big", PhysicalDeviceType::DiscreteGpu, 8 << 30),
+        ];
+        assert_eq!(select_device(&candidates, None), Some(1));
+    }
+
+    #[test]
+    fn test_clamp_sample_count() {
+        // 1, 2, 4 and 8 samples.
+        let supported = 0b1111;
+        assert_eq!(clamp_sample_count(1, supported), 1);
+        assert_eq!(clamp_sample_count(4, supported), 4);
+        assert_eq!(clamp_sample_count(6, supported), 4);
+        assert_eq!(clamp_sample_count(16, supported), 8);
+        assert_eq!(clamp_sample_count(8, 0b0101), 4);
+        assert_eq!(clamp_sample_count(2, 0b0101), 1);
+    }
+
+    #[test]
+    fn test_override() {
+        assert_eq!(DeviceOverride::parse("1"), DeviceOverride::Index(1));
+        assert_eq!(DeviceOverride::parse(" llvmpipe "), DeviceOverride::Name("llvmpipe".to_string()));
+
+        let candidates = candidates();
+        assert_eq!(select_device(&candidates, Some(&DeviceOverride::Index(1))), Some(1));
+        assert_eq!(select_device(&candidates, Some(&DeviceOverride::parse("intel"))), Some(0));
+        // Unusable or unknown devices fall back to automatic selection.
+        assert_eq!(select_device(&candidates, Some(&DeviceOverride::parse("radeon"))), Some(2));
+        let mut candidates = candidates;
+        candidates[1].supports_extensions = false;
+        assert_eq!(select_device(&candidates, Some(&DeviceOverride::Index(1))), Some(2));
+    }
+}
@@ -1,5 +1,7 @@
 use cgmath::*;
 
+pub mod subdivision;
+
 pub struct ModelVertex {
     pub position: Point3<f32>,
     pub normal: Vector3<f32>,
@@ -17,6 +19,62 @@ pub struct Vertex {
 }
 impl_vertex!(Vertex, position, normal, uv, tangent_u, tangent_v);
 
+/// A `Vertex` bound to up to four joints of a skeleton, for GPU skinning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkinnedVertex {
+    pub position: (f32, f32, f32),
+    pub normal: (f32, f32, f32),
+    pub uv: (f32, f32),
+
+    pub tangent_u: (f32, f32, f32),
+    pub tangent_v: (f32, f32, f32),
+
+    /// Indices into the joint palette.
+    pub joints: (u32, u32, u32, u32),
+    /// Influence of each joint. They sum to 1.0.
+    pub weights: (f32, f32, f32, f32),
+}
+impl_vertex!(SkinnedVertex, position, normal, uv, tangent_u, tangent_v, joints, weights);
+
+impl SkinnedVertex {
+    /// Binds a vertex to joints, normalizing the weights. Unused slots should have a weight of 0.0.
+    pub fn new(vertex: Vertex, joints: (u32, u32, u32, u32), weights: (f32, f32, f32, f32)) -> SkinnedVertex {
+        let total = weights.0 + weights.1 + weights.2 + weights.3;
+        let weights = if total > 0.0 {
+            (weights.0 / total, weights.1 / total, weights.2 / total, weights.3 / total)
+        } else {
+            (1.0, 0.0, 0.0, 0.0)
+        };
+        SkinnedVertex {
+            position: vertex.position,
+            normal: vertex.normal,
+            uv: vertex.uv,
+            tangent_u: vertex.tangent_u,
+            tangent_v: vertex.tangent_v,
+            joints,
+            weights,
+        }
+    }
+}
+
+/// Per-instance data for instanced drawing. Binds as a second vertex buffer
+/// next to the mesh, with `world` taking four consecutive locations, or as a
+/// std430 storage buffer indexed by `gl_InstanceIndex`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct InstanceData {
+    /// Column-major world matrix.
+    pub world: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+impl_vertex!(InstanceData, world, color);
+
+impl InstanceData {
+    pub fn new(world: Matrix4<f32>, color: [f32; 4]) -> InstanceData {
+        InstanceData { world: world.into(), color }
+    }
+}
+
 pub fn compute_triangle(v0: ModelVertex, v1: ModelVertex, v2: ModelVertex) -> (Vertex, Vertex, Vertex) {
     let tangents_0 = compute_vertex_tangents(
         v0.normal,
@@ -71,10 +129,20 @@ pub fn compute_vertex_tangents(
     edge_2: Vector3<f32>,
     uv_edge_2: Vector2<f32>,
 ) -> (Vector3<f32>, Vector3<f32>) {
-    let tangent_basis = Matrix3::from_cols(uv_edge_1.extend(0.0), uv_edge_2.extend(0.0), Vector3{ x: 0.0, y: 0.0, z: 1.0 });
-    let world_basis = Matrix3::from_cols(edge_1, edge_2, normal);
-    let from_tangent_space = world_basis * tangent_basis.invert().unwrap();
-    let tangent_u = from_tangent_space * Vector3{ x: 1.0, y: 0.0, z: 0.0 };
-    let tangent_v = from_tangent_space * Vector3{ x: 0.0, y: 1.0, z: 0.0 };
+    // Solve [edge_1 edge_2] = [tangent_u tangent_v] * [uv_edge_1 uv_edge_2] directly rather than
+    // through Matrix3::invert, whose epsilon rejects the small UV triangles of subdivided meshes.
+    let det = uv_edge_1.x * uv_edge_2.y - uv_edge_2.x * uv_edge_1.y;
+    if det == 0.0 || !det.is_finite() {
+        // No UV mapping to follow; any frame around the normal will do.
+        let tangent_u = if normal.x.abs() < 0.9 {
+            normal.cross(Vector3::unit_x())
+        } else {
+            normal.cross(Vector3::unit_y())
+        }.normalize();
+        let tangent_v = normal.cross(tangent_u).normalize();
+        return (tangent_u, tangent_v);
+    }
+    let tangent_u = (edge_1 * uv_edge_2.y - edge_2 * uv_edge_1.y) / det;
+    let tangent_v = (edge_2 * uv_edge_1.x - edge_1 * uv_edge_2.x) / det;
     (tangent_u, tangent_v)
 }
\ No newline at end of file
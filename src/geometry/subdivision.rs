@@ -0,0 +1,588 @@
+use std::collections::HashMap;
+use std::f32;
+
+use cgmath::*;
+
+use geometry::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SubdivisionScheme {
+    /// Catmull-Clark, for quad-dominant meshes. Produces an all-quad mesh.
+    CatmullClark,
+
+    /// Loop, for triangle meshes. Produces an all-triangle mesh.
+    Loop,
+}
+
+/// A polygon mesh with shared vertices, suitable for subdivision.
+///
+/// Positions and UVs are indexed separately so that UV seams don't split the
+/// surface: a corner of a face refers to one position and one UV.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolyMesh {
+    /// Vertex positions.
+    pub positions: Vec<Point3<f32>>,
+
+    /// Vertex texture coordinates.
+    pub uvs: Vec<Point2<f32>>,
+
+    /// Faces, as indices into `positions`.
+    pub faces: Vec<Vec<usize>>,
+
+    /// Faces, as indices into `uvs`. Must have the same shape as `faces`.
+    pub face_uvs: Vec<Vec<usize>>,
+
+    /// Crease sharpness of edges, keyed by position indices in ascending order.
+    /// A sharpness of 1.0 or more keeps the edge sharp for that many levels.
+    pub creases: HashMap<(usize, usize), f32>,
+}
+
+impl PolyMesh {
+    pub fn new() -> PolyMesh {
+        PolyMesh {
+            positions: vec![],
+            uvs: vec![],
+            faces: vec![],
+            face_uvs: vec![],
+            creases: HashMap::new(),
+        }
+    }
+
+    /// Marks the edge between two positions as a crease. Use `f32::INFINITY`
+    /// for an edge that should stay sharp at every level.
+    pub fn set_crease(&mut self, a: usize, b: usize, sharpness: f32) {
+        if sharpness > 0.0 {
+            self.creases.insert(edge_key(a, b), sharpness);
+        } else {
+            self.creases.remove(&edge_key(a, b));
+        }
+    }
+
+    pub fn subdivide(&self, scheme: SubdivisionScheme, levels: usize) -> PolyMesh {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = match scheme {
+                SubdivisionScheme::CatmullClark => mesh.catmull_clark(),
+                SubdivisionScheme::Loop => mesh.loop_subdivide(),
+            };
+        }
+        mesh
+    }
+
+    /// Applies one level of Catmull-Clark subdivision.
+    pub fn catmull_clark(&self) -> PolyMesh {
+        let positions: Vec<Vector3<f32>> = self.positions.iter().map(|p| p.to_vec()).collect();
+        let (positions, faces, creases) = catmull_clark_channel(&positions, &self.faces, &self.creases);
+
+        let (uvs, face_uvs) = self.welded_uvs();
+        let (uvs, face_uvs, _) = catmull_clark_channel(&uvs, &face_uvs, &HashMap::new());
+
+        PolyMesh {
+            positions: positions.into_iter().map(Point3::from_vec).collect(),
+            uvs: uvs.into_iter().map(Point2::from_vec).collect(),
+            faces,
+            face_uvs,
+            creases,
+        }
+    }
+
+    /// Applies one level of Loop subdivision. Faces with more than three
+    /// corners are triangulated first, see `triangulate`.
+    pub fn loop_subdivide(&self) -> PolyMesh {
+        if self.faces.iter().any(|face| face.len() != 3) {
+            return self.triangulate().loop_subdivide();
+        }
+
+        let positions: Vec<Vector3<f32>> = self.positions.iter().map(|p| p.to_vec()).collect();
+        let (positions, faces, creases) = loop_channel(&positions, &self.faces, &self.creases);
+
+        let (uvs, face_uvs) = self.welded_uvs();
+        let (uvs, face_uvs, _) = loop_channel(&uvs, &face_uvs, &HashMap::new());
+
+        PolyMesh {
+            positions: positions.into_iter().map(Point3::from_vec).collect(),
+            uvs: uvs.into_iter().map(Point2::from_vec).collect(),
+            faces,
+            face_uvs,
+            creases,
+        }
+    }
+
+    /// Splits every face into a fan of triangles around its first corner.
+    /// Creases stay on the original edges; the new diagonals are smooth.
+    pub fn triangulate(&self) -> PolyMesh {
+        let fan = |faces: &[Vec<usize>]| -> Vec<Vec<usize>> {
+            faces.iter()
+                .flat_map(|face| (1..face.len().saturating_sub(1)).map(move |i| vec![face[0], face[i], face[i + 1]]))
+                .collect()
+        };
+        PolyMesh {
+            positions: self.positions.clone(),
+            uvs: self.uvs.clone(),
+            faces: fan(&self.faces),
+            face_uvs: fan(&self.face_uvs),
+            creases: self.creases.clone(),
+        }
+    }
+
+    /// Smooth per-position normals, averaged from the faces around each position.
+    pub fn normals(&self) -> Vec<Vector3<f32>> {
+        let mut normals = vec![Vector3::zero(); self.positions.len()];
+        for face in &self.faces {
+            let normal = self.face_normal(face);
+            for &v in face {
+                normals[v] += normal;
+            }
+        }
+        normals.into_iter().map(|n| {
+            if n.magnitude2() > 0.0 { n.normalize() } else { n }
+        }).collect()
+    }
+
+    /// Triangulates the mesh and computes tangents, like `ObjModel::vertices`.
+    pub fn vertices(&self) -> Vec<Vertex> {
+        let normals = self.normals();
+        let model_vertex = |face: usize, corner: usize| {
+            let v = self.faces[face][corner];
+            ModelVertex {
+                position: self.positions[v],
+                normal: normals[v],
+                uv: self.uvs[self.face_uvs[face][corner]],
+            }
+        };
+
+        let mut verts = vec![];
+        for (i, face) in self.faces.iter().enumerate() {
+            for corner in 1..(face.len() - 1) {
+                let tri = compute_triangle(
+                    model_vertex(i, 0),
+                    model_vertex(i, corner),
+                    model_vertex(i, corner + 1),
+                );
+                verts.push(tri.0);
+                verts.push(tri.1);
+                verts.push(tri.2);
+            }
+        }
+        verts
+    }
+
+    // OBJ exporters share texture coordinates between unrelated faces whenever
+    // the values happen to match. Giving each (position, UV) pair its own index
+    // makes UV islands disconnected, so seams subdivide as boundaries.
+    fn welded_uvs(&self) -> (Vec<Vector2<f32>>, Vec<Vec<usize>>) {
+        let mut indices = HashMap::new();
+        let mut uvs = vec![];
+        let face_uvs = self.faces.iter().zip(self.face_uvs.iter()).map(|(face, face_uv)| {
+            face.iter().zip(face_uv.iter()).map(|(&v, &uv)| {
+                *indices.entry((v, uv)).or_insert_with(|| {
+                    uvs.push(self.uvs[uv].to_vec());
+                    uvs.len() - 1
+                })
+            }).collect()
+        }).collect();
+        (uvs, face_uvs)
+    }
+
+    // Area-weighted normal of a polygon, using Newell's method.
+    fn face_normal(&self, face: &[usize]) -> Vector3<f32> {
+        let mut normal = Vector3::zero();
+        for i in 0..face.len() {
+            let a = self.positions[face[i]];
+            let b = self.positions[face[(i + 1) % face.len()]];
+            normal.x += (a.y - b.y) * (a.z + b.z);
+            normal.y += (a.z - b.z) * (a.x + b.x);
+            normal.z += (a.x - b.x) * (a.y + b.y);
+        }
+        normal
+    }
+}
+
+type Creases = HashMap<(usize, usize), f32>;
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// Edge and adjacency information for one channel (positions or UVs) of a mesh.
+struct Topology {
+    edges: Vec<(usize, usize)>,
+    edge_indices: HashMap<(usize, usize), usize>,
+    edge_faces: Vec<Vec<usize>>,
+    vertex_edges: Vec<Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    fn new(vertex_count: usize, faces: &[Vec<usize>]) -> Topology {
+        let mut topology = Topology {
+            edges: vec![],
+            edge_indices: HashMap::new(),
+            edge_faces: vec![],
+            vertex_edges: vec![vec![]; vertex_count],
+            vertex_faces: vec![vec![]; vertex_count],
+        };
+        for (f, face) in faces.iter().enumerate() {
+            for i in 0..face.len() {
+                let a = face[i];
+                let b = face[(i + 1) % face.len()];
+                let key = edge_key(a, b);
+                let edge = match topology.edge_indices.get(&key) {
+                    Some(&edge) => edge,
+                    None => {
+                        let edge = topology.edges.len();
+                        topology.edges.push(key);
+                        topology.edge_indices.insert(key, edge);
+                        topology.edge_faces.push(vec![]);
+                        topology.vertex_edges[key.0].push(edge);
+                        topology.vertex_edges[key.1].push(edge);
+                        edge
+                    },
+                };
+                topology.edge_faces[edge].push(f);
+                topology.vertex_faces[a].push(f);
+            }
+        }
+        topology
+    }
+
+    fn edge(&self, a: usize, b: usize) -> usize {
+        self.edge_indices[&edge_key(a, b)]
+    }
+
+    fn is_boundary(&self, edge: usize) -> bool {
+        self.edge_faces[edge].len() != 2
+    }
+
+    /// Boundary edges are treated as infinitely sharp creases.
+    fn sharpness(&self, edge: usize, creases: &Creases) -> f32 {
+        if self.is_boundary(edge) {
+            f32::INFINITY
+        } else {
+            *creases.get(&self.edges[edge]).unwrap_or(&0.0)
+        }
+    }
+
+    fn other_vertex(&self, edge: usize, v: usize) -> usize {
+        let (a, b) = self.edges[edge];
+        if a == v { b } else { a }
+    }
+
+    /// Creases of the child edges, which lose one level of sharpness.
+    fn child_creases(&self, creases: &Creases, vertex_count: usize) -> Creases {
+        let mut child_creases = HashMap::new();
+        for (e, &(a, b)) in self.edges.iter().enumerate() {
+            if self.is_boundary(e) {
+                continue;
+            }
+            let sharpness = self.sharpness(e, creases) - 1.0;
+            if sharpness > 0.0 {
+                child_creases.insert(edge_key(a, vertex_count + e), sharpness);
+                child_creases.insert(edge_key(b, vertex_count + e), sharpness);
+            }
+        }
+        child_creases
+    }
+
+    /// Applies the crease rules to a vertex: a smooth vertex keeps `smooth`,
+    /// a crease vertex (two sharp edges) uses `crease(a, b)`, and a corner
+    /// (three or more sharp edges, or a boundary vertex with a single face)
+    /// stays in place. Fractional sharpness blends between the smooth and
+    /// sharp results.
+    fn vertex_rule<V, F, G>(&self, v: usize, values: &[V], creases: &Creases, smooth: F, crease: G) -> V
+        where V: VectorSpace<Scalar = f32>,
+              F: Fn() -> V,
+              G: Fn(V, V) -> V {
+        let sharp_edges: Vec<usize> = self.vertex_edges[v].iter()
+            .cloned()
+            .filter(|&e| self.sharpness(e, creases) > 0.0)
+            .collect();
+        if sharp_edges.len() < 2 {
+            return smooth();
+        }
+
+        let sharp = if sharp_edges.len() == 2 && self.vertex_faces[v].len() > 1 {
+            crease(
+                values[self.other_vertex(sharp_edges[0], v)],
+                values[self.other_vertex(sharp_edges[1], v)],
+            )
+        } else {
+            values[v]
+        };
+
+        let sharpness = sharp_edges.iter()
+            .map(|&e| self.sharpness(e, creases))
+            .fold(0.0, |sum, s| sum + s) / sharp_edges.len() as f32;
+        if sharpness >= 1.0 {
+            sharp
+        } else {
+            smooth() * (1.0 - sharpness) + sharp * sharpness
+        }
+    }
+}
+
+fn average<V, I>(values: I) -> V
+    where V: VectorSpace<Scalar = f32>,
+          I: Iterator<Item = V> {
+    let mut sum = V::zero();
+    let mut count = 0;
+    for value in values {
+        sum = sum + value;
+        count += 1;
+    }
+    if count > 0 { sum / count as f32 } else { sum }
+}
+
+/// One level of Catmull-Clark over a single channel. New vertices are laid out
+/// as [vertex points, edge points, face points], so channels that share face
+/// shapes stay aligned.
+fn catmull_clark_channel<V>(values: &[V], faces: &[Vec<usize>], creases: &Creases) -> (Vec<V>, Vec<Vec<usize>>, Creases)
+    where V: VectorSpace<Scalar = f32> {
+    let topology = Topology::new(values.len(), faces);
+
+    let face_points: Vec<V> = faces.iter()
+        .map(|face| average(face.iter().map(|&v| values[v])))
+        .collect();
+
+    let edge_points: Vec<V> = topology.edges.iter().enumerate().map(|(e, &(a, b))| {
+        let midpoint = (values[a] + values[b]) / 2.0;
+        let sharpness = topology.sharpness(e, creases);
+        if sharpness >= 1.0 {
+            return midpoint;
+        }
+        let f = &topology.edge_faces[e];
+        let smooth = (values[a] + values[b] + face_points[f[0]] + face_points[f[1]]) / 4.0;
+        smooth * (1.0 - sharpness) + midpoint * sharpness
+    }).collect();
+
+    let vertex_points: Vec<V> = (0..values.len()).map(|v| {
+        let valence = topology.vertex_edges[v].len();
+        if valence == 0 {
+            return values[v];
+        }
+        topology.vertex_rule(v, values, creases, || {
+            let n = valence as f32;
+            let q = average(topology.vertex_faces[v].iter().map(|&f| face_points[f]));
+            let r = average(topology.vertex_edges[v].iter().map(|&e| {
+                let (a, b) = topology.edges[e];
+                (values[a] + values[b]) / 2.0
+            }));
+            (q + r * 2.0 + values[v] * (n - 3.0)) / n
+        }, |a, b| {
+            (values[v] * 6.0 + a + b) / 8.0
+        })
+    }).collect();
+
+    let edge_offset = values.len();
+    let face_offset = edge_offset + topology.edges.len();
+    let mut new_faces = vec![];
+    for (f, face) in faces.iter().enumerate() {
+        let n = face.len();
+        for i in 0..n {
+            let prev = face[(i + n - 1) % n];
+            let next = face[(i + 1) % n];
+            new_faces.push(vec![
+                face[i],
+                edge_offset + topology.edge(face[i], next),
+                face_offset + f,
+                edge_offset + topology.edge(prev, face[i]),
+            ]);
+        }
+    }
+
+    let new_creases = topology.child_creases(creases, edge_offset);
+
+    let mut new_values = vertex_points;
+    new_values.extend(edge_points);
+    new_values.extend(face_points);
+    (new_values, new_faces, new_creases)
+}
+
+/// One level of Loop subdivision over a single channel. New vertices are laid
+/// out as [vertex points, edge points].
+fn loop_channel<V>(values: &[V], faces: &[Vec<usize>], creases: &Creases) -> (Vec<V>, Vec<Vec<usize>>, Creases)
+    where V: VectorSpace<Scalar = f32> {
+    let topology = Topology::new(values.len(), faces);
+
+    let edge_points: Vec<V> = topology.edges.iter().enumerate().map(|(e, &(a, b))| {
+        let midpoint = (values[a] + values[b]) / 2.0;
+        let sharpness = topology.sharpness(e, creases);
+        if sharpness >= 1.0 {
+            return midpoint;
+        }
+        let opposite = topology.edge_faces[e].iter().map(|&f| {
+            let face = &faces[f];
+            values[*face.iter().find(|&&v| v != a && v != b).unwrap()]
+        });
+        let smooth = (values[a] + values[b]) * (3.0 / 8.0) + opposite.fold(V::zero(), |sum, v| sum + v) / 8.0;
+        smooth * (1.0 - sharpness) + midpoint * sharpness
+    }).collect();
+
+    let vertex_points: Vec<V> = (0..values.len()).map(|v| {
+        let valence = topology.vertex_edges[v].len();
+        if valence == 0 {
+            return values[v];
+        }
+        topology.vertex_rule(v, values, creases, || {
+            let n = valence as f32;
+            let c = 3.0 / 8.0 + (2.0 * f32::consts::PI / n).cos() / 4.0;
+            let beta = (5.0 / 8.0 - c * c) / n;
+            let neighbors = topology.vertex_edges[v].iter()
+                .map(|&e| values[topology.other_vertex(e, v)])
+                .fold(V::zero(), |sum, value| sum + value);
+            values[v] * (1.0 - n * beta) + neighbors * beta
+        }, |a, b| {
+            values[v] * (3.0 / 4.0) + (a + b) / 8.0
+        })
+    }).collect();
+
+    let edge_offset = values.len();
+    let mut new_faces = vec![];
+    for face in faces {
+        let (a, b, c) = (face[0], face[1], face[2]);
+        let ab = edge_offset + topology.edge(a, b);
+        let bc = edge_offset + topology.edge(b, c);
+        let ca = edge_offset + topology.edge(c, a);
+        new_faces.push(vec![a, ab, ca]);
+        new_faces.push(vec![b, bc, ab]);
+        new_faces.push(vec![c, ca, bc]);
+        new_faces.push(vec![ab, bc, ca]);
+    }
+
+    let new_creases = topology.child_creases(creases, edge_offset);
+
+    let mut new_values = vertex_points;
+    new_values.extend(edge_points);
+    (new_values, new_faces, new_creases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> PolyMesh {
+        let mut mesh = PolyMesh::new();
+        for &(x, y, z) in &[
+            (-1.0, -1.0, -1.0), (1.0, -1.0, -1.0), (1.0, 1.0, -1.0), (-1.0, 1.0, -1.0),
+            (-1.0, -1.0, 1.0), (1.0, -1.0, 1.0), (1.0, 1.0, 1.0), (-1.0, 1.0, 1.0),
+        ] {
+            mesh.positions.push(Point3::new(x, y, z));
+        }
+        mesh.uvs = vec![
+            Point2::new(0.0, 0.0), Point2::new(1.0, 0.0), Point2::new(1.0, 1.0), Point2::new(0.0, 1.0),
+        ];
+        mesh.faces = vec![
+            vec![0, 3, 2, 1], vec![4, 5, 6, 7], vec![0, 1, 5, 4],
+            vec![2, 3, 7, 6], vec![0, 4, 7, 3], vec![1, 2, 6, 5],
+        ];
+        mesh.face_uvs = vec![vec![0, 1, 2, 3]; 6];
+        mesh
+    }
+
+    fn tetrahedron() -> PolyMesh {
+        let mut mesh = PolyMesh::new();
+        mesh.positions = vec![
+            Point3::new(1.0, 1.0, 1.0), Point3::new(1.0, -1.0, -1.0),
+            Point3::new(-1.0, 1.0, -1.0), Point3::new(-1.0, -1.0, 1.0),
+        ];
+        mesh.uvs = vec![Point2::new(0.0, 0.0), Point2::new(1.0, 0.0), Point2::new(0.0, 1.0)];
+        mesh.faces = vec![vec![0, 1, 2], vec![0, 3, 1], vec![0, 2, 3], vec![1, 3, 2]];
+        mesh.face_uvs = vec![vec![0, 1, 2]; 4];
+        mesh
+    }
+
+    fn assert_close(actual: Point3<f32>, expected: Point3<f32>) {
+        assert!((actual - expected).magnitude() < 1e-5, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_catmull_clark_cube() {
+        let mesh = cube().catmull_clark();
+        assert_eq!(mesh.positions.len(), 8 + 12 + 6);
+        assert_eq!(mesh.faces.len(), 24);
+        assert!(mesh.faces.iter().all(|face| face.len() == 4));
+        assert_eq!(mesh.face_uvs.len(), 24);
+
+        // Corners of a cube move to 5/9 of their distance from the center.
+        assert_close(mesh.positions[6], Point3::new(5.0 / 9.0, 5.0 / 9.0, 5.0 / 9.0));
+        // Edge points end up at 3/4.
+        assert_close(mesh.positions[8], Point3::new(-0.75, 0.0, -0.75));
+    }
+
+    #[test]
+    fn test_catmull_clark_creases() {
+        let mut mesh = cube();
+        for &(a, b) in &[(0, 1), (1, 2), (2, 3), (3, 0)] {
+            mesh.set_crease(a, b, f32::INFINITY);
+        }
+        let subdivided = mesh.subdivide(SubdivisionScheme::CatmullClark, 2);
+        // The border of the creased face stays in its plane.
+        assert_eq!(subdivided.creases.len(), 16);
+        for &(a, b) in subdivided.creases.keys() {
+            assert!((subdivided.positions[a].z + 1.0).abs() < 1e-6);
+            assert!((subdivided.positions[b].z + 1.0).abs() < 1e-6);
+        }
+
+        let mut semi_sharp = cube();
+        semi_sharp.set_crease(0, 1, 1.0);
+        let subdivided = semi_sharp.subdivide(SubdivisionScheme::CatmullClark, 2);
+        assert!(subdivided.creases.is_empty());
+    }
+
+    #[test]
+    fn test_catmull_clark_uvs() {
+        // Every face is its own UV island covering the unit square, so its
+        // corners stay put and its edges stay straight.
+        let mesh = cube().subdivide(SubdivisionScheme::CatmullClark, 2);
+        for face in &mesh.face_uvs {
+            for &uv in face {
+                let uv = mesh.uvs[uv];
+                assert!(uv.x >= 0.0 && uv.x <= 1.0 && uv.y >= 0.0 && uv.y <= 1.0);
+            }
+        }
+
+        let mesh = cube().catmull_clark();
+        assert_eq!(mesh.uvs[mesh.face_uvs[0][0]], Point2::new(0.0, 0.0));
+        assert_eq!(mesh.uvs[mesh.face_uvs[0][1]], Point2::new(0.5, 0.0));
+        assert_eq!(mesh.uvs[mesh.face_uvs[0][2]], Point2::new(0.5, 0.5));
+    }
+
+    #[test]
+    fn test_loop_tetrahedron() {
+        let mesh = tetrahedron().subdivide(SubdivisionScheme::Loop, 2);
+        assert_eq!(mesh.faces.len(), 4 * 16);
+        assert!(mesh.faces.iter().all(|face| face.len() == 3));
+        // V - E + F = 2 for the closed surface.
+        assert_eq!(mesh.positions.len() as i32 - 96 + 64, 2);
+
+        // Symmetric valence-3 vertices shrink towards the center uniformly.
+        let first = tetrahedron().loop_subdivide();
+        assert_close(first.positions[0], Point3::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn test_loop_quads() {
+        let triangles = cube().triangulate();
+        assert_eq!(triangles.faces.len(), 12);
+        assert_eq!(triangles.face_uvs.len(), 12);
+        assert_eq!(triangles.faces[0], vec![0, 3, 2]);
+        assert_eq!(triangles.faces[1], vec![0, 2, 1]);
+
+        // Quads are triangulated rather than rejected.
+        let mesh = cube().subdivide(SubdivisionScheme::Loop, 1);
+        assert_eq!(mesh.faces.len(), 12 * 4);
+        assert!(mesh.faces.iter().all(|face| face.len() == 3));
+        assert_eq!(mesh, triangles.loop_subdivide());
+    }
+
+    #[test]
+    fn test_vertices() {
+        let verts = cube().subdivide(SubdivisionScheme::CatmullClark, 1).vertices();
+        assert_eq!(verts.len(), 24 * 6);
+        for vertex in verts {
+            let position = Vector3::new(vertex.position.0, vertex.position.1, vertex.position.2);
+            let normal = Vector3::new(vertex.normal.0, vertex.normal.1, vertex.normal.2);
+            assert!(position.dot(normal) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,556 @@
+use std::borrow::Cow;
+use std::error;
+use std::ffi::CStr;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::vec;
+
+use glsl_to_spirv;
+use glsl_to_spirv::ShaderType;
+use vulkano::OomError;
+use vulkano::descriptor::descriptor::DescriptorBufferDesc;
+use vulkano::descriptor::descriptor::DescriptorDesc;
+use vulkano::descriptor::descriptor::DescriptorDescTy;
+use vulkano::descriptor::descriptor::DescriptorImageDesc;
+use vulkano::descriptor::descriptor::DescriptorImageDescArray;
+use vulkano::descriptor::descriptor::DescriptorImageDescDimensions;
+use vulkano::descriptor::descriptor::ShaderStages;
+use vulkano::descriptor::pipeline_layout::PipelineLayoutDesc;
+use vulkano::descriptor::pipeline_layout::PipelineLayoutDescPcRange;
+use vulkano::device::Device;
+use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::pipeline::GraphicsPipelineCreationError;
+use vulkano::pipeline::shader::GraphicsEntryPoint;
+use vulkano::pipeline::shader::GraphicsShaderType;
+use vulkano::pipeline::shader::ShaderInterfaceDef;
+use vulkano::pipeline::shader::ShaderInterfaceDefEntry;
+use vulkano::pipeline::shader::ShaderModule;
+use vulkano::pipeline::shader::SpecializationConstants;
+use vulkano::pipeline::shader::SpecializationMapEntry;
+use vulkano::pipeline::vertex;
+
+use geometry::Vertex;
+use graphics::Graphics;
+use loader;
+use pipeline::PipelineBuilder;
+use spirv;
+use spirv::Descriptor;
+use spirv::DescriptorKind;
+use spirv::ImageDimensions;
+use spirv::ImageType;
+use spirv::InterfaceVariable;
+use spirv::ReflectError;
+use spirv::SpecializationConstant;
+
+/// Error that can happen when loading a shader or building a pipeline from it.
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(io::Error),
+    /// The GLSL didn't compile, with the compiler's output.
+    Compile(String),
+    Reflect(ReflectError),
+    ModuleCreation(OomError),
+    /// The shaders don't fit together or with the vertex type, e.g. after
+    /// renaming a vertex attribute.
+    PipelineCreation(GraphicsPipelineCreationError),
+    /// Specialization constants that don't match the ones the shader declares.
+    Specialization(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderError::Io(ref err) => write!(f, "failed to read shader: {}", err),
+            ShaderError::Compile(ref output) => write!(f, "failed to compile shader:\n{}", output),
+            ShaderError::Reflect(ref err) => write!(f, "{}", err),
+            ShaderError::ModuleCreation(ref err) => write!(f, "failed to create shader module: {}", err),
+            ShaderError::PipelineCreation(ref err) => write!(f, "failed to create pipeline: {}", err),
+            ShaderError::Specialization(ref reason) => write!(f, "invalid specialization constants: {}", reason),
+        }
+    }
+}
+
+impl error::Error for ShaderError {
+    fn description(&self) -> &str {
+        "failed to load shader"
+    }
+}
+
+impl From<io::Error> for ShaderError {
+    fn from(err: io::Error) -> ShaderError { ShaderError::Io(err) }
+}
+
+impl From<ReflectError> for ShaderError {
+    fn from(err: ReflectError) -> ShaderError { ShaderError::Reflect(err) }
+}
+
+impl From<OomError> for ShaderError {
+    fn from(err: OomError) -> ShaderError { ShaderError::ModuleCreation(err) }
+}
+
+impl From<GraphicsPipelineCreationError> for ShaderError {
+    fn from(err: GraphicsPipelineCreationError) -> ShaderError { ShaderError::PipelineCreation(err) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl ShaderStage {
+    /// The stage of a `.vert` or `.frag` file.
+    pub fn from_path(path: &str) -> Option<ShaderStage> {
+        let lowercase = path.to_lowercase();
+        if lowercase.ends_with(".vert") {
+            Some(ShaderStage::Vertex)
+        } else if lowercase.ends_with(".frag") {
+            Some(ShaderStage::Fragment)
+        } else {
+            None
+        }
+    }
+
+    fn shader_type(&self) -> ShaderType {
+        match *self {
+            ShaderStage::Vertex => ShaderType::Vertex,
+            ShaderStage::Fragment => ShaderType::Fragment,
+        }
+    }
+
+    fn graphics_shader_type(&self) -> GraphicsShaderType {
+        match *self {
+            ShaderStage::Vertex => GraphicsShaderType::Vertex,
+            ShaderStage::Fragment => GraphicsShaderType::Fragment,
+        }
+    }
+
+    fn stages(&self) -> ShaderStages {
+        match *self {
+            ShaderStage::Vertex => ShaderStages { vertex: true, .. ShaderStages::none() },
+            ShaderStage::Fragment => ShaderStages { fragment: true, .. ShaderStages::none() },
+        }
+    }
+}
+
+/// A shader compiled from GLSL at runtime, with the interface vulkano needs
+/// reflected from the SPIR-V, so it can be used like the shaders generated by
+/// `#[derive(VulkanoShader)]`.
+pub struct Shader {
+    module: Arc<ShaderModule>,
+    pub stage: ShaderStage,
+    pub reflection: spirv::Reflection,
+}
+
+impl Shader {
+    pub fn compile(device: Arc<Device>, source: &str, stage: ShaderStage) -> Result<Shader, ShaderError> {
+        let (bytes, reflection) = compile_spirv(source, stage)?;
+        // The SPIR-V comes straight from the compiler.
+        let module = unsafe { ShaderModule::new(device, &bytes)? };
+        Ok(Shader { module, stage, reflection })
+    }
+
+    pub fn load(device: Arc<Device>, path: &str, stage: ShaderStage) -> Result<Shader, ShaderError> {
+        let mut source = String::new();
+        File::open(path)?.read_to_string(&mut source)?;
+        Shader::compile(device, &source, stage)
+    }
+
+    /// The `main` entry point, to pass to `Graphics::create_pipeline`.
+    pub fn entry_point(&self) -> GraphicsEntryPoint<(), Interface, Interface, Layout> {
+        self.entry_point_unchecked()
+    }
+
+    /// Like `entry_point`, for `Graphics::create_specialized_pipeline`. Fails
+    /// unless every constant `S` describes is declared by the shader, with the
+    /// same size. Constants `S` leaves out keep their default values.
+    pub fn specialized_entry_point<S>(&self) -> Result<GraphicsEntryPoint<S, Interface, Interface, Layout>, ShaderError>
+        where S: SpecializationConstants {
+        check_specialization(&self.reflection.specialization_constants, S::descriptors(), mem::size_of::<S>())
+            .map_err(ShaderError::Specialization)?;
+        Ok(self.entry_point_unchecked())
+    }
+
+    fn entry_point_unchecked<S>(&self) -> GraphicsEntryPoint<S, Interface, Interface, Layout>
+        where S: SpecializationConstants {
+        let name = CStr::from_bytes_with_nul(b"main\0").unwrap();
+        let stages = self.stage.stages();
+        unsafe {
+            self.module.graphics_entry_point(
+                name,
+                Interface { variables: self.reflection.inputs.clone() },
+                Interface { variables: self.reflection.outputs.clone() },
+                Layout {
+                    descriptors: self.reflection.descriptors.clone(),
+                    push_constants_size: self.reflection.push_constants_size,
+                    stages,
+                },
+                self.stage.graphics_shader_type())
+        }
+    }
+}
+
+// Compiles GLSL to SPIR-V, and reflects its interface.
+fn compile_spirv(source: &str, stage: ShaderStage) -> Result<(Vec<u8>, spirv::Reflection), ShaderError> {
+    let mut output = glsl_to_spirv::compile(source, stage.shader_type()).map_err(ShaderError::Compile)?;
+    let mut bytes = vec![];
+    output.read_to_end(&mut bytes)?;
+    let words: Vec<u32> = bytes.chunks(4)
+        .map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+        .collect();
+    let reflection = spirv::reflect(&words)?;
+    Ok((bytes, reflection))
+}
+
+// Checks the entries of a `SpecializationConstants` implementation, whose
+// struct is `size` bytes, against the constants declared by a shader.
+fn check_specialization(declared: &[SpecializationConstant], entries: &[SpecializationMapEntry], size: usize) -> Result<(), String> {
+    for entry in entries {
+        let constant = declared.iter().find(|constant| constant.id == entry.constant_id)
+            .ok_or_else(|| format!("the shader has no constant with id {}", entry.constant_id))?;
+        if entry.size != constant.size {
+            return Err(format!("{} (id {}) is {} bytes, not {}", constant.name, constant.id, constant.size, entry.size));
+        }
+        if entry.offset as usize + entry.size > size {
+            return Err(format!("{} (id {}) is outside of the constants", constant.name, constant.id));
+        }
+    }
+    Ok(())
+}
+
+/// Inputs or outputs of a `Shader`.
+#[derive(Clone, Debug)]
+pub struct Interface {
+    variables: Vec<InterfaceVariable>,
+}
+
+unsafe impl ShaderInterfaceDef for Interface {
+    type Iter = vec::IntoIter<ShaderInterfaceDefEntry>;
+
+    fn elements(&self) -> Self::Iter {
+        self.variables.iter().map(|variable| ShaderInterfaceDefEntry {
+            location: variable.location..variable.location + variable.locations,
+            format: variable.format,
+            name: Some(Cow::Owned(variable.name.clone())),
+        }).collect::<Vec<_>>().into_iter()
+    }
+}
+
+/// Descriptors and push constants of a `Shader`.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    descriptors: Vec<Descriptor>,
+    push_constants_size: usize,
+    stages: ShaderStages,
+}
+
+unsafe impl PipelineLayoutDesc for Layout {
+    fn num_sets(&self) -> usize {
+        self.descriptors.iter().map(|descriptor| descriptor.set as usize + 1).max().unwrap_or(0)
+    }
+
+    fn num_bindings_in_set(&self, set: usize) -> Option<usize> {
+        if set >= self.num_sets() {
+            return None;
+        }
+        Some(self.descriptors.iter()
+            .filter(|descriptor| descriptor.set as usize == set)
+            .map(|descriptor| descriptor.binding as usize + 1)
+            .max().unwrap_or(0))
+    }
+
+    fn descriptor(&self, set: usize, binding: usize) -> Option<DescriptorDesc> {
+        self.descriptors.iter()
+            .find(|descriptor| descriptor.set as usize == set && descriptor.binding as usize == binding)
+            .map(|descriptor| descriptor_desc(descriptor, self.stages))
+    }
+
+    fn num_push_constants_ranges(&self) -> usize {
+        if self.push_constants_size > 0 { 1 } else { 0 }
+    }
+
+    fn push_constants_range(&self, num: usize) -> Option<PipelineLayoutDescPcRange> {
+        if num == 0 && self.push_constants_size > 0 {
+            Some(PipelineLayoutDescPcRange { offset: 0, size: self.push_constants_size, stages: self.stages })
+        } else {
+            None
+        }
+    }
+}
+
+fn descriptor_desc(descriptor: &Descriptor, stages: ShaderStages) -> DescriptorDesc {
+    let image = |image: ImageType, sampled: bool| DescriptorImageDesc {
+        sampled,
+        dimensions: match image.dimensions {
+            ImageDimensions::Dim1d => DescriptorImageDescDimensions::OneDimensional,
+            ImageDimensions::Dim2d => DescriptorImageDescDimensions::TwoDimensional,
+            ImageDimensions::Dim3d => DescriptorImageDescDimensions::ThreeDimensional,
+            ImageDimensions::Cube => DescriptorImageDescDimensions::Cube,
+        },
+        format: None,
+        multisampled: image.multisampled,
+        array_layers: if image.arrayed {
+            DescriptorImageDescArray::Arrayed { max_layers: None }
+        } else {
+            DescriptorImageDescArray::NonArrayed
+        },
+    };
+    let ty = match descriptor.kind {
+        DescriptorKind::UniformBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc { dynamic: Some(false), storage: false }),
+        DescriptorKind::StorageBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc { dynamic: Some(false), storage: true }),
+        DescriptorKind::Sampler => DescriptorDescTy::Sampler,
+        DescriptorKind::CombinedImageSampler(ty) => DescriptorDescTy::CombinedImageSampler(image(ty, true)),
+        DescriptorKind::SampledImage(ty) => DescriptorDescTy::Image(image(ty, true)),
+        DescriptorKind::StorageImage(ty) => DescriptorDescTy::Image(image(ty, false)),
+        DescriptorKind::UniformTexelBuffer => DescriptorDescTy::TexelBuffer { storage: false, format: None },
+        DescriptorKind::StorageTexelBuffer => DescriptorDescTy::TexelBuffer { storage: true, format: None },
+        DescriptorKind::InputAttachment { multisampled } => DescriptorDescTy::InputAttachment {
+            multisampled,
+            array_layers: DescriptorImageDescArray::NonArrayed,
+        },
+    };
+    DescriptorDesc { ty, array_count: descriptor.count, stages, readonly: descriptor.readonly }
+}
+
+type BuildPipeline = Box<Fn(&Graphics, &PipelineBuilder, &str, &str) -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, ShaderError> + Send>;
+
+/// A pipeline built from a vertex and a fragment shader file, rebuilt when
+/// either file changes. Errors while rebuilding are logged, and the last good
+/// pipeline kept.
+pub struct HotPipeline {
+    vertex_path: String,
+    fragment_path: String,
+    modified: [Option<SystemTime>; 2],
+    build: BuildPipeline,
+    builder: PipelineBuilder,
+    pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    generation: u32,
+    poll_interval: Duration,
+    last_poll: Instant,
+}
+
+impl HotPipeline {
+    /// Builds a pipeline like `Graphics::create_pipeline` from GLSL files.
+    pub fn new(graphics: &Graphics, vertex_path: &str, fragment_path: &str) -> Result<HotPipeline, ShaderError> {
+        HotPipeline::for_vertex::<Vertex>(graphics, vertex_path, fragment_path)
+    }
+
+    /// Like `new`, for vertex types other than `Vertex`.
+    pub fn for_vertex<T>(graphics: &Graphics, vertex_path: &str, fragment_path: &str) -> Result<HotPipeline, ShaderError>
+        where T: vertex::Vertex {
+        HotPipeline::with_builder::<T>(graphics, PipelineBuilder::new(), vertex_path, fragment_path)
+    }
+
+    /// Like `for_vertex`, with the fixed function state configured by `builder`.
+    pub fn with_builder<T>(graphics: &Graphics, builder: PipelineBuilder, vertex_path: &str, fragment_path: &str) -> Result<HotPipeline, ShaderError>
+        where T: vertex::Vertex {
+        HotPipeline::from_build(graphics, Box::new(build_pipeline::<T>), builder, vertex_path, fragment_path)
+    }
+
+    /// Like `with_builder`, with values for the specialization constants of
+    /// the vertex and fragment shaders. They are checked against the shaders
+    /// on every rebuild, see `Shader::specialized_entry_point`.
+    pub fn specialized<T, VS, FS>(graphics: &Graphics, builder: PipelineBuilder, vertex_path: &str, fragment_path: &str, vs_constants: VS, fs_constants: FS)
+                                  -> Result<HotPipeline, ShaderError>
+        where T: vertex::Vertex,
+              VS: SpecializationConstants + Clone + Send + 'static,
+              FS: SpecializationConstants + Clone + Send + 'static {
+        let build = move |graphics: &Graphics, builder: &PipelineBuilder, vertex_path: &str, fragment_path: &str| {
+            build_specialized_pipeline::<T, VS, FS>(graphics, builder, vertex_path, fragment_path, vs_constants.clone(), fs_constants.clone())
+        };
+        HotPipeline::from_build(graphics, Box::new(build), builder, vertex_path, fragment_path)
+    }
+
+    /// Like `with_builder`, for instanced drawing with per-instance data of
+    /// `I` in a second vertex buffer, see `PipelineBuilder::build_instanced`.
+    pub fn instanced<T, I>(graphics: &Graphics, builder: PipelineBuilder, vertex_path: &str, fragment_path: &str) -> Result<HotPipeline, ShaderError>
+        where T: vertex::Vertex,
+              I: vertex::Vertex {
+        HotPipeline::from_build(graphics, Box::new(build_instanced_pipeline::<T, I>), builder, vertex_path, fragment_path)
+    }
+
+    fn from_build(graphics: &Graphics, build: BuildPipeline, builder: PipelineBuilder, vertex_path: &str, fragment_path: &str) -> Result<HotPipeline, ShaderError> {
+        let modified = [loader::modified(vertex_path), loader::modified(fragment_path)];
+        let pipeline = build(graphics, &builder, vertex_path, fragment_path)?;
+        Ok(HotPipeline {
+            vertex_path: vertex_path.to_string(),
+            fragment_path: fragment_path.to_string(),
+            modified,
+            build,
+            builder,
+            pipeline,
+            generation: 0,
+            poll_interval: Duration::from_millis(loader::DEFAULT_POLL_INTERVAL_MS),
+            last_poll: Instant::now(),
+        })
+    }
+
+    pub fn pipeline(&self) -> Arc<GraphicsPipelineAbstract + Send + Sync> {
+        self.pipeline.clone()
+    }
+
+    /// Counts the times the pipeline was rebuilt.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Call between frames. Rebuilds the pipeline if a shader file changed,
+    /// compiling on the calling thread. Returns whether the pipeline was
+    /// replaced, in which case descriptor sets and pools created from the old
+    /// one must be recreated.
+    pub fn update(&mut self, graphics: &Graphics) -> bool {
+        if self.last_poll.elapsed() < self.poll_interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        let modified = [loader::modified(&self.vertex_path), loader::modified(&self.fragment_path)];
+        if modified == self.modified || modified.iter().any(|modified| modified.is_none()) {
+            return false;
+        }
+        self.modified = modified;
+
+        info!("Rebuilding pipeline from {} and {}", self.vertex_path, self.fragment_path);
+        match (self.build)(graphics, &self.builder, &self.vertex_path, &self.fragment_path) {
+            Ok(pipeline) => {
+                self.pipeline = pipeline;
+                self.generation += 1;
+                true
+            },
+            Err(err) => {
+                error!("Failed to rebuild pipeline from {} and {}, keeping the last good version: {}",
+                       self.vertex_path, self.fragment_path, err);
+                false
+            },
+        }
+    }
+}
+
+fn build_pipeline<T>(graphics: &Graphics, builder: &PipelineBuilder, vertex_path: &str, fragment_path: &str) -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, ShaderError>
+    where T: vertex::Vertex {
+    let vs = Shader::load(graphics.device.clone(), vertex_path, ShaderStage::Vertex)?;
+    let fs = Shader::load(graphics.device.clone(), fragment_path, ShaderStage::Fragment)?;
+    Ok(builder.build::<T, _, _>(graphics, vs.entry_point(), fs.entry_point())?)
+}
+
+fn build_specialized_pipeline<T, VS, FS>(graphics: &Graphics, builder: &PipelineBuilder, vertex_path: &str, fragment_path: &str, vs_constants: VS, fs_constants: FS)
+                                         -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, ShaderError>
+    where T: vertex::Vertex,
+          VS: SpecializationConstants,
+          FS: SpecializationConstants {
+    let vs = Shader::load(graphics.device.clone(), vertex_path, ShaderStage::Vertex)?;
+    let fs = Shader::load(graphics.device.clone(), fragment_path, ShaderStage::Fragment)?;
+    Ok(builder.build_specialized::<T, _, _>(graphics, vs.specialized_entry_point::<VS>()?, vs_constants, fs.specialized_entry_point::<FS>()?, fs_constants)?)
+}
+
+fn build_instanced_pipeline<T, I>(graphics: &Graphics, builder: &PipelineBuilder, vertex_path: &str, fragment_path: &str) -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, ShaderError>
+    where T: vertex::Vertex,
+          I: vertex::Vertex {
+    let vs = Shader::load(graphics.device.clone(), vertex_path, ShaderStage::Vertex)?;
+    let fs = Shader::load(graphics.device.clone(), fragment_path, ShaderStage::Fragment)?;
+    Ok(builder.build_instanced::<T, I, _, _>(graphics, vs.entry_point(), fs.entry_point())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use vulkano::format::Format;
+    use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+    use vulkano::pipeline::vertex::VertexDefinition;
+
+    use geometry::InstanceData;
+
+    fn reflect_file(path: &str, stage: ShaderStage) -> spirv::Reflection {
+        let source = fs::read_to_string(path).unwrap();
+        compile_spirv(&source, stage).unwrap().1
+    }
+
+    #[test]
+    fn test_stage_from_path() {
+        assert_eq!(ShaderStage::from_path("shaders/simple.vert"), Some(ShaderStage::Vertex));
+        assert_eq!(ShaderStage::from_path("SIMPLE.FRAG"), Some(ShaderStage::Fragment));
+        assert_eq!(ShaderStage::from_path("simple.glsl"), None);
+    }
+
+    #[test]
+    fn test_layout() {
+        let descriptor = |set, binding, kind| Descriptor { name: String::new(), set, binding, kind, count: 1, readonly: true };
+        let layout = Layout {
+            descriptors: vec![descriptor(0, 0, DescriptorKind::Sampler), descriptor(2, 1, DescriptorKind::UniformBuffer)],
+            push_constants_size: 0,
+            stages: ShaderStage::Fragment.stages(),
+        };
+        assert_eq!(layout.num_sets(), 3);
+        assert_eq!(layout.num_bindings_in_set(0), Some(1));
+        assert_eq!(layout.num_bindings_in_set(1), Some(0));
+        assert_eq!(layout.num_bindings_in_set(2), Some(2));
+        assert_eq!(layout.num_bindings_in_set(3), None);
+        assert!(layout.descriptor(2, 0).is_none());
+        assert!(layout.descriptor(2, 1).is_some());
+        assert_eq!(layout.num_push_constants_ranges(), 0);
+    }
+
+    #[test]
+    fn test_check_specialization() {
+        let declared = vec![
+            SpecializationConstant { name: "enabled".to_string(), id: 0, size: 4 },
+            SpecializationConstant { name: "scale".to_string(), id: 3, size: 8 },
+        ];
+        let entry = |constant_id, offset, size| SpecializationMapEntry { constant_id, offset, size };
+        assert_eq!(check_specialization(&declared, &[], 0), Ok(()));
+        assert_eq!(check_specialization(&declared, &[entry(3, 0, 8), entry(0, 8, 4)], 12), Ok(()));
+        // Leaving constants out keeps their defaults.
+        assert_eq!(check_specialization(&declared, &[entry(0, 0, 4)], 4), Ok(()));
+        assert_eq!(check_specialization(&declared, &[entry(1, 0, 4)], 4),
+                   Err("the shader has no constant with id 1".to_string()));
+        assert_eq!(check_specialization(&declared, &[entry(3, 0, 4)], 4),
+                   Err("scale (id 3) is 8 bytes, not 4".to_string()));
+        assert_eq!(check_specialization(&declared, &[entry(0, 4, 4)], 4),
+                   Err("enabled (id 0) is outside of the constants".to_string()));
+    }
+
+    #[test]
+    fn test_instance_data_matches_instanced_shader() {
+        let interface = Interface { variables: reflect_file("resources/shaders/instanced.vert", ShaderStage::Vertex).inputs };
+        let (buffers, attributes) = OneVertexOneInstanceDefinition::<Vertex, InstanceData>::new().definition(&interface).unwrap();
+        let buffers: Vec<_> = buffers.map(|(binding, stride, _)| (binding, stride)).collect();
+        assert_eq!(buffers, vec![(0, mem::size_of::<Vertex>()), (1, 80)]);
+
+        let instance_attributes: Vec<_> = attributes
+            .filter(|&(_, binding, _)| binding == 1)
+            .map(|(location, _, info)| (location, info.offset, info.format))
+            .collect();
+        // The world matrix takes a location per column.
+        assert_eq!(instance_attributes, vec![
+            (5, 0, Format::R32G32B32A32Sfloat),
+            (6, 16, Format::R32G32B32A32Sfloat),
+            (7, 32, Format::R32G32B32A32Sfloat),
+            (8, 48, Format::R32G32B32A32Sfloat),
+            (9, 64, Format::R32G32B32A32Sfloat),
+        ]);
+    }
+
+    #[test]
+    fn test_instanced_storage_shader_interface() {
+        let reflection = reflect_file("resources/shaders/instanced_storage.vert", ShaderStage::Vertex);
+        let inputs: Vec<_> = reflection.inputs.iter().map(|input| input.name.as_str()).collect();
+        assert_eq!(inputs, vec!["position", "normal"]);
+        let descriptors: Vec<_> = reflection.descriptors.iter()
+            .map(|descriptor| (descriptor.set, descriptor.binding, descriptor.kind, descriptor.readonly))
+            .collect();
+        // glslang marks the members of the `readonly` instance block, not the block.
+        assert_eq!(descriptors, vec![(0, 0, DescriptorKind::UniformBuffer, true), (0, 1, DescriptorKind::StorageBuffer, true)]);
+    }
+}
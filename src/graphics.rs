@@ -1,9 +1,26 @@
-
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
+use vulkano::OomError;
 use vulkano::device::Device;
+use vulkano::device::DeviceCreationError;
+use vulkano::device::DeviceExtensions;
 use vulkano::device::Queue;
+use vulkano::instance::ApplicationInfo;
+use vulkano::instance::Features;
+use vulkano::instance::Instance;
+use vulkano::instance::InstanceCreationError;
+use vulkano::instance::InstanceExtensions;
+use vulkano::instance::debug::DebugCallbackCreationError;
 use winit::Window;
+use vulkano::swapchain::CapabilitiesError;
+use vulkano::swapchain::ColorSpace as SurfaceColorSpace;
+use vulkano::swapchain::PresentMode;
 use vulkano::swapchain::Surface;
+use vulkano::swapchain::SwapchainCreationError;
 use winit::EventsLoop;
 use vulkano_win;
 use vulkano;
@@ -11,186 +28,719 @@ use winit;
 use vulkano_win::VkSurfaceBuild;
 use vulkano::swapchain::Swapchain;
 use vulkano::image::SwapchainImage;
+use vulkano::image::ImageCreationError;
 use vulkano::instance::PhysicalDevice;
+use vulkano::instance::QueueFamily;
+use camera::SharedViewport;
+use debug::DebugReport;
+use debug::ValidationMessage;
+use debug::available_validation_layer;
+use device::DeviceCandidate;
+use device::DeviceOverride;
+use device::clamp_sample_count;
+use device::select_device;
+use geometry::InstanceData;
 use geometry::Vertex;
 use obj::ObjModel;
+use geometry::subdivision::SubdivisionScheme;
+use morph::MorphMesh;
+use pipeline::PipelineBuilder;
+use pipeline_cache;
+use pipeline_cache::PipelineCacheError;
+use pipeline_cache::PipelineCacheFile;
+use vulkano::pipeline::cache::PipelineCache;
+use vulkano::image::Dimensions;
+use vulkano::image::ImageLayout;
+use vulkano::image::ImageUsage;
 use vulkano::image::ImageViewAccess;
+use vulkano::image::ImmutableImage;
+use vulkano::image::MipmapsCount;
+use vulkano::image::StorageImage;
+use vulkano::command_buffer::CommandBuffer;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::format::AcceptsPixels;
+use compressed::TextureError;
+use cubemap;
+use shaders;
+use texture;
+use texture::Channel;
+use texture::ColorSpace;
+use texture::ImageData;
+use texture::LoadedImage;
+use upload::DecodedTexture;
+use upload::Uploader;
 use vulkano::sync::GpuFuture;
+use vulkano::sampler::Filter;
 use vulkano::sampler::Sampler;
+use vulkano::sampler::SamplerCreationError;
 use image;
+use vulkano::framebuffer::FramebufferCreationError;
 use vulkano::framebuffer::RenderPassAbstract;
+use vulkano::framebuffer::RenderPassCreationError;
 use vulkano::framebuffer::FramebufferAbstract;
+use vulkano::pipeline::ComputePipeline;
 use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::pipeline::GraphicsPipelineCreationError;
 use vulkano::pipeline::shader::GraphicsEntryPointAbstract;
 use vulkano::image::AttachmentImage;
 use vulkano::format::D16Unorm;
+use vulkano::format::Format;
+use vulkano::format::R8G8B8A8Srgb;
+use vulkano::command_buffer::DrawIndirectCommand;
 use vulkano::command_buffer::DynamicState;
+use vulkano::format::ClearValue;
 use vulkano::pipeline::viewport::Viewport;
 use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::buffer::ImmutableBuffer;
+use vulkano::buffer::CpuBufferPool;
+
+/// Color format of the offscreen target used by headless `Graphics`. Like the
+/// swapchain, it is sRGB, so shaders write linear colors.
+pub type OffscreenFormat = R8G8B8A8Srgb;
+
+/// Error that can happen when creating `Graphics`.
+#[derive(Debug)]
+pub enum GraphicsError {
+    ValidationUnavailable,
+    InstanceCreation(InstanceCreationError),
+    DebugCallbackCreation(DebugCallbackCreationError),
+    NoDevice,
+    WindowCreation(vulkano_win::CreationError),
+    NoQueueFamily,
+    NoSurfaceFormat,
+    UnsupportedSampleCount(u32),
+    DeviceCreation(DeviceCreationError),
+    SurfaceCapabilities(CapabilitiesError),
+    SwapchainCreation(SwapchainCreationError),
+    RenderPassCreation(RenderPassCreationError),
+    ImageCreation(ImageCreationError),
+    FramebufferCreation(FramebufferCreationError),
+    SamplerCreation(SamplerCreationError),
+    PipelineCacheCreation(OomError),
+}
+
+impl fmt::Display for GraphicsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GraphicsError::ValidationUnavailable => write!(f, "no validation layer is installed"),
+            GraphicsError::InstanceCreation(ref err) => write!(f, "failed to create Vulkan instance: {}", err),
+            GraphicsError::DebugCallbackCreation(ref err) => write!(f, "failed to create debug callback: {}", err),
+            GraphicsError::NoDevice => write!(f, "no suitable Vulkan device available"),
+            GraphicsError::WindowCreation(ref err) => write!(f, "failed to create window: {}", err),
+            GraphicsError::NoQueueFamily => write!(f, "couldn't find a graphical queue family"),
+            GraphicsError::NoSurfaceFormat => write!(f, "the surface supports no formats"),
+            GraphicsError::UnsupportedSampleCount(samples) => write!(f, "unsupported sample count: {}", samples),
+            GraphicsError::DeviceCreation(ref err) => write!(f, "failed to create device: {}", err),
+            GraphicsError::SurfaceCapabilities(ref err) => write!(f, "failed to get surface capabilities: {}", err),
+            GraphicsError::SwapchainCreation(ref err) => write!(f, "failed to create swapchain: {}", err),
+            GraphicsError::RenderPassCreation(ref err) => write!(f, "failed to create render pass: {}", err),
+            GraphicsError::ImageCreation(ref err) => write!(f, "failed to create image: {}", err),
+            GraphicsError::FramebufferCreation(ref err) => write!(f, "failed to create framebuffer: {}", err),
+            GraphicsError::SamplerCreation(ref err) => write!(f, "failed to create sampler: {}", err),
+            GraphicsError::PipelineCacheCreation(ref err) => write!(f, "failed to create pipeline cache: {}", err),
+        }
+    }
+}
+
+impl error::Error for GraphicsError {
+    fn description(&self) -> &str {
+        "failed to create graphics"
+    }
+}
+
+impl From<InstanceCreationError> for GraphicsError {
+    fn from(err: InstanceCreationError) -> GraphicsError { GraphicsError::InstanceCreation(err) }
+}
+
+impl From<DebugCallbackCreationError> for GraphicsError {
+    fn from(err: DebugCallbackCreationError) -> GraphicsError { GraphicsError::DebugCallbackCreation(err) }
+}
+
+impl From<vulkano_win::CreationError> for GraphicsError {
+    fn from(err: vulkano_win::CreationError) -> GraphicsError { GraphicsError::WindowCreation(err) }
+}
+
+impl From<DeviceCreationError> for GraphicsError {
+    fn from(err: DeviceCreationError) -> GraphicsError { GraphicsError::DeviceCreation(err) }
+}
+
+impl From<CapabilitiesError> for GraphicsError {
+    fn from(err: CapabilitiesError) -> GraphicsError { GraphicsError::SurfaceCapabilities(err) }
+}
+
+impl From<SwapchainCreationError> for GraphicsError {
+    fn from(err: SwapchainCreationError) -> GraphicsError { GraphicsError::SwapchainCreation(err) }
+}
+
+impl From<RenderPassCreationError> for GraphicsError {
+    fn from(err: RenderPassCreationError) -> GraphicsError { GraphicsError::RenderPassCreation(err) }
+}
+
+impl From<ImageCreationError> for GraphicsError {
+    fn from(err: ImageCreationError) -> GraphicsError { GraphicsError::ImageCreation(err) }
+}
+
+impl From<FramebufferCreationError> for GraphicsError {
+    fn from(err: FramebufferCreationError) -> GraphicsError { GraphicsError::FramebufferCreation(err) }
+}
+
+impl From<SamplerCreationError> for GraphicsError {
+    fn from(err: SamplerCreationError) -> GraphicsError { GraphicsError::SamplerCreation(err) }
+}
+
+impl From<OomError> for GraphicsError {
+    fn from(err: OomError) -> GraphicsError { GraphicsError::PipelineCacheCreation(err) }
+}
+
+/// Configures and creates `Graphics`.
+pub struct GraphicsBuilder {
+    application_name: Option<String>,
+    title: String,
+    dimensions: Option<[u32; 2]>,
+    headless: bool,
+    present_mode: PresentMode,
+    surface_formats: Vec<Format>,
+    features: Features,
+    extensions: DeviceExtensions,
+    device: Option<DeviceOverride>,
+    samples: u32,
+    anisotropy: f32,
+    validation: bool,
+    pipeline_cache: bool,
+    pipeline_cache_dir: Option<PathBuf>,
+}
+
+impl GraphicsBuilder {
+    pub fn new() -> GraphicsBuilder {
+        GraphicsBuilder {
+            application_name: None,
+            title: "render".to_string(),
+            dimensions: None,
+            headless: false,
+            present_mode: PresentMode::Fifo,
+            surface_formats: vec![Format::B8G8R8A8Srgb, Format::R8G8B8A8Srgb, Format::A8B8G8R8SrgbPack32],
+            features: Features::none(),
+            extensions: DeviceExtensions::none(),
+            device: None,
+            samples: 1,
+            anisotropy: 1.0,
+            validation: false,
+            pipeline_cache: true,
+            pipeline_cache_dir: None,
+        }
+    }
+
+    /// Application name reported to the Vulkan driver.
+    pub fn application_name(mut self, name: &str) -> GraphicsBuilder {
+        self.application_name = Some(name.to_string());
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> GraphicsBuilder {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Size of the window, or of the offscreen target when headless.
+    pub fn dimensions(mut self, dimensions: [u32; 2]) -> GraphicsBuilder {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Renders into an offscreen target instead of a window.
+    pub fn headless(mut self) -> GraphicsBuilder {
+        self.headless = true;
+        self
+    }
+
+    /// Present mode to use if the surface supports it. Falls back to `Fifo`,
+    /// which is always supported.
+    pub fn present_mode(mut self, present_mode: PresentMode) -> GraphicsBuilder {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Swapchain formats in order of preference, by default the common sRGB
+    /// formats. If the surface supports none of them in the sRGB color space,
+    /// the first format it supports is used.
+    pub fn surface_formats(mut self, formats: Vec<Format>) -> GraphicsBuilder {
+        self.surface_formats = formats;
+        self
+    }
+
+    /// Features the device must support. Only these are enabled, along with
+    /// `sampler_anisotropy` when `anisotropy` asks for it and the device has it.
+    /// Compressed textures need the matching `texture_compression_*` feature.
+    pub fn features(mut self, features: Features) -> GraphicsBuilder {
+        self.features = features;
+        self
+    }
+
+    /// Extensions to enable on the device, in addition to the swapchain extension.
+    pub fn extensions(mut self, extensions: DeviceExtensions) -> GraphicsBuilder {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Forces the choice of device instead of picking the highest scoring one.
+    /// Takes precedence over the `RENDER_DEVICE` environment variable.
+    pub fn device(mut self, device: DeviceOverride) -> GraphicsBuilder {
+        self.device = Some(device);
+        self
+    }
+
+    /// Number of samples per pixel: 1, 2, 4, 8 and so on. Clamped to what the
+    /// device supports for color and depth attachments.
+    pub fn samples(mut self, samples: u32) -> GraphicsBuilder {
+        self.samples = samples;
+        self
+    }
+
+    /// Maximum anisotropy of `Graphics::sampler`, clamped to what the device
+    /// supports. 1.0 disables anisotropic filtering.
+    pub fn anisotropy(mut self, anisotropy: f32) -> GraphicsBuilder {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    /// Enables the validation layer and routes its messages into `log`. They
+    /// are also collected, see `Graphics::take_validation_messages`.
+    pub fn validation(mut self, validation: bool) -> GraphicsBuilder {
+        self.validation = validation;
+        self
+    }
+
+    /// Whether `Graphics::pipeline_cache` is loaded from and saved to disk.
+    /// On by default.
+    pub fn pipeline_cache(mut self, pipeline_cache: bool) -> GraphicsBuilder {
+        self.pipeline_cache = pipeline_cache;
+        self
+    }
+
+    /// Directory of the pipeline cache file. Defaults to
+    /// `pipeline_cache::cache_dir` of the application name, or of the title.
+    pub fn pipeline_cache_dir<P: AsRef<Path>>(mut self, dir: P) -> GraphicsBuilder {
+        self.pipeline_cache_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn build(self) -> Result<Graphics, GraphicsError> {
+        if self.samples == 0 {
+            return Err(GraphicsError::UnsupportedSampleCount(self.samples));
+        }
+
+        let mut instance_extensions = if self.headless {
+            InstanceExtensions::none()
+        } else {
+            vulkano_win::required_extensions()
+        };
+        let app_info = ApplicationInfo {
+            application_name: self.application_name.as_ref().map(|name| Cow::from(name.as_str())),
+            application_version: None,
+            engine_name: Some(Cow::from("render")),
+            engine_version: None,
+        };
+        let mut layers = vec![];
+        if self.validation {
+            layers.push(available_validation_layer().ok_or(GraphicsError::ValidationUnavailable)?);
+            instance_extensions.ext_debug_report = true;
+        }
+        let instance = Instance::new(Some(&app_info), &instance_extensions, layers.iter())?;
+        let debug_report = if self.validation {
+            Some(DebugReport::new(&instance)?)
+        } else {
+            None
+        };
+
+        if self.headless {
+            let physical = self.choose_device(&instance, None)?;
+            let mut graphics = self.build_headless(physical)?;
+            graphics.debug_report = debug_report;
+            return Ok(graphics);
+        }
+
+        let events_loop = winit::EventsLoop::new();
+        let mut window = winit::WindowBuilder::new().with_title(self.title.clone());
+        if let Some(dimensions) = self.dimensions {
+            window = window.with_dimensions(winit::dpi::LogicalSize::new(dimensions[0] as f64, dimensions[1] as f64));
+        }
+        let surface = window.build_vk_surface(&events_loop, instance.clone())?;
+
+        let physical = self.choose_device(&instance, Some(&surface))?;
+
+        let queue = physical.queue_families().find(|&q| {
+            q.supports_graphics() && surface.is_supported(q).unwrap_or(false)
+        }).ok_or(GraphicsError::NoQueueFamily)?;
+
+        let device_ext = DeviceExtensions {
+            khr_swapchain: true,
+            .. self.extensions.clone()
+        };
+
+        let (device, queue, transfer_queue) = self.create_device(physical, queue, &device_ext)?;
+
+        let caps = surface.capabilities(physical)?;
+        let dimensions = caps.current_extent.unwrap_or(self.dimensions.unwrap_or([1024, 768]));
+
+        let usage = caps.supported_usage_flags;
+        let format = self.surface_formats.iter()
+            .find(|&&format| caps.supported_formats.iter().any(|&(supported, color_space)| {
+                supported == format && color_space == SurfaceColorSpace::SrgbNonLinear
+            }))
+            .cloned()
+            .or_else(|| caps.supported_formats.first().map(|&(format, _)| format))
+            .ok_or(GraphicsError::NoSurfaceFormat)?;
+        if !texture::is_srgb(format) {
+            warn!("Using non-sRGB swapchain format {:?}, colors won't be gamma correct", format);
+        }
+        let alpha = caps.supported_composite_alpha.iter().next().unwrap();
+        let present_mode = if caps.present_modes.supports(self.present_mode) {
+            self.present_mode
+        } else {
+            PresentMode::Fifo
+        };
+
+        let (swapchain, images) = Swapchain::new(
+            device.clone(),
+            surface.clone(),
+            caps.min_image_count,
+            format,
+            dimensions,
+            1,
+            usage,
+            &queue,
+            vulkano::swapchain::SurfaceTransform::Identity,
+            alpha,
+            present_mode,
+            true,
+            None)?;
+
+        let samples = self.sample_count(physical);
+        let mut graphics = Graphics::with_target(device, queue, transfer_queue, format, dimensions, samples, self.anisotropy)?;
+        graphics.open_pipeline_cache(self.cache_location())?;
+        graphics.swapchain = Some(swapchain);
+        graphics.images = images;
+        graphics.surface = Some(surface);
+        graphics.events_loop = Some(events_loop);
+        graphics.debug_report = debug_report;
+        graphics.recreate_framebuffers()?;
+        Ok(graphics)
+    }
+
+    // Scores every device and picks the best usable one, unless overridden by
+    // `device` or the `RENDER_DEVICE` environment variable.
+    fn choose_device<'a>(&self, instance: &'a Arc<Instance>, surface: Option<&Arc<Surface<Window>>>) -> Result<PhysicalDevice<'a>, GraphicsError> {
+        let extensions = DeviceExtensions {
+            khr_swapchain: surface.is_some(),
+            .. self.extensions.clone()
+        };
+        let candidates: Vec<DeviceCandidate> = PhysicalDevice::enumerate(instance)
+            .map(|physical| DeviceCandidate::from_physical(physical, &self.features, &extensions, surface))
+            .collect();
+        let device_override = self.device.clone().or_else(DeviceOverride::from_env);
+        let index = select_device(&candidates, device_override.as_ref()).ok_or(GraphicsError::NoDevice)?;
+        let physical = PhysicalDevice::from_index(instance, index).unwrap();
+        info!("Using device: {} (type: {:?})", physical.name(), physical.ty());
+        Ok(physical)
+    }
+
+    // Where the pipeline cache is kept, if anywhere.
+    fn cache_location(&self) -> Option<PathBuf> {
+        if !self.pipeline_cache {
+            return None;
+        }
+        self.pipeline_cache_dir.clone().or_else(|| {
+            pipeline_cache::cache_dir(self.application_name.as_ref().unwrap_or(&self.title))
+        })
+    }
+
+    fn sample_count(&self, physical: PhysicalDevice) -> u32 {
+        let limits = physical.limits();
+        let supported = limits.framebuffer_color_sample_counts() & limits.framebuffer_depth_sample_counts();
+        let samples = clamp_sample_count(self.samples, supported);
+        if samples != self.samples {
+            warn!("{} samples per pixel requested, using {}", self.samples, samples);
+        }
+        samples
+    }
+
+    // Creates the device with a queue from `family` and, if the device has a
+    // dedicated transfer family, a queue from that too for background uploads.
+    fn create_device(&self, physical: PhysicalDevice, family: QueueFamily, extensions: &DeviceExtensions) -> Result<(Arc<Device>, Arc<Queue>, Arc<Queue>), DeviceCreationError> {
+        let transfer_family = physical.queue_families().find(|&q| {
+            q.explicitly_supports_transfers() && !q.supports_graphics() && !q.supports_compute()
+        });
+        let families = Some((family, 0.5)).into_iter().chain(transfer_family.map(|q| (q, 0.5)));
+        let features = Features {
+            sampler_anisotropy: self.features.sampler_anisotropy
+                || (self.anisotropy > 1.0 && physical.supported_features().sampler_anisotropy),
+            .. self.features.clone()
+        };
+        let (device, mut queues) = Device::new(physical, &features, extensions, families)?;
+        let queue = queues.next().unwrap();
+        let transfer_queue = queues.next().unwrap_or_else(|| queue.clone());
+        Ok((device, queue, transfer_queue))
+    }
+
+    fn build_headless(self, physical: PhysicalDevice) -> Result<Graphics, GraphicsError> {
+        let queue = physical.queue_families().find(|&q| q.supports_graphics())
+            .ok_or(GraphicsError::NoQueueFamily)?;
+
+        let (device, queue, transfer_queue) = self.create_device(physical, queue, &self.extensions)?;
+
+        let dimensions = self.dimensions.unwrap_or([1024, 768]);
+        let samples = self.sample_count(physical);
+        let mut graphics = Graphics::with_target(device, queue, transfer_queue, Format::R8G8B8A8Srgb, dimensions, samples, self.anisotropy)?;
+        graphics.open_pipeline_cache(self.cache_location())?;
+        graphics.recreate_offscreen_image()?;
+        graphics.recreate_framebuffers()?;
+        Ok(graphics)
+    }
+}
 
 pub struct Graphics {
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
-    pub swapchain: Arc<Swapchain<Window>>,
+    /// Queue for uploads from background threads, see `loader::AssetLoader`.
+    /// A queue of a dedicated transfer family if the device has one, `queue`
+    /// otherwise.
+    pub transfer_queue: Arc<Queue>,
+    // Windowed graphics present to a swapchain, headless graphics render into
+    // `offscreen_image`. Exactly one of the two is set.
+    pub swapchain: Option<Arc<Swapchain<Window>>>,
     pub images: Vec<Arc<SwapchainImage<Window>>>,
+    pub offscreen_image: Option<Arc<AttachmentImage<OffscreenFormat>>>,
     pub framebuffers: Vec<Arc<FramebufferAbstract + Send + Sync>>,
     // We only support a single pass for now.
     pub renderpass: Arc<RenderPassAbstract + Send + Sync>,
     pub dimensions: [u32; 2],
+    /// Follows `dimensions`, for scenes to keep their cameras' aspect ratios
+    /// in sync with, see `Scene::follow_viewport`.
+    pub viewport: SharedViewport,
     // Should we always have a depth buffer?
     pub depth_buffer: Arc<AttachmentImage<D16Unorm>>,
+    /// Samples per pixel. With more than one, we render into `msaa_color`
+    /// (and a multisampled depth buffer) and resolve into the swapchain or
+    /// offscreen image at the end of the pass.
+    pub samples: u32,
+    pub msaa_color: Option<Arc<AttachmentImage<Format>>>,
+    pub color_format: Format,
 
-    pub surface: Arc<Surface<Window>>,
-    pub events_loop: EventsLoop,
+    pub surface: Option<Arc<Surface<Window>>>,
+    pub events_loop: Option<EventsLoop>,
 
     pub dynamic_state: DynamicState,
 
     pub sampler: Arc<Sampler>,
 
+    /// Pipeline cache loaded from the directory configured on
+    /// `GraphicsBuilder`, and saved back there by `save_pipeline_cache` and
+    /// when the graphics are dropped. The pipeline builders of the vulkano
+    /// version in use don't take a cache, so pipelines created by this crate
+    /// don't go through it yet.
+    pub pipeline_cache: Arc<PipelineCache>,
+    pipeline_cache_file: Option<PipelineCacheFile>,
+
+    /// Set when validation is enabled.
+    pub debug_report: Option<DebugReport>,
+
     // Frame-specific fields
 
 }
 
 impl Graphics {
+    /// Creates windowed graphics with the default configuration, panicking on
+    /// failure. Use `GraphicsBuilder` to configure creation or handle errors.
     pub fn new() -> Graphics {
-        let extensions = vulkano_win::required_extensions();
-        let instance = vulkano::instance::Instance::new(None, &extensions, None)
-            .expect("failed to create instance");
-
-        let physical = vulkano::instance::PhysicalDevice::enumerate(&instance)
-            .next().expect("no device available");
-        println!("Using device: {} (type: {:?})", physical.name(), physical.ty());
-
-        let events_loop = winit::EventsLoop::new();
-        let surface = winit::WindowBuilder::new()
-            .build_vk_surface(&events_loop, instance.clone()).unwrap();
+        GraphicsBuilder::new().build().expect("failed to create graphics")
+    }
 
-        let dimensions;
+    /// Creates graphics without a window, surface or swapchain, rendering into
+    /// an offscreen color and depth target that can be read back with
+    /// `read_offscreen_image`. Works on software implementations such as lavapipe.
+    pub fn new_headless(dimensions: [u32; 2]) -> Graphics {
+        GraphicsBuilder::new().headless().dimensions(dimensions).build().expect("failed to create graphics")
+    }
 
-        let queue = physical.queue_families().find(|&q| {
-            q.supports_graphics() && surface.is_supported(q).unwrap_or(false)
-        }).expect("couldn't find a graphical queue family");
+    // Creates everything that doesn't depend on whether we render to a window.
+    // The caller still has to set up the color target and the framebuffers.
+    fn with_target(device: Arc<Device>, queue: Arc<Queue>, transfer_queue: Arc<Queue>, color_format: Format, dimensions: [u32; 2], samples: u32, anisotropy: f32) -> Result<Graphics, GraphicsError> {
+        let renderpass = Graphics::create_renderpass(device.clone(), color_format, samples)?;
+        let depth_buffer = Graphics::create_depth_buffer(device.clone(), dimensions, samples)?;
+        let msaa_color = Graphics::create_msaa_color(device.clone(), dimensions, color_format, samples)?;
 
-        let device_ext = vulkano::device::DeviceExtensions {
-            khr_swapchain: true,
-            .. vulkano::device::DeviceExtensions::none()
+        let dynamic_state = vulkano::command_buffer::DynamicState {
+            line_width: None,
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                depth_range: 0.0 .. 1.0,
+            }]),
+            scissors: None,
         };
 
-        let (device, mut queues) = vulkano::device::Device::new(
-            physical,
-            physical.supported_features(),
-            &device_ext,
-            [(queue, 0.5)].iter().cloned()).expect("failed to create device");
-        let queue = queues.next().unwrap();
+        let sampler = Graphics::new_sampler(device.clone(), anisotropy)?;
+        let pipeline_cache = PipelineCache::empty(device.clone())?;
 
-        let (swapchain, images) = {
-            let caps = surface.capabilities(physical)
-                .expect("failed to get surface capabilities");
+        Ok(Graphics {
+            device,
+            queue,
+            transfer_queue,
+            swapchain: None,
+            images: vec![],
+            offscreen_image: None,
+            framebuffers: vec![],
+            renderpass,
+            dimensions,
+            viewport: SharedViewport::new(dimensions),
+            surface: None,
+            events_loop: None,
+            depth_buffer,
+            samples,
+            msaa_color,
+            color_format,
+            dynamic_state,
+            sampler,
+            pipeline_cache,
+            pipeline_cache_file: None,
+            debug_report: None,
+        })
+    }
 
-            dimensions = caps.current_extent.unwrap_or([1024, 768]);
+    // Replaces the empty pipeline cache with the one saved in `dir`, before
+    // any pipelines are created.
+    fn open_pipeline_cache(&mut self, dir: Option<PathBuf>) -> Result<(), OomError> {
+        if let Some(dir) = dir {
+            let file = PipelineCacheFile::open(self.device.clone(), dir)?;
+            self.pipeline_cache = file.cache();
+            self.pipeline_cache_file = Some(file);
+        }
+        Ok(())
+    }
 
-            let usage = caps.supported_usage_flags;
-            let format = caps.supported_formats[0].0;
-            let alpha = caps.supported_composite_alpha.iter().next().unwrap();
+    /// Writes the pipeline cache to its file, if it has one. Dropping the
+    /// graphics does this too, but only logs failures.
+    pub fn save_pipeline_cache(&self) -> Result<(), PipelineCacheError> {
+        match self.pipeline_cache_file {
+            Some(ref file) => file.save(),
+            None => Ok(()),
+        }
+    }
 
-            vulkano::swapchain::Swapchain::new(
-                device.clone(),
-                surface.clone(),
-                caps.min_image_count,
-                format,
-                dimensions,
-                1,
-                usage,
-                &queue,
-                vulkano::swapchain::SurfaceTransform::Identity,
-                alpha,
-                vulkano::swapchain::PresentMode::Fifo,
-                true,
-                None).expect("failed to create swapchain")
-        };
+    fn create_renderpass(device: Arc<Device>, color_format: Format, samples: u32) -> Result<Arc<RenderPassAbstract + Send + Sync>, RenderPassCreationError> {
+        if samples == 1 {
+            return Ok(Arc::new(single_pass_renderpass!(device,
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: color_format,
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Clear,
+                        store: DontCare,
+                        format: Format::D16Unorm,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
+                }
+            )?));
+        }
 
-        let renderpass = Arc::new(
-            single_pass_renderpass!(device.clone(),
+        Ok(Arc::new(single_pass_renderpass!(device,
             attachments: {
                 color: {
                     load: Clear,
-                    store: Store,
-                    format: swapchain.format(),
-                    samples: 1,
+                    store: DontCare,
+                    format: color_format,
+                    samples: samples,
                 },
                 depth: {
                     load: Clear,
                     store: DontCare,
-                    format: vulkano::format::Format::D16Unorm,
+                    format: Format::D16Unorm,
+                    samples: samples,
+                },
+                resolve: {
+                    load: DontCare,
+                    store: Store,
+                    format: color_format,
                     samples: 1,
                 }
             },
             pass: {
                 color: [color],
-                depth_stencil: {depth}
+                depth_stencil: {depth},
+                resolve: [resolve]
             }
-        ).unwrap());
+        )?))
+    }
 
-        let depth_buffer = vulkano::image::attachment::AttachmentImage::transient(
-            device.clone(),
-            dimensions,
-            vulkano::format::D16Unorm,
-        ).unwrap();
-
-        let framebuffers = images.iter().map(|image| {
-            let f: Arc<FramebufferAbstract + Send + Sync> = Arc::new(
-                vulkano::framebuffer::Framebuffer::start(renderpass.clone())
-                    .add(image.clone()).unwrap()
-                    .add(depth_buffer.clone()).unwrap()
-                    .build().unwrap());
-            f
-        }).collect::<Vec<_>>();
+    fn create_depth_buffer(device: Arc<Device>, dimensions: [u32; 2], samples: u32) -> Result<Arc<AttachmentImage<D16Unorm>>, ImageCreationError> {
+        if samples == 1 {
+            AttachmentImage::transient(device, dimensions, D16Unorm)
+        } else {
+            AttachmentImage::transient_multisampled(device, dimensions, samples, D16Unorm)
+        }
+    }
 
-        let dynamic_state = vulkano::command_buffer::DynamicState {
-            line_width: None,
-            viewports: Some(vec![Viewport {
-                origin: [0.0, 0.0],
-                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-                depth_range: 0.0 .. 1.0,
-            }]),
-            scissors: None,
-        };
+    fn create_msaa_color(device: Arc<Device>, dimensions: [u32; 2], color_format: Format, samples: u32) -> Result<Option<Arc<AttachmentImage<Format>>>, ImageCreationError> {
+        if samples == 1 {
+            Ok(None)
+        } else {
+            Ok(Some(AttachmentImage::transient_multisampled(device, dimensions, samples, color_format)?))
+        }
+    }
 
-        let sampler = vulkano::sampler::Sampler::new(
-            device.clone(),
-            vulkano::sampler::Filter::Linear,
-            vulkano::sampler::Filter::Linear,
-            vulkano::sampler::MipmapMode::Nearest,
-            vulkano::sampler::SamplerAddressMode::Repeat,
-            vulkano::sampler::SamplerAddressMode::Repeat,
-            vulkano::sampler::SamplerAddressMode::Repeat,
-            0.0,
-            1.0,
-            0.0,
-            0.0).unwrap();
+    /// Clear values for the attachments of `framebuffers`, in order.
+    pub fn clear_values(&self, color: [f32; 4], depth: f32) -> Vec<ClearValue> {
+        let mut values = vec![color.into(), depth.into()];
+        if self.samples > 1 {
+            // The resolve attachment isn't cleared.
+            values.push(ClearValue::None);
+        }
+        values
+    }
 
-        Graphics {
-            device,
-            queue,
-            swapchain,
-            images,
-            framebuffers,
-            renderpass,
-            dimensions,
-            surface,
-            events_loop,
-            depth_buffer,
-            dynamic_state,
-            sampler,
+    /// Removes and returns the validation messages reported so far. Always
+    /// empty unless validation is enabled.
+    pub fn take_validation_messages(&self) -> Vec<ValidationMessage> {
+        match self.debug_report {
+            Some(ref debug_report) => debug_report.messages.take(),
+            None => vec![],
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.swapchain.is_none()
+    }
+
+    /// The swapchain of windowed graphics.
+    pub fn swapchain(&self) -> Arc<Swapchain<Window>> {
+        self.swapchain.clone().expect("headless graphics have no swapchain")
+    }
+
+    /// Polls window events. Headless graphics have none.
+    pub fn poll_events<F>(&mut self, callback: F) where F: FnMut(winit::Event) {
+        if let Some(ref mut events_loop) = self.events_loop {
+            events_loop.poll_events(callback);
         }
     }
 
     pub fn recreate_swapchain(&mut self) -> bool {
-        self.dimensions = self.surface.capabilities(self.physical_device())
+        let surface = match self.surface {
+            Some(ref surface) => surface.clone(),
+            None => return true,
+        };
+        self.dimensions = surface.capabilities(self.physical_device())
             .expect("failed to get surface capabilities")
             .current_extent.unwrap_or([1024, 768]);
 
-        let (new_swapchain, new_images) = match self.swapchain.recreate_with_dimension(self.dimensions) {
+        let (new_swapchain, new_images) = match self.swapchain().recreate_with_dimension(self.dimensions) {
             Ok(r) => r,
             Err(vulkano::swapchain::SwapchainCreationError::UnsupportedDimensions) => {
                 return false;
@@ -198,10 +748,29 @@ impl Graphics {
             Err(err) => panic!("{:?}", err)
         };
 
-        self.swapchain = new_swapchain;
+        self.swapchain = Some(new_swapchain);
         self.images = new_images;
 
-        self.depth_buffer = vulkano::image::attachment::AttachmentImage::transient(self.device.clone(), self.dimensions, vulkano::format::D16Unorm).unwrap();
+        self.recreate_size_dependent();
+        true
+    }
+
+    /// Changes the size of the offscreen target of headless graphics.
+    pub fn resize_offscreen(&mut self, dimensions: [u32; 2]) {
+        if !self.is_headless() {
+            panic!("windowed graphics are resized through recreate_swapchain");
+        }
+        self.dimensions = dimensions;
+        self.recreate_offscreen_image().expect("failed to create offscreen image");
+        self.recreate_size_dependent();
+    }
+
+    fn recreate_size_dependent(&mut self) {
+        self.viewport.set(self.dimensions);
+        self.depth_buffer = Graphics::create_depth_buffer(self.device.clone(), self.dimensions, self.samples)
+            .expect("failed to create depth buffer");
+        self.msaa_color = Graphics::create_msaa_color(self.device.clone(), self.dimensions, self.color_format, self.samples)
+            .expect("failed to create multisampled color buffer");
 
         self.dynamic_state.viewports = Some(vec![vulkano::pipeline::viewport::Viewport {
             origin: [0.0, 0.0],
@@ -209,61 +778,427 @@ impl Graphics {
             depth_range: 0.0 .. 1.0,
         }]);
 
-        self.recreate_framebuffers();
-        true
+        self.recreate_framebuffers().expect("failed to create framebuffers");
+    }
+
+    fn recreate_offscreen_image(&mut self) -> Result<(), ImageCreationError> {
+        self.offscreen_image = Some(vulkano::image::attachment::AttachmentImage::with_usage(
+            self.device.clone(),
+            self.dimensions,
+            R8G8B8A8Srgb,
+            vulkano::image::ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                .. vulkano::image::ImageUsage::none()
+            })?);
+        Ok(())
     }
 
-    fn recreate_framebuffers(&mut self) {
-        self.framebuffers = self.images.iter().map(|image| {
-            let f: Arc<FramebufferAbstract + Send + Sync> = Arc::new(
-                vulkano::framebuffer::Framebuffer::start(self.renderpass.clone())
-                    .add(image.clone()).unwrap()
-                    .add(self.depth_buffer.clone()).unwrap()
-                    .build().unwrap());
-            f
-        }).collect::<Vec<_>>();
+    fn recreate_framebuffers(&mut self) -> Result<(), FramebufferCreationError> {
+        let color_images: Vec<Arc<ImageViewAccess + Send + Sync>> = match self.offscreen_image {
+            Some(ref image) => vec![image.clone()],
+            None => self.images.iter().map(|image| image.clone() as Arc<ImageViewAccess + Send + Sync>).collect(),
+        };
+        let mut framebuffers = Vec::with_capacity(color_images.len());
+        for image in color_images {
+            let f: Arc<FramebufferAbstract + Send + Sync> = match self.msaa_color {
+                Some(ref msaa_color) => Arc::new(
+                    vulkano::framebuffer::Framebuffer::start(self.renderpass.clone())
+                        .add(msaa_color.clone())?
+                        .add(self.depth_buffer.clone())?
+                        .add(image)?
+                        .build()?),
+                None => Arc::new(
+                    vulkano::framebuffer::Framebuffer::start(self.renderpass.clone())
+                        .add(image)?
+                        .add(self.depth_buffer.clone())?
+                        .build()?),
+            };
+            framebuffers.push(f);
+        }
+        self.framebuffers = framebuffers;
+        Ok(())
+    }
+
+    /// Copies the offscreen target of headless graphics into an image, after
+    /// waiting for `future` (e.g. the submitted frame) to complete.
+    pub fn read_offscreen_image<F>(&self, future: F) -> image::RgbaImage where F: GpuFuture + 'static {
+        let offscreen_image = self.offscreen_image.clone().expect("only headless graphics have an offscreen image");
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            vulkano::buffer::BufferUsage {
+                transfer_destination: true,
+                .. vulkano::buffer::BufferUsage::none()
+            },
+            (0 .. self.dimensions[0] * self.dimensions[1] * 4).map(|_| 0u8)).expect("failed to create buffer");
+
+        let command_buffer = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family()).unwrap()
+            .copy_image_to_buffer(offscreen_image, buffer.clone()).unwrap()
+            .build().unwrap();
+
+        future
+            .then_execute(self.queue.clone(), command_buffer).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let data = buffer.read().unwrap().to_vec();
+        image::RgbaImage::from_raw(self.dimensions[0], self.dimensions[1], data).unwrap()
     }
 
     pub fn physical_device(&self) -> PhysicalDevice {
         self.device.physical_device()
     }
 
-    pub fn load_model(&self, filename: &str) -> Arc<CpuAccessibleBuffer<[Vertex]>> {
+    /// Loads an OBJ model into a device-local vertex buffer, see `load_vertex_buffer`.
+    pub fn load_model(&self, filename: &str) -> (Arc<ImmutableBuffer<[Vertex]>>, Box<GpuFuture>) {
         let model = ObjModel::from_file(filename);
-         CpuAccessibleBuffer::from_iter(
-             self.device.clone(),
-             vulkano::buffer::BufferUsage::all(),
-             model.vertices().iter().cloned()).expect("failed to create buffer")
-    }
-
-    pub fn load_texture(&self, filename: &str) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>) {
-        let image = image::open(filename).unwrap().to_rgba();
-        let image_width = image.width();
-        let image_height = image.height();
-        let image_data = image.into_raw().clone();
-        let (tex, tex_future) = vulkano::image::immutable::ImmutableImage::from_iter(
-            image_data.iter().cloned(),
-            vulkano::image::Dimensions::Dim2d { width: image_width, height: image_height },
-            vulkano::format::R8G8B8A8Unorm,
-            self.queue.clone()).unwrap();
+        self.load_vertex_buffer(model.vertices())
+    }
+
+    pub fn load_subdivided_model(&self, filename: &str, scheme: SubdivisionScheme, levels: usize) -> (Arc<ImmutableBuffer<[Vertex]>>, Box<GpuFuture>) {
+        let mesh = ObjModel::from_file(filename).poly_mesh().subdivide(scheme, levels);
+        self.load_vertex_buffer(mesh.vertices())
+    }
+
+    /// Uploads static vertices into a device-local buffer through a staging
+    /// buffer. The future completes once the copy is done. Vertices that
+    /// change every frame are better off in a `CpuBufferPool`.
+    pub fn load_vertex_buffer<T>(&self, vertices: Vec<T>) -> (Arc<ImmutableBuffer<[T]>>, Box<GpuFuture>)
+        where T: Send + Sync + 'static {
+        self.load_buffer(vertices, vulkano::buffer::BufferUsage::vertex_buffer())
+    }
+
+    /// Like `load_vertex_buffer`, for indices to draw with `draw_indexed`.
+    pub fn load_index_buffer(&self, indices: Vec<u32>) -> (Arc<ImmutableBuffer<[u32]>>, Box<GpuFuture>) {
+        self.load_buffer(indices, vulkano::buffer::BufferUsage::index_buffer())
+    }
+
+    fn load_buffer<T>(&self, data: Vec<T>, usage: vulkano::buffer::BufferUsage) -> (Arc<ImmutableBuffer<[T]>>, Box<GpuFuture>)
+        where T: Send + Sync + 'static {
+        // Vulkano adds the transfer usages the staging copy needs.
+        let (buffer, future) = ImmutableBuffer::from_iter(data.into_iter(), usage, self.queue.clone())
+            .expect("failed to create buffer");
+        (buffer, Box::new(future))
+    }
+
+    /// Loads an image file as a texture, in a format matching the precision of
+    /// the file (see `texture::load_image`). 8-bit color maps should be loaded
+    /// as `ColorSpace::Srgb` so they are decoded to linear when sampled, data
+    /// like normal, roughness or occlusion maps as `ColorSpace::Linear`.
+    /// DDS and KTX2 files are loaded with `load_compressed_texture`.
+    pub fn load_texture(&self, filename: &str, color_space: ColorSpace) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>) {
+        let decoded = DecodedTexture::load(filename, color_space, &self.device)
+            .unwrap_or_else(|err| panic!("Failed to load {}: {}!", filename, err));
+        self.uploader().upload_texture(decoded)
+    }
+
+    /// Uploads an image along with its full mip chain. The chain is generated
+    /// with blits if the format allows, and on the CPU otherwise.
+    pub fn load_texture_data<T>(&self, width: u32, height: u32, format: Format, data: Vec<T>) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>)
+        where T: Channel, Format: AcceptsPixels<T> {
+        self.uploader().upload_image(width, height, format, data)
+    }
+
+    /// Loads a cubemap from six images of square faces, in +X, -X, +Y, -Y, +Z,
+    /// -Z order, with a full mip chain.
+    pub fn load_cubemap(&self, filenames: [&str; 6], color_space: ColorSpace) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>) {
+        let faces = filenames.iter().map(|filename| texture::load_image(filename)).collect();
+        self.load_cubemap_image(cubemap::stack_faces(faces), color_space)
+    }
+
+    /// Loads a cubemap from a single image with the faces laid out in a
+    /// horizontal or vertical cross, see `cubemap::unfold_cross_data`.
+    pub fn load_cubemap_cross(&self, filename: &str, color_space: ColorSpace) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>) {
+        let image = texture::load_image(filename);
+        let faces = cubemap::unfold_cross(&image)
+            .unwrap_or_else(|| panic!("{} isn't laid out as a cross of cubemap faces!", filename));
+        self.load_cubemap_image(faces, color_space)
+    }
+
+    // Uploads the faces stacked by `cubemap::stack_faces` or `cubemap::unfold_cross`.
+    fn load_cubemap_image(&self, image: LoadedImage, color_space: ColorSpace) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>) {
+        let physical = self.device.physical_device();
+        let (image, format) = texture::prepare_texture(image, color_space, |format| texture::can_sample(physical, format));
+        match image.data {
+            ImageData::U8(data) => self.load_cubemap_data(image.width, format, data),
+            ImageData::U16(data) => self.load_cubemap_data(image.width, format, data),
+            ImageData::F16(data) => self.load_cubemap_data(image.width, format, data),
+            ImageData::F32(data) => self.load_cubemap_data(image.width, format, data),
+        }
+    }
+
+    /// Uploads six stacked faces of `size` by `size` texels as a cubemap. The
+    /// mip chain of each face is generated on the CPU.
+    pub fn load_cubemap_data<T>(&self, size: u32, format: Format, data: Vec<T>) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>)
+        where T: Channel, Format: AcceptsPixels<T> {
+        let (tex, init) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            Dimensions::Cubemap { size },
+            format,
+            MipmapsCount::Specific(texture::mip_levels(size, size)),
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                .. ImageUsage::none()
+            },
+            ImageLayout::ShaderReadOnlyOptimal,
+            Some(self.queue.family())).unwrap();
+        let init = Arc::new(init);
+
+        let mut command_buffer = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family()).unwrap();
+        let face_len = data.len() / 6;
+        let channels = face_len / (size * size) as usize;
+        for (face, face_data) in data.chunks(face_len).enumerate() {
+            for (level, level_data) in texture::generate_mipmaps(size, size, channels, face_data.to_vec()).into_iter().enumerate() {
+                let dimensions = texture::mip_dimensions(size, size, level as u32);
+                let buffer = CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    vulkano::buffer::BufferUsage::transfer_source(),
+                    level_data.into_iter()).expect("failed to create buffer");
+                command_buffer = command_buffer.copy_buffer_to_image_dimensions(
+                    buffer, init.clone(), [0, 0, 0], [dimensions[0], dimensions[1], 1], face as u32, 1, level as u32).unwrap();
+            }
+        }
+
+        let tex_future = command_buffer.build().unwrap()
+            .execute(self.queue.clone()).unwrap();
         (tex, Box::new(tex_future))
     }
 
+    /// Converts an equirectangular panorama, usually an `.hdr` file, into a
+    /// half float cubemap with `size` by `size` faces, on the GPU. The mip
+    /// chain is generated with blits.
+    pub fn load_cubemap_equirectangular(&self, filename: &str, size: u32) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>) {
+        let format = Format::R16G16B16A16Sfloat;
+        let levels = texture::mip_levels(size, size);
+        let (panorama, panorama_future) = self.load_texture(filename, ColorSpace::Linear);
+        let (tex, init) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            Dimensions::Cubemap { size },
+            format,
+            MipmapsCount::Specific(levels),
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                .. ImageUsage::none()
+            },
+            ImageLayout::ShaderReadOnlyOptimal,
+            Some(self.queue.family())).unwrap();
+        let init = Arc::new(init);
+
+        // Like `Uploader::upload_blitted`, every level is generated in a
+        // scratch image of its own, the first one by the compute shader.
+        let scratch: Vec<_> = (0..levels).map(|level| {
+            StorageImage::with_usage(
+                self.device.clone(),
+                Dimensions::Cubemap { size: texture::mip_dimensions(size, size, level)[0] },
+                format,
+                ImageUsage {
+                    storage: level == 0,
+                    transfer_source: true,
+                    transfer_destination: true,
+                    .. ImageUsage::none()
+                },
+                Some(self.queue.family())).unwrap()
+        }).collect();
+        let cubemap = scratch[0].clone();
+
+        let shader = shaders::equirectangular_cs::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let pipeline = Arc::new(ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"));
+        let set = Arc::new(PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_sampled_image(panorama, self.sampler.clone()).expect("failed to add panorama")
+            .add_image(cubemap.clone()).expect("failed to add cubemap")
+            .build().expect("failed to build descriptor set"));
+
+        let groups = (size + 7) / 8;
+        let mut command_buffer = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family()).unwrap()
+            .dispatch([groups, groups, 6], pipeline, set, ()).unwrap();
+
+        let corner = |level: u32| {
+            let size = texture::mip_dimensions(size, size, level)[0] as i32;
+            [size, size, 1]
+        };
+        for level in 0..levels {
+            if level > 0 {
+                command_buffer = command_buffer.blit_image(
+                    scratch[level as usize - 1].clone(), [0, 0, 0], corner(level - 1), 0, 0,
+                    scratch[level as usize].clone(), [0, 0, 0], corner(level), 0, 0,
+                    6, Filter::Linear).unwrap();
+            }
+            command_buffer = command_buffer.blit_image(
+                scratch[level as usize].clone(), [0, 0, 0], corner(level), 0, 0,
+                init.clone(), [0, 0, 0], corner(level), 0, level,
+                6, Filter::Nearest).unwrap();
+        }
+
+        let future = panorama_future.then_execute(self.queue.clone(), command_buffer.build().unwrap()).unwrap();
+        (tex, Box::new(future))
+    }
+
+    /// Uploads a DDS or KTX2 texture as stored in the file, with its mip chain
+    /// and, for cubemaps, all six faces. Fails if the device can't sample the
+    /// format, e.g. BC formats on most mobile GPUs or ASTC on most desktop ones.
+    pub fn load_compressed_texture(&self, filename: &str, color_space: ColorSpace) -> Result<(Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>), TextureError> {
+        let decoded = DecodedTexture::compressed(filename, color_space, &self.device)?;
+        Ok(self.uploader().upload_texture(decoded))
+    }
+
+    // Uploads through the graphics queue, which can also generate mip chains
+    // with blits.
+    fn uploader(&self) -> Uploader {
+        Uploader::new(self.device.clone(), self.queue.clone(), self.queue.clone())
+    }
+
+    /// Creates a trilinear sampler. `anisotropy` is clamped to what the device
+    /// supports, and ignored if anisotropic filtering isn't available.
+    pub fn create_sampler(&self, anisotropy: f32) -> Arc<Sampler> {
+        Graphics::new_sampler(self.device.clone(), anisotropy).expect("failed to create sampler")
+    }
+
+    fn new_sampler(device: Arc<Device>, anisotropy: f32) -> Result<Arc<Sampler>, SamplerCreationError> {
+        let max_anisotropy = if device.enabled_features().sampler_anisotropy {
+            anisotropy.max(1.0).min(device.physical_device().limits().max_sampler_anisotropy())
+        } else {
+            1.0
+        };
+        Sampler::new(
+            device,
+            Filter::Linear,
+            Filter::Linear,
+            vulkano::sampler::MipmapMode::Linear,
+            vulkano::sampler::SamplerAddressMode::Repeat,
+            vulkano::sampler::SamplerAddressMode::Repeat,
+            vulkano::sampler::SamplerAddressMode::Repeat,
+            0.0,
+            max_anisotropy,
+            0.0,
+            // VK_LOD_CLAMP_NONE, so every mip level is used.
+            1000.0)
+    }
+
+    /// Creates a pool to upload a skeleton's joint palette into every frame, as
+    /// a storage buffer of column-major matrices.
+    pub fn create_joint_palette_pool(&self) -> CpuBufferPool<[[f32; 4]; 4]> {
+        CpuBufferPool::new(
+            self.device.clone(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                .. vulkano::buffer::BufferUsage::none()
+            })
+    }
+
+    /// Uploads the deltas of a mesh's morph targets into a storage buffer. The
+    /// mesh must have at least one target.
+    pub fn load_morph_deltas(&self, mesh: &MorphMesh) -> Arc<CpuAccessibleBuffer<[[f32; 4]]>> {
+        CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                .. vulkano::buffer::BufferUsage::none()
+            },
+            mesh.delta_data().into_iter()).expect("failed to create buffer")
+    }
+
+    /// Creates a pool to upload per-instance data into every frame, usable
+    /// both as the instance vertex buffer of an instanced pipeline and as a
+    /// storage buffer. Static instances can use `load_vertex_buffer` instead.
+    pub fn create_instance_pool(&self) -> CpuBufferPool<InstanceData> {
+        CpuBufferPool::new(
+            self.device.clone(),
+            vulkano::buffer::BufferUsage {
+                vertex_buffer: true,
+                storage_buffer: true,
+                .. vulkano::buffer::BufferUsage::none()
+            })
+    }
+
+    /// Creates the argument buffer for `draw_indirect` drawing `instances`
+    /// copies of `vertices` vertices. This is how to draw instances with a
+    /// single vertex buffer, reading their data from a storage buffer indexed
+    /// by `gl_InstanceIndex`.
+    pub fn create_instanced_draw(&self, vertices: u32, instances: u32) -> Arc<CpuAccessibleBuffer<[DrawIndirectCommand]>> {
+        let command = DrawIndirectCommand {
+            vertex_count: vertices,
+            instance_count: instances,
+            first_vertex: 0,
+            first_instance: 0,
+        };
+        CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            vulkano::buffer::BufferUsage::indirect_buffer(),
+            Some(command).into_iter()).expect("failed to create buffer")
+    }
+
+    /// Creates a pool to upload morph target weights into every frame, as a storage buffer.
+    pub fn create_morph_weights_pool(&self) -> CpuBufferPool<f32> {
+        CpuBufferPool::new(
+            self.device.clone(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                .. vulkano::buffer::BufferUsage::none()
+            })
+    }
+
+    /// Creates a pipeline with the default state of `PipelineBuilder`, which
+    /// configures culling, blending, depth testing and the like.
     pub fn create_pipeline<V, F>(&self, vs: V, fs: F) -> Arc<GraphicsPipelineAbstract + Send + Sync>
         where V: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
               V::PipelineLayout: Clone + 'static + Send + Sync,
               F: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
               F::PipelineLayout: Clone + 'static + Send + Sync {
-        let pipeline = Arc::new(vulkano::pipeline::GraphicsPipeline::start()
-            .vertex_input_single_buffer::<Vertex>()
-            .vertex_shader(vs, ())
-            .triangle_list()
-            .viewports_dynamic_scissors_irrelevant(1)
-            .fragment_shader(fs, ())
-            .depth_stencil_simple_depth()
-            .render_pass(vulkano::framebuffer::Subpass::from(self.renderpass.clone(), 0).unwrap())
-            .build(self.device.clone())
-            .unwrap());
-        pipeline
-    }
-}
\ No newline at end of file
+        self.create_pipeline_for_vertex::<Vertex, V, F>(vs, fs)
+    }
+
+    /// Like `create_pipeline`, with values for the specialization constants
+    /// of the vertex and fragment shaders. A `PipelineVariants` keyed by the
+    /// constants can create each combination once.
+    pub fn create_specialized_pipeline<V, F>(&self, vs: V, vs_constants: V::SpecializationConstants, fs: F, fs_constants: F::SpecializationConstants)
+                                             -> Arc<GraphicsPipelineAbstract + Send + Sync>
+        where V: GraphicsEntryPointAbstract,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        PipelineBuilder::new().build_specialized::<Vertex, V, F>(self, vs, vs_constants, fs, fs_constants).unwrap()
+    }
+
+    /// Like `create_pipeline`, for instanced drawing with `InstanceData` in a
+    /// second vertex buffer. Draw with `vec![mesh, instances]`, which draws
+    /// one copy of the mesh per element of `instances`.
+    pub fn create_instanced_pipeline<V, F>(&self, vs: V, fs: F) -> Arc<GraphicsPipelineAbstract + Send + Sync>
+        where V: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        PipelineBuilder::new().build_instanced::<Vertex, InstanceData, V, F>(self, vs, fs).unwrap()
+    }
+
+    /// Like `create_pipeline`, for vertex types other than `Vertex`, e.g. `SkinnedVertex`.
+    pub fn create_pipeline_for_vertex<T, V, F>(&self, vs: V, fs: F) -> Arc<GraphicsPipelineAbstract + Send + Sync>
+        where T: vulkano::pipeline::vertex::Vertex,
+              V: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        self.try_create_pipeline_for_vertex::<T, V, F>(vs, fs).unwrap()
+    }
+
+    /// Like `create_pipeline_for_vertex`, returning an error if the shaders
+    /// don't fit together, e.g. shaders compiled at runtime with `glsl::Shader`.
+    pub fn try_create_pipeline_for_vertex<T, V, F>(&self, vs: V, fs: F) -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, GraphicsPipelineCreationError>
+        where T: vulkano::pipeline::vertex::Vertex,
+              V: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        PipelineBuilder::new().build::<T, V, F>(self, vs, fs)
+    }
+}
+
+impl Drop for Graphics {
+    fn drop(&mut self) {
+        if let Err(err) = self.save_pipeline_cache() {
+            warn!("Failed to save pipeline cache: {}", err);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use cgmath::*;
+use vulkano;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::format::Format;
+use vulkano::image::Dimensions;
+use vulkano::image::ImageLayout;
+use vulkano::image::ImageUsage;
+use vulkano::image::ImageViewAccess;
+use vulkano::image::ImmutableImage;
+use vulkano::image::MipmapsCount;
+use vulkano::image::StorageImage;
+use vulkano::pipeline::ComputePipeline;
+use vulkano::sampler::Filter;
+use vulkano::sampler::Sampler;
+use vulkano::sync::GpuFuture;
+
+use graphics::Graphics;
+use shaders::brdf_lut_cs;
+use shaders::irradiance_cs;
+use shaders::prefilter_cs;
+use texture;
+
+/// Size of the faces of the irradiance cubemap. Irradiance varies slowly, so
+/// this can be small.
+pub const IRRADIANCE_SIZE: u32 = 32;
+/// Size of the faces of the first level of the prefiltered specular cubemap.
+pub const PREFILTERED_SIZE: u32 = 128;
+/// Mip levels of the prefiltered cubemap, from roughness 0.0 to 1.0.
+pub const PREFILTERED_LEVELS: u32 = 5;
+pub const BRDF_LUT_SIZE: u32 = 256;
+
+// Everything is computed at half float precision.
+const FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+/// The roughness a level of the prefiltered cubemap is filtered for. Shaders
+/// should sample `roughness * (levels - 1)` as the level of detail.
+pub fn prefiltered_roughness(level: u32, levels: u32) -> f32 {
+    if levels <= 1 { 0.0 } else { level as f32 / (levels - 1) as f32 }
+}
+
+/// The Van der Corput sequence: the bits of `i` mirrored around the binary point.
+pub fn radical_inverse(i: u32) -> f32 {
+    let mut bits = (i << 16) | (i >> 16);
+    bits = ((bits & 0x5555_5555) << 1) | ((bits & 0xaaaa_aaaa) >> 1);
+    bits = ((bits & 0x3333_3333) << 2) | ((bits & 0xcccc_cccc) >> 2);
+    bits = ((bits & 0x0f0f_0f0f) << 4) | ((bits & 0xf0f0_f0f0) >> 4);
+    bits = ((bits & 0x00ff_00ff) << 8) | ((bits & 0xff00_ff00) >> 8);
+    bits as f32 * 2.328_306_4e-10
+}
+
+/// The `i`th of `n` points of the Hammersley set, evenly spread over the unit square.
+pub fn hammersley(i: u32, n: u32) -> Vector2<f32> {
+    Vector2::new(i as f32 / n as f32, radical_inverse(i))
+}
+
+/// A half vector around +Z, distributed like the GGX normal distribution
+/// for `roughness`, for a point `xi` of the unit square.
+pub fn importance_sample_ggx(xi: Vector2<f32>, roughness: f32) -> Vector3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    Vector3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    n_dot_x / (n_dot_x * (1.0 - k) + k)
+}
+
+/// The scale and bias to F0 of the split-sum approximation of the specular
+/// BRDF, integrated over `samples` directions. This is what the BRDF lookup
+/// table holds; the compute shader does the same integration.
+pub fn integrate_brdf(n_dot_v: f32, roughness: f32, samples: u32) -> (f32, f32) {
+    let v = Vector3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+    let (mut scale, mut bias) = (0.0, 0.0);
+    for i in 0..samples {
+        let h = importance_sample_ggx(hammersley(i, samples), roughness);
+        let l = (h * 2.0 * v.dot(h) - v).normalize();
+        let n_dot_l = l.z.max(0.0);
+        let n_dot_h = h.z.max(0.0);
+        let v_dot_h = v.dot(h).max(0.0);
+        if n_dot_l > 0.0 {
+            let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = (1.0 - v_dot_h).powi(5);
+            scale += (1.0 - fresnel) * g_vis;
+            bias += fresnel * g_vis;
+        }
+    }
+    (scale / samples as f32, bias / samples as f32)
+}
+
+/// The maps for image-based lighting, computed from an environment cubemap.
+///
+/// Shaders light a surface with normal `n`, seen from direction `v`, as
+/// `diffuse = albedo * texture(irradiance, n)` plus
+/// `specular = textureLod(prefiltered, reflect(-v, n), roughness * (levels - 1)) * (f0 * lut.r + lut.g)`,
+/// where `lut = texture(brdf_lut, vec2(dot(n, v), roughness))`.
+pub struct Environment {
+    pub irradiance: Arc<ImageViewAccess + Send + Sync>,
+    pub prefiltered: Arc<ImageViewAccess + Send + Sync>,
+    pub brdf_lut: Arc<ImageViewAccess + Send + Sync>,
+    /// Clamps to the edges, which the lookup table needs.
+    pub sampler: Arc<Sampler>,
+}
+
+impl Environment {
+    /// Runs the compute passes that generate the maps from `cubemap`, once
+    /// `future` has completed. `cubemap` should have a full mip chain, like
+    /// the ones `Graphics::load_cubemap_equirectangular` creates, for the
+    /// rough levels to come out smooth.
+    pub fn new<F>(graphics: &Graphics, cubemap: Arc<ImageViewAccess + Send + Sync>, future: F) -> (Environment, Box<GpuFuture>)
+        where F: GpuFuture + 'static {
+        let device = graphics.device.clone();
+        let family = graphics.queue.family();
+        let storage_usage = ImageUsage {
+            storage: true,
+            sampled: true,
+            transfer_source: true,
+            .. ImageUsage::none()
+        };
+        let mut command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), family).unwrap();
+
+        let irradiance = StorageImage::with_usage(
+            device.clone(), Dimensions::Cubemap { size: IRRADIANCE_SIZE }, FORMAT, storage_usage, Some(family)).unwrap();
+        let shader = irradiance_cs::Shader::load(device.clone()).expect("failed to create shader module");
+        let pipeline = Arc::new(ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"));
+        let set = Arc::new(PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_sampled_image(cubemap.clone(), graphics.sampler.clone()).unwrap()
+            .add_image(irradiance.clone()).unwrap()
+            .build().unwrap());
+        command_buffer = command_buffer.dispatch(cube_groups(IRRADIANCE_SIZE), pipeline, set, ()).unwrap();
+
+        // Each level is filtered into a storage image of its own, since storage
+        // images have a single level, and then copied into the mip chain.
+        let (prefiltered, init) = ImmutableImage::uninitialized(
+            device.clone(),
+            Dimensions::Cubemap { size: PREFILTERED_SIZE },
+            FORMAT,
+            MipmapsCount::Specific(PREFILTERED_LEVELS),
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                .. ImageUsage::none()
+            },
+            ImageLayout::ShaderReadOnlyOptimal,
+            Some(family)).unwrap();
+        let init = Arc::new(init);
+        let shader = prefilter_cs::Shader::load(device.clone()).expect("failed to create shader module");
+        let pipeline = Arc::new(ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"));
+        for level in 0..PREFILTERED_LEVELS {
+            let size = texture::mip_dimensions(PREFILTERED_SIZE, PREFILTERED_SIZE, level)[0];
+            let scratch = StorageImage::with_usage(
+                device.clone(), Dimensions::Cubemap { size }, FORMAT, storage_usage, Some(family)).unwrap();
+            let set = Arc::new(PersistentDescriptorSet::start(pipeline.clone(), 0)
+                .add_sampled_image(cubemap.clone(), graphics.sampler.clone()).unwrap()
+                .add_image(scratch.clone()).unwrap()
+                .build().unwrap());
+            let push_constants = prefilter_cs::ty::PushConstants {
+                roughness: prefiltered_roughness(level, PREFILTERED_LEVELS),
+            };
+            let corner = [size as i32, size as i32, 1];
+            command_buffer = command_buffer
+                .dispatch(cube_groups(size), pipeline.clone(), set, push_constants).unwrap()
+                .blit_image(
+                    scratch, [0, 0, 0], corner, 0, 0,
+                    init.clone(), [0, 0, 0], corner, 0, level,
+                    6, Filter::Nearest).unwrap();
+        }
+
+        let brdf_lut = StorageImage::with_usage(
+            device.clone(),
+            Dimensions::Dim2d { width: BRDF_LUT_SIZE, height: BRDF_LUT_SIZE },
+            FORMAT, storage_usage, Some(family)).unwrap();
+        let shader = brdf_lut_cs::Shader::load(device.clone()).expect("failed to create shader module");
+        let pipeline = Arc::new(ComputePipeline::new(device.clone(), &shader.main_entry_point(), &())
+            .expect("failed to create compute pipeline"));
+        let set = Arc::new(PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_image(brdf_lut.clone()).unwrap()
+            .build().unwrap());
+        let groups = (BRDF_LUT_SIZE + 7) / 8;
+        command_buffer = command_buffer.dispatch([groups, groups, 1], pipeline, set, ()).unwrap();
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            vulkano::sampler::MipmapMode::Linear,
+            vulkano::sampler::SamplerAddressMode::ClampToEdge,
+            vulkano::sampler::SamplerAddressMode::ClampToEdge,
+            vulkano::sampler::SamplerAddressMode::ClampToEdge,
+            0.0, 1.0, 0.0, 1000.0).expect("failed to create sampler");
+
+        let future = future.then_execute(graphics.queue.clone(), command_buffer.build().unwrap()).unwrap();
+        let environment = Environment { irradiance, prefiltered, brdf_lut, sampler };
+        (environment, Box::new(future))
+    }
+}
+
+// Workgroups covering every texel of every face, for the 8x8 local size of
+// the cubemap shaders.
+fn cube_groups(size: u32) -> [u32; 3] {
+    let groups = (size + 7) / 8;
+    [groups, groups, 6]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hammersley() {
+        assert_eq!(radical_inverse(0), 0.0);
+        assert_eq!(radical_inverse(1), 0.5);
+        assert_eq!(radical_inverse(2), 0.25);
+        assert_eq!(radical_inverse(3), 0.75);
+        assert_eq!(hammersley(1, 4), Vector2::new(0.25, 0.5));
+        assert_eq!(prefiltered_roughness(0, 5), 0.0);
+        assert_eq!(prefiltered_roughness(2, 5), 0.5);
+        assert_eq!(prefiltered_roughness(4, 5), 1.0);
+    }
+
+    #[test]
+    fn test_importance_sample_ggx() {
+        // A perfectly smooth surface only reflects along the normal.
+        let h = importance_sample_ggx(Vector2::new(0.3, 0.7), 0.0);
+        assert!((h - Vector3::unit_z()).magnitude() < 1e-6);
+        // Rougher surfaces spread further from it, in the upper hemisphere.
+        let smooth = importance_sample_ggx(Vector2::new(0.3, 0.7), 0.3);
+        let rough = importance_sample_ggx(Vector2::new(0.3, 0.7), 0.9);
+        assert!(rough.z < smooth.z && rough.z > 0.0);
+        assert!((rough.magnitude() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_integrate_brdf() {
+        // Smooth and seen head on, all of F0 is reflected.
+        let (scale, bias) = integrate_brdf(1.0, 0.0, 64);
+        assert!((scale - 1.0).abs() < 1e-4 && bias.abs() < 1e-4);
+        // Otherwise less is, and Fresnel adds more at grazing angles.
+        for &roughness in &[0.25, 0.5, 1.0] {
+            let (scale, bias) = integrate_brdf(0.5, roughness, 256);
+            assert!(scale + bias <= 1.0 && scale > 0.0 && bias > 0.0);
+            let (grazing_scale, grazing_bias) = integrate_brdf(0.05, roughness, 256);
+            assert!(grazing_scale + grazing_bias <= 1.0 && grazing_bias > bias);
+        }
+    }
+}
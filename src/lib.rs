@@ -1,12 +1,38 @@
 #[macro_use]
 extern crate vulkano;
+#[macro_use]
+extern crate vulkano_shader_derive;
 extern crate winit;
 extern crate vulkano_win;
 extern crate cgmath;
+#[macro_use]
 extern crate log;
 extern crate image;
+extern crate png;
+extern crate glsl_to_spirv;
+#[cfg(feature = "exr")]
+extern crate openexr;
 
+pub mod animation;
+pub mod assets;
+pub mod camera;
+pub mod compressed;
+pub mod cubemap;
+pub mod debug;
+pub mod device;
 pub mod geometry;
+pub mod glsl;
 pub mod graphics;
+pub mod ibl;
+pub mod loader;
+pub mod morph;
 pub mod obj;
+pub mod pipeline;
+pub mod pipeline_cache;
 pub mod render;
+pub mod scene;
+mod shaders;
+pub mod skybox;
+pub mod spirv;
+pub mod texture;
+pub mod upload;
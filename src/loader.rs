@@ -0,0 +1,547 @@
+use std::any::Any;
+use std::fs;
+use std::hash::Hash;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::ImmutableBuffer;
+use vulkano::format::Format;
+use vulkano::image::ImageViewAccess;
+use vulkano::sync::GpuFuture;
+
+use assets::AssetCache;
+use assets::Handle;
+use assets::MeshKey;
+use assets::TextureKey;
+use geometry::Vertex;
+use graphics::Graphics;
+use obj::ObjModel;
+use texture::ColorSpace;
+use upload::DecodedTexture;
+use upload::Uploader;
+
+/// Worker threads used by `AssetLoader::new`.
+pub const DEFAULT_THREADS: usize = 2;
+
+/// How often a `HotAsset` checks whether its file has changed, by default.
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+
+pub type TextureRequest = Request<Arc<ImageViewAccess + Send + Sync>>;
+pub type MeshRequest = Request<Arc<ImmutableBuffer<[Vertex]>>>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState<T> {
+    Loading,
+    Ready(T),
+    /// Loading failed, with the reason. Only `HotAsset` logs failures.
+    Failed(String),
+}
+
+struct Slot<T> {
+    state: Mutex<LoadState<T>>,
+    done: Condvar,
+}
+
+impl<T> Slot<T> {
+    fn new() -> Slot<T> {
+        Slot { state: Mutex::new(LoadState::Loading), done: Condvar::new() }
+    }
+}
+
+/// An asset being loaded in the background. Cloning the request shares it.
+pub struct Request<T> {
+    slot: Handle<Slot<T>>,
+}
+
+impl<T> Clone for Request<T> {
+    fn clone(&self) -> Request<T> {
+        Request { slot: self.slot.clone() }
+    }
+}
+
+impl<T: Clone> Request<T> {
+    fn complete(&self, result: Result<T, String>) {
+        *self.slot.state.lock().unwrap() = match result {
+            Ok(asset) => LoadState::Ready(asset),
+            Err(reason) => LoadState::Failed(reason),
+        };
+        self.slot.done.notify_all();
+    }
+
+    pub fn state(&self) -> LoadState<T> {
+        self.slot.state.lock().unwrap().clone()
+    }
+
+    /// The asset, if it is ready.
+    pub fn poll(&self) -> Option<T> {
+        match *self.slot.state.lock().unwrap() {
+            LoadState::Ready(ref asset) => Some(asset.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether loading has finished, successfully or not.
+    pub fn is_done(&self) -> bool {
+        match *self.slot.state.lock().unwrap() {
+            LoadState::Loading => false,
+            _ => true,
+        }
+    }
+
+    /// The asset if it is ready, `placeholder` while it is loading or if it failed.
+    pub fn get_or(&self, placeholder: &T) -> T {
+        self.poll().unwrap_or_else(|| placeholder.clone())
+    }
+
+    /// Blocks until loading has finished.
+    pub fn wait(&self) -> Result<T, String> {
+        let mut state = self.slot.state.lock().unwrap();
+        loop {
+            match *state {
+                LoadState::Loading => state = self.slot.done.wait(state).unwrap(),
+                LoadState::Ready(ref asset) => return Ok(asset.clone()),
+                LoadState::Failed(ref reason) => return Err(reason.clone()),
+            }
+        }
+    }
+}
+
+enum Job {
+    Texture { path: String, color_space: ColorSpace, request: TextureRequest },
+    Mesh { path: String, request: MeshRequest },
+}
+
+/// Loads textures and meshes on worker threads. Files are decoded on the
+/// workers like `Graphics::load_texture` does, then uploaded through
+/// `Graphics::transfer_queue`. Workers wait for the upload, so a request is
+/// only ready once the asset can be used on any queue.
+///
+/// Until then, draw with the placeholders instead of waiting, e.g.
+/// `request.get_or(&loader.placeholder_texture(ColorSpace::Srgb))`.
+///
+/// Requests for the same file and settings share one load, for as long as
+/// any of them is alive. Failed loads are retried by the next request.
+pub struct AssetLoader {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    textures: Mutex<AssetCache<TextureKey, Slot<Arc<ImageViewAccess + Send + Sync>>>>,
+    meshes: Mutex<AssetCache<MeshKey, Slot<Arc<ImmutableBuffer<[Vertex]>>>>>,
+    placeholder_color: Arc<ImageViewAccess + Send + Sync>,
+    placeholder_normal: Arc<ImageViewAccess + Send + Sync>,
+    placeholder_mesh: Arc<ImmutableBuffer<[Vertex]>>,
+}
+
+impl AssetLoader {
+    pub fn new(graphics: &Graphics) -> AssetLoader {
+        AssetLoader::with_threads(graphics, DEFAULT_THREADS)
+    }
+
+    /// Creates the loader with `threads` workers, and uploads the placeholders
+    /// before returning.
+    pub fn with_threads(graphics: &Graphics, threads: usize) -> AssetLoader {
+        let uploader = Uploader::new(graphics.device.clone(), graphics.transfer_queue.clone(), graphics.queue.clone());
+
+        let placeholder_color = finish(uploader.upload_image(1, 1, Format::R8G8B8A8Srgb, vec![255u8, 255, 255, 255]));
+        let placeholder_normal = finish(uploader.upload_image(1, 1, Format::R8G8B8A8Unorm, vec![128u8, 128, 255, 255]));
+        // A single degenerate triangle, which draws nothing.
+        let placeholder_mesh = finish(uploader.upload_buffer(vec![Vertex {
+            position: (0.0, 0.0, 0.0),
+            normal: (0.0, 0.0, 1.0),
+            uv: (0.0, 0.0),
+            tangent_u: (1.0, 0.0, 0.0),
+            tangent_v: (0.0, 1.0, 0.0),
+        }; 3], BufferUsage::vertex_buffer()));
+
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1)).map(|index| {
+            let receiver = receiver.clone();
+            let uploader = uploader.clone();
+            thread::Builder::new()
+                .name(format!("asset loader {}", index))
+                .spawn(move || work(receiver, uploader))
+                .expect("failed to spawn asset loader thread")
+        }).collect();
+
+        AssetLoader {
+            jobs: Some(sender),
+            workers,
+            textures: Mutex::new(AssetCache::new()),
+            meshes: Mutex::new(AssetCache::new()),
+            placeholder_color,
+            placeholder_normal,
+            placeholder_mesh,
+        }
+    }
+
+    /// Loads a texture like `Graphics::load_texture`, in the background.
+    pub fn load_texture(&self, path: &str, color_space: ColorSpace) -> TextureRequest {
+        self.request_texture(path, color_space, false)
+    }
+
+    /// Loads a model like `Graphics::load_model`, in the background.
+    pub fn load_model(&self, path: &str) -> MeshRequest {
+        self.request_model(path, false)
+    }
+
+    /// Loads a texture in the background like `load_texture`, and again
+    /// whenever the file changes.
+    pub fn watch_texture(&self, path: &str, color_space: ColorSpace) -> HotAsset<Arc<ImageViewAccess + Send + Sync>> {
+        HotAsset::new(self, path, Box::new(move |loader: &AssetLoader, path: &str, reload: bool| loader.request_texture(path, color_space, reload)))
+    }
+
+    /// Loads a model in the background like `load_model`, and again whenever
+    /// the file changes.
+    pub fn watch_model(&self, path: &str) -> HotAsset<Arc<ImmutableBuffer<[Vertex]>>> {
+        HotAsset::new(self, path, Box::new(|loader: &AssetLoader, path: &str, reload: bool| loader.request_model(path, reload)))
+    }
+
+    /// A 1x1 texture to draw with until a texture is ready: white for color
+    /// maps, a flat normal for linear data such as normal maps.
+    pub fn placeholder_texture(&self, color_space: ColorSpace) -> Arc<ImageViewAccess + Send + Sync> {
+        match color_space {
+            ColorSpace::Srgb => self.placeholder_color.clone(),
+            ColorSpace::Linear => self.placeholder_normal.clone(),
+        }
+    }
+
+    /// A mesh that draws nothing, to draw until a mesh is ready.
+    pub fn placeholder_mesh(&self) -> Arc<ImmutableBuffer<[Vertex]>> {
+        self.placeholder_mesh.clone()
+    }
+
+    // A reload always starts a new load, which later requests then share.
+    fn request_texture(&self, path: &str, color_space: ColorSpace, reload: bool) -> TextureRequest {
+        shared(&self.textures, TextureKey::new(path, color_space), reload, |request| {
+            self.send(Job::Texture { path: path.to_string(), color_space, request });
+        })
+    }
+
+    fn request_model(&self, path: &str, reload: bool) -> MeshRequest {
+        shared(&self.meshes, MeshKey::new(path, None), reload, |request| {
+            self.send(Job::Mesh { path: path.to_string(), request });
+        })
+    }
+
+    fn send(&self, job: Job) {
+        self.jobs.as_ref().unwrap().send(job).expect("asset loader threads have stopped");
+    }
+}
+
+impl Drop for AssetLoader {
+    // Lets the workers finish the jobs already queued, then stops them.
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// An asset that is reloaded in the background whenever its file changes,
+/// found by polling the file's modification time.
+///
+/// Call `update` between frames: that is the only time the asset is swapped.
+/// The previous version stays alive for as long as anything references it,
+/// including command buffers and descriptor sets still in flight, so it is
+/// freed once the frames that used it have finished. A failed reload keeps
+/// the last good version and logs the error.
+pub struct HotAsset<T> {
+    watch: Watch<T>,
+    start: Box<Fn(&AssetLoader, &str, bool) -> Request<T>>,
+}
+
+impl<T: Clone> HotAsset<T> {
+    fn new(loader: &AssetLoader, path: &str, start: Box<Fn(&AssetLoader, &str, bool) -> Request<T>>) -> HotAsset<T> {
+        let watch = Watch::new(path, start(loader, path, false));
+        HotAsset { watch, start }
+    }
+
+    /// Swaps in a finished (re)load and starts a reload if the file changed.
+    /// Returns whether the asset was swapped, in which case anything built
+    /// from it, like descriptor sets, should be rebuilt.
+    pub fn update(&mut self, loader: &AssetLoader) -> bool {
+        let start = &self.start;
+        self.watch.update(|path| start(loader, path, true))
+    }
+
+    /// The last version that loaded successfully, if any.
+    pub fn get(&self) -> Option<T> {
+        self.watch.current.clone()
+    }
+
+    /// Like `get`, with `placeholder` until the asset has loaded once.
+    pub fn get_or(&self, placeholder: &T) -> T {
+        self.get().unwrap_or_else(|| placeholder.clone())
+    }
+
+    /// Counts the times the asset was swapped; 1 once it has first loaded.
+    pub fn generation(&self) -> u32 {
+        self.watch.generation
+    }
+
+    pub fn path(&self) -> &str {
+        &self.watch.path
+    }
+
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.watch.poll_interval = interval;
+    }
+}
+
+// The part of a `HotAsset` that doesn't need a loader.
+struct Watch<T> {
+    path: String,
+    current: Option<T>,
+    pending: Option<Request<T>>,
+    modified: Option<SystemTime>,
+    generation: u32,
+    poll_interval: Duration,
+    last_poll: Instant,
+}
+
+impl<T: Clone> Watch<T> {
+    fn new(path: &str, request: Request<T>) -> Watch<T> {
+        Watch {
+            path: path.to_string(),
+            current: None,
+            pending: Some(request),
+            modified: modified(path),
+            generation: 0,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            last_poll: Instant::now(),
+        }
+    }
+
+    fn update<F: FnOnce(&str) -> Request<T>>(&mut self, start: F) -> bool {
+        let mut swapped = false;
+        let state = self.pending.as_ref().map(|request| request.state());
+        match state {
+            Some(LoadState::Ready(asset)) => {
+                self.current = Some(asset);
+                self.generation += 1;
+                self.pending = None;
+                swapped = true;
+            },
+            Some(LoadState::Failed(reason)) => {
+                if self.current.is_some() {
+                    error!("Failed to reload {}, keeping the last good version: {}", self.path, reason);
+                } else {
+                    error!("Failed to load {}: {}", self.path, reason);
+                }
+                self.pending = None;
+            },
+            Some(LoadState::Loading) | None => (),
+        }
+
+        // Changes made while a reload is running are picked up after it.
+        if self.pending.is_none() && self.last_poll.elapsed() >= self.poll_interval {
+            self.last_poll = Instant::now();
+            let modified = modified(&self.path);
+            // A missing file is usually being replaced; keep what we have.
+            if modified.is_some() && modified != self.modified {
+                info!("Reloading {}", self.path);
+                self.modified = modified;
+                self.pending = Some(start(&self.path));
+            }
+        }
+        swapped
+    }
+}
+
+// The live request for `key`, unless it failed, or a new one passed to `start`.
+fn shared<K, T, F>(requests: &Mutex<AssetCache<K, Slot<T>>>, key: K, reload: bool, start: F) -> Request<T>
+    where K: Hash + Eq + Clone, T: Clone, F: FnOnce(Request<T>) {
+    let mut requests = requests.lock().unwrap();
+    if !reload {
+        if let Some(slot) = requests.get(&key) {
+            let request = Request { slot };
+            match request.state() {
+                LoadState::Failed(_) => (),
+                _ => return request,
+            }
+        }
+    }
+    requests.prune();
+    let request = Request { slot: requests.insert(key, Slot::new()) };
+    start(request.clone());
+    request
+}
+
+/// When the file at `path` was last modified, if it exists.
+pub fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn work(jobs: Arc<Mutex<Receiver<Job>>>, uploader: Uploader) {
+    loop {
+        // Only hold the lock while waiting for a job, not while running it.
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        match job {
+            Job::Texture { path, color_space, request } => {
+                let result = catch_panic(|| load_texture(&uploader, &path, color_space)).and_then(|result| result);
+                request.complete(result);
+            },
+            Job::Mesh { path, request } => {
+                let result = catch_panic(|| load_model(&uploader, &path)).and_then(|result| result);
+                request.complete(result);
+            },
+        }
+    }
+}
+
+// Bad files are reported as errors. Anything that still panics, like a lost
+// device, fails the request too instead of killing the worker.
+fn catch_panic<T, F: FnOnce() -> T>(f: F) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(panic_message)
+}
+
+fn panic_message(payload: Box<Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown error".to_string()
+    }
+}
+
+fn load_texture(uploader: &Uploader, path: &str, color_space: ColorSpace) -> Result<Arc<ImageViewAccess + Send + Sync>, String> {
+    let decoded = DecodedTexture::load(path, color_space, &uploader.device).map_err(|err| err.to_string())?;
+    Ok(finish(uploader.upload_texture(decoded)))
+}
+
+fn load_model(uploader: &Uploader, path: &str) -> Result<Arc<ImmutableBuffer<[Vertex]>>, String> {
+    let model = ObjModel::load(path)?;
+    Ok(finish(uploader.upload_buffer(model.vertices(), BufferUsage::vertex_buffer())))
+}
+
+// Waits until an upload is done, so the asset can be used on any queue.
+fn finish<T>(upload: (T, Box<GpuFuture>)) -> T {
+    let (asset, future) = upload;
+    future.then_signal_fence_and_flush().expect("failed to upload asset")
+        .wait(None).expect("failed to upload asset");
+    asset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_request<T: Clone>() -> Request<T> {
+        Request { slot: Handle::new(Slot::new()) }
+    }
+
+    #[test]
+    fn test_request_states() {
+        let request: Request<u32> = new_request();
+        assert_eq!(request.state(), LoadState::Loading);
+        assert_eq!(request.poll(), None);
+        assert!(!request.is_done());
+        assert_eq!(request.get_or(&7), 7);
+
+        request.clone().complete(Ok(3));
+        assert_eq!(request.state(), LoadState::Ready(3));
+        assert_eq!(request.poll(), Some(3));
+        assert!(request.is_done());
+        assert_eq!(request.get_or(&7), 3);
+
+        let failed: Request<u32> = new_request();
+        failed.complete(Err("missing".to_string()));
+        assert_eq!(failed.poll(), None);
+        assert!(failed.is_done());
+        assert_eq!(failed.get_or(&7), 7);
+        assert_eq!(failed.wait(), Err("missing".to_string()));
+    }
+
+    #[test]
+    fn test_wait_for_another_thread() {
+        let request: Request<u32> = new_request();
+        let worker = {
+            let request = request.clone();
+            thread::spawn(move || request.complete(Ok(5)))
+        };
+        assert_eq!(request.wait(), Ok(5));
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_requests_are_shared_until_dropped() {
+        let requests = Mutex::new(AssetCache::new());
+        let mut started = 0;
+        let first: Request<u32> = shared(&requests, "a", false, |_| started += 1);
+        let second = shared(&requests, "a", false, |_| started += 1);
+        assert_eq!(started, 1);
+        first.complete(Ok(1));
+        assert_eq!(second.poll(), Some(1));
+        shared(&requests, "b", false, |_| started += 1);
+        assert_eq!(started, 2);
+
+        // Reloads start over, and later requests share the reload.
+        let reload = shared(&requests, "a", true, |_| started += 1);
+        assert_eq!(started, 3);
+        assert_eq!(shared(&requests, "a", false, |_| started += 1).state(), LoadState::Loading);
+        assert_eq!(started, 3);
+
+        // Failed loads are retried.
+        reload.complete(Err("bad file".to_string()));
+        let retry = shared(&requests, "a", false, |_| started += 1);
+        assert_eq!(started, 4);
+
+        drop((first, second, reload, retry));
+        shared(&requests, "a", false, |_| started += 1);
+        assert_eq!(started, 5);
+    }
+
+    #[test]
+    fn test_watch_swaps_and_keeps_last_good_version() {
+        let path = ::std::env::temp_dir().join(format!("render-watch-test-{}", ::std::process::id()));
+        let _ = fs::remove_file(&path);
+        let path = path.to_str().unwrap().to_string();
+
+        let first = new_request();
+        let mut watch: Watch<u32> = Watch::new(&path, first.clone());
+        watch.poll_interval = Duration::from_secs(0);
+        let never = |_: &str| -> Request<u32> { panic!("nothing changed") };
+
+        assert!(!watch.update(never));
+        assert_eq!((watch.current, watch.generation), (None, 0));
+        first.complete(Ok(1));
+        assert!(watch.update(never));
+        assert_eq!((watch.current, watch.generation), (Some(1), 1));
+        assert!(!watch.update(never));
+
+        // The file appearing counts as a change.
+        fs::write(&path, "changed").unwrap();
+        let second = new_request();
+        assert!(!watch.update(|_| second.clone()));
+        second.complete(Err("bad file".to_string()));
+        assert!(!watch.update(never));
+        assert_eq!((watch.current, watch.generation), (Some(1), 1));
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watch.update(never));
+    }
+
+    #[test]
+    fn test_panics_become_errors() {
+        assert_eq!(catch_panic(|| 1), Ok(1));
+        assert_eq!(catch_panic(|| -> u32 { panic!("bad file") }), Err("bad file".to_string()));
+        assert_eq!(catch_panic(|| -> u32 { panic!("bad {}", "header") }), Err("bad header".to_string()));
+    }
+}
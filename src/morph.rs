@@ -0,0 +1,197 @@
+use cgmath::*;
+
+use geometry::Vertex;
+
+/// A blend shape: per-vertex offsets from the base mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorphTarget {
+    pub name: String,
+    pub position_deltas: Vec<Vector3<f32>>,
+    pub normal_deltas: Vec<Vector3<f32>>,
+    pub tangent_u_deltas: Vec<Vector3<f32>>,
+    pub tangent_v_deltas: Vec<Vector3<f32>>,
+}
+
+impl MorphTarget {
+    /// A target that only moves positions.
+    pub fn from_positions(name: &str, position_deltas: Vec<Vector3<f32>>) -> MorphTarget {
+        let zero = vec![Vector3::zero(); position_deltas.len()];
+        MorphTarget {
+            name: name.to_string(),
+            position_deltas,
+            normal_deltas: zero.clone(),
+            tangent_u_deltas: zero.clone(),
+            tangent_v_deltas: zero,
+        }
+    }
+
+    /// The target that turns `base` into `morphed`, which must have the same layout.
+    pub fn from_vertices(name: &str, base: &[Vertex], morphed: &[Vertex]) -> MorphTarget {
+        if base.len() != morphed.len() {
+            panic!("Morph target must have as many vertices as the base mesh!");
+        }
+        let delta = |a: (f32, f32, f32), b: (f32, f32, f32)| Vector3::new(b.0 - a.0, b.1 - a.1, b.2 - a.2);
+        MorphTarget {
+            name: name.to_string(),
+            position_deltas: base.iter().zip(morphed).map(|(a, b)| delta(a.position, b.position)).collect(),
+            normal_deltas: base.iter().zip(morphed).map(|(a, b)| delta(a.normal, b.normal)).collect(),
+            tangent_u_deltas: base.iter().zip(morphed).map(|(a, b)| delta(a.tangent_u, b.tangent_u)).collect(),
+            tangent_v_deltas: base.iter().zip(morphed).map(|(a, b)| delta(a.tangent_v, b.tangent_v)).collect(),
+        }
+    }
+}
+
+/// A mesh with morph targets and the weight of each target.
+///
+/// On the GPU the deltas live in a storage buffer (see `delta_data`) and are
+/// applied in the vertex shader, indexed by `gl_VertexIndex`. `apply` does the
+/// same on the CPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorphMesh {
+    pub base: Vec<Vertex>,
+    pub targets: Vec<MorphTarget>,
+    pub weights: Vec<f32>,
+}
+
+/// Number of `vec4`s per vertex per target in `delta_data`: position, normal, tangent_u and tangent_v.
+pub const DELTA_VEC4S_PER_VERTEX: usize = 4;
+
+impl MorphMesh {
+    pub fn new(base: Vec<Vertex>) -> MorphMesh {
+        MorphMesh { base, targets: vec![], weights: vec![] }
+    }
+
+    /// Adds a target with a weight of 0.0, returning its index.
+    pub fn add_target(&mut self, target: MorphTarget) -> usize {
+        let len = self.base.len();
+        if target.position_deltas.len() != len || target.normal_deltas.len() != len
+            || target.tangent_u_deltas.len() != len || target.tangent_v_deltas.len() != len {
+            panic!("Morph target must have as many vertices as the base mesh!");
+        }
+        self.targets.push(target);
+        self.weights.push(0.0);
+        self.targets.len() - 1
+    }
+
+    pub fn find_target(&self, name: &str) -> Option<usize> {
+        self.targets.iter().position(|target| target.name == name)
+    }
+
+    /// Applies the weighted deltas to the base mesh on the CPU.
+    pub fn apply(&self) -> Vec<Vertex> {
+        let add = |v: (f32, f32, f32), d: Vector3<f32>, w: f32| (v.0 + d.x * w, v.1 + d.y * w, v.2 + d.z * w);
+        let mut verts = self.base.clone();
+        for (target, &weight) in self.targets.iter().zip(&self.weights) {
+            if weight == 0.0 {
+                continue;
+            }
+            for (i, vertex) in verts.iter_mut().enumerate() {
+                vertex.position = add(vertex.position, target.position_deltas[i], weight);
+                vertex.normal = add(vertex.normal, target.normal_deltas[i], weight);
+                vertex.tangent_u = add(vertex.tangent_u, target.tangent_u_deltas[i], weight);
+                vertex.tangent_v = add(vertex.tangent_v, target.tangent_v_deltas[i], weight);
+            }
+        }
+        verts
+    }
+
+    /// The deltas of every target, as the vertex shader reads them from a
+    /// `vec4` storage buffer: the deltas of vertex `v` of target `t` start at
+    /// `(t * vertex_count + v) * DELTA_VEC4S_PER_VERTEX`.
+    pub fn delta_data(&self) -> Vec<[f32; 4]> {
+        let mut data = Vec::with_capacity(self.targets.len() * self.base.len() * DELTA_VEC4S_PER_VERTEX);
+        for target in &self.targets {
+            for i in 0..self.base.len() {
+                data.push(target.position_deltas[i].extend(0.0).into());
+                data.push(target.normal_deltas[i].extend(0.0).into());
+                data.push(target.tangent_u_deltas[i].extend(0.0).into());
+                data.push(target.tangent_v_deltas[i].extend(0.0).into());
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32) -> Vertex {
+        Vertex {
+            position: (x, 0.0, 0.0),
+            normal: (0.0, 0.0, 1.0),
+            uv: (x, 0.0),
+            tangent_u: (1.0, 0.0, 0.0),
+            tangent_v: (0.0, 1.0, 0.0),
+        }
+    }
+
+    fn morph_mesh() -> MorphMesh {
+        let base = vec![vertex(0.0), vertex(1.0)];
+        let mut mesh = MorphMesh::new(base.clone());
+        mesh.add_target(MorphTarget::from_positions("raise", vec![
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 2.0, 0.0),
+        ]));
+        let mut tilted = base.clone();
+        for v in tilted.iter_mut() {
+            v.normal = (1.0, 0.0, 1.0);
+            v.tangent_u = (1.0, 0.0, -1.0);
+        }
+        mesh.add_target(MorphTarget::from_vertices("tilt", &base, &tilted));
+        mesh
+    }
+
+    // Mirrors the vertex shader's reads from the delta buffer.
+    fn apply_from_delta_data(mesh: &MorphMesh) -> Vec<Vertex> {
+        let data = mesh.delta_data();
+        let vertex_count = data.len() / (DELTA_VEC4S_PER_VERTEX * mesh.weights.len());
+        mesh.base.iter().enumerate().map(|(v, base)| {
+            let mut sums = [[0.0; 3]; DELTA_VEC4S_PER_VERTEX];
+            for (t, &weight) in mesh.weights.iter().enumerate() {
+                let start = (t * vertex_count + v) * DELTA_VEC4S_PER_VERTEX;
+                for k in 0..DELTA_VEC4S_PER_VERTEX {
+                    for c in 0..3 {
+                        sums[k][c] += weight * data[start + k][c];
+                    }
+                }
+            }
+            let add = |a: (f32, f32, f32), d: [f32; 3]| (a.0 + d[0], a.1 + d[1], a.2 + d[2]);
+            Vertex {
+                position: add(base.position, sums[0]),
+                normal: add(base.normal, sums[1]),
+                uv: base.uv,
+                tangent_u: add(base.tangent_u, sums[2]),
+                tangent_v: add(base.tangent_v, sums[3]),
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut mesh = morph_mesh();
+        assert_eq!(mesh.apply(), mesh.base);
+
+        mesh.weights[0] = 0.5;
+        let morphed = mesh.apply();
+        assert_eq!(morphed[0].position, (0.0, 0.5, 0.0));
+        assert_eq!(morphed[1].position, (1.0, 1.0, 0.0));
+        assert_eq!(morphed[1].normal, (0.0, 0.0, 1.0));
+
+        let tilt = mesh.find_target("tilt").unwrap();
+        mesh.weights[tilt] = 1.0;
+        let morphed = mesh.apply();
+        assert_eq!(morphed[0].normal, (1.0, 0.0, 1.0));
+        assert_eq!(morphed[0].tangent_u, (1.0, 0.0, -1.0));
+        assert_eq!(morphed[0].tangent_v, (0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_delta_data_matches_apply() {
+        let mut mesh = morph_mesh();
+        for &weights in &[[0.0, 0.0], [1.0, 0.0], [0.25, 0.75], [-0.5, 2.0]] {
+            mesh.weights = weights.to_vec();
+            assert_eq!(apply_from_delta_data(&mesh), mesh.apply());
+        }
+    }
+}
@@ -4,6 +4,7 @@ use std::io::prelude::*;
 use cgmath::*;
 
 use geometry::*;
+use geometry::subdivision::PolyMesh;
 
 #[derive(PartialEq, Debug)]
 pub struct ObjModel {
@@ -31,16 +32,31 @@ impl ObjModel {
     }
 
     pub fn from_file(filename: &str) -> Self {
-        let mut f = File::open(filename).expect("File not found!");
+        ObjModel::load(filename).unwrap_or_else(|err| panic!("Failed to load {}: {}!", filename, err))
+    }
+
+    /// Reads and parses an OBJ file, checking that faces only refer to
+    /// vertices the file has.
+    pub fn load(filename: &str) -> Result<ObjModel, String> {
         let mut contents = String::new();
-        f.read_to_string(&mut contents).expect("Error reading file!");
-        ObjModel::parse(contents.as_str())
+        File::open(filename)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|err| err.to_string())?;
+        let model = ObjModel::try_parse(contents.as_str())?;
+        model.check_indices()?;
+        Ok(model)
     }
 
     pub fn parse(input: &str) -> Self {
+        ObjModel::try_parse(input).unwrap_or_else(|err| panic!("{}!", err))
+    }
+
+    /// Like `parse`, but returns the first malformed line as an error.
+    pub fn try_parse(input: &str) -> Result<ObjModel, String> {
         let mut model = ObjModel::new();
-        for line in input.lines() {
-            match ObjModel::parse_line(line) {
+        for (number, line) in input.lines().enumerate() {
+            let parsed = ObjModel::parse_line(line).map_err(|err| format!("{} on line {}", err, number + 1))?;
+            match parsed {
                 Some(ObjModelLine::VertexPos(x, y, z, w)) => {
                     model.v.push((x, y, z, w));
                 },
@@ -56,82 +72,87 @@ impl ObjModel {
                 _ => {}
             }
         }
-        model
+        Ok(model)
     }
 
-    fn parse_line(line: &str) -> Option<ObjModelLine> {
+    fn parse_line(line: &str) -> Result<Option<ObjModelLine>, String> {
         let mut parts = line.split_whitespace();
+        let parse_floats = |parts: ::std::str::SplitWhitespace| -> Result<Vec<f32>, String> {
+            parts.map(|part| part.parse().map_err(|_| format!("Couldn't parse float {:?}", part))).collect()
+        };
         match parts.next() {
             Some("v") => {
-                let float_parts: Vec<f32> = parts.map(|part| {
-                    part.parse().expect("Couldn't parse float!")
-                }).collect();
+                let float_parts = parse_floats(parts)?;
                 match (float_parts.get(0), float_parts.get(1), float_parts.get(2), float_parts.get(3)) {
                     (Some(x), Some(y), Some(z), Some(w)) => {
-                        Some(ObjModelLine::VertexPos(*x, *y, *z, *w))
+                        Ok(Some(ObjModelLine::VertexPos(*x, *y, *z, *w)))
                     },
                     (Some(x), Some(y), Some(z), None) => {
-                        Some(ObjModelLine::VertexPos(*x, *y, *z, 1.0))
-                    },
-                    _ => {
-                        // TODO: Better error handling
-                        panic!("Invalid vertex!");
+                        Ok(Some(ObjModelLine::VertexPos(*x, *y, *z, 1.0)))
                     },
+                    _ => Err("Invalid vertex".to_string()),
                 }
             },
             Some("vt") => {
-                let float_parts: Vec<f32> = parts.map(|part| {
-                    part.parse().expect("Couldn't parse float!")
-                }).collect();
+                let float_parts = parse_floats(parts)?;
                 match (float_parts.get(0), float_parts.get(1)) {
                     (Some(u), Some(v)) => {
-                        Some(ObjModelLine::VertexUv(*u, *v))
-                    },
-                    _ => {
-                        // TODO: Better error handling
-                        panic!("Invalid texture coordinates!");
+                        Ok(Some(ObjModelLine::VertexUv(*u, *v)))
                     },
+                    _ => Err("Invalid texture coordinates".to_string()),
                 }
             },
             Some("vn") => {
-                let float_parts: Vec<f32> = parts.map(|part| {
-                    part.parse().expect("Couldn't parse float!")
-                }).collect();
+                let float_parts = parse_floats(parts)?;
                 match (float_parts.get(0), float_parts.get(1), float_parts.get(2)) {
                     (Some(x), Some(y), Some(z)) => {
-                        Some(ObjModelLine::VertexNormal(*x, *y, *z))
-                    },
-                    _ => {
-                        // TODO: Better error handling
-                        panic!("Invalid vertex!");
+                        Ok(Some(ObjModelLine::VertexNormal(*x, *y, *z)))
                     },
+                    _ => Err("Invalid vertex normal".to_string()),
                 }
             },
             Some("f") => {
-                let indices_parts: Vec<VertexIndices> = parts.map(|part| {
+                let indices_parts: Result<Vec<VertexIndices>, String> = parts.map(|part| {
                     ObjModel::parse_vertex_indices(part)
                 }).collect();
-                Some(ObjModelLine::Face(indices_parts))
+                Ok(Some(ObjModelLine::Face(indices_parts?)))
             },
-            _ => None,
+            _ => Ok(None),
         }
     }
 
-    fn parse_vertex_indices(input: &str) -> VertexIndices {
+    fn parse_vertex_indices(input: &str) -> Result<VertexIndices, String> {
         let parts: Vec<Option<usize>> = input.split('/').map(|part| {
             if part.len() > 0 {
-                Some(part.parse().expect("Couldn't parse usize!"))
+                part.parse().map(Some).map_err(|_| format!("Couldn't parse index {:?}", part))
             } else {
-                None
+                Ok(None)
             }
-        }).collect();
-        VertexIndices {
-            v: parts.get(0).unwrap().unwrap(),
-            vt: *parts.get(1).unwrap(),
-            vn: parts.get(2).unwrap().unwrap(),
+        }).collect::<Result<_, _>>()?;
+        match (parts.get(0), parts.get(1), parts.get(2)) {
+            (Some(&Some(v)), Some(&vt), Some(&Some(vn))) => Ok(VertexIndices { v, vt, vn }),
+            _ => Err(format!("Invalid face vertex {:?}", input)),
         }
     }
 
+    // Faces need at least three corners, and OBJ indices start at 1.
+    fn check_indices(&self) -> Result<(), String> {
+        let in_range = |index: usize, len: usize| index >= 1 && index <= len;
+        for face in &self.f {
+            if face.len() < 3 {
+                return Err("Face with fewer than three vertices".to_string());
+            }
+            for indices in face {
+                if !in_range(indices.v, self.v.len())
+                    || !in_range(indices.vn, self.vn.len())
+                    || !indices.vt.map_or(true, |vt| in_range(vt, self.vt.len())) {
+                    return Err(format!("Face refers to a missing vertex: {:?}", indices));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn vertices(&self) -> Vec<Vertex> {
         let mut verts = vec![];
         for face in &self.f {
@@ -171,6 +192,28 @@ impl ObjModel {
         verts
     }
 
+    /// Converts the model into a mesh with shared vertices, e.g. for subdivision.
+    /// Normals are dropped, since they are recomputed from the refined surface.
+    pub fn poly_mesh(&self) -> PolyMesh {
+        let mut mesh = PolyMesh::new();
+        mesh.positions = self.v.iter()
+            .map(|&(x, y, z, _)| Point3 { x, y, z })
+            .collect();
+        mesh.uvs = self.vt.iter()
+            .map(|&(u, v)| Point2 { x: u, y: v })
+            .collect();
+        // Corners without texture coordinates share a default one.
+        let default_uv = mesh.uvs.len();
+        mesh.uvs.push(Point2 { x: 0.0, y: 0.0 });
+        for face in &self.f {
+            mesh.faces.push(face.iter().map(|indices| indices.v - 1).collect());
+            mesh.face_uvs.push(face.iter().map(|indices| {
+                indices.vt.map(|i| i - 1).unwrap_or(default_uv)
+            }).collect());
+        }
+        mesh
+    }
+
     fn lookup_vertex_indices(&self, indices: VertexIndices) -> ModelVertex {
         let pos = self.v.get(indices.v - 1).unwrap();
         let normal = self.vn.get(indices.vn - 1).unwrap();
@@ -212,23 +255,37 @@ mod tests {
 
     #[test]
     fn test_parse_line() {
-        assert_eq!(ObjModel::parse_line("v 1.0 2.0 3.0"), Some(ObjModelLine::VertexPos(1.0, 2.0, 3.0, 1.0)));
-        assert_eq!(ObjModel::parse_line("v 1.0 2.0 3.0 4.0"), Some(ObjModelLine::VertexPos(1.0, 2.0, 3.0, 4.0)));
+        assert_eq!(ObjModel::parse_line("v 1.0 2.0 3.0"), Ok(Some(ObjModelLine::VertexPos(1.0, 2.0, 3.0, 1.0))));
+        assert_eq!(ObjModel::parse_line("v 1.0 2.0 3.0 4.0"), Ok(Some(ObjModelLine::VertexPos(1.0, 2.0, 3.0, 4.0))));
 
-        assert_eq!(ObjModel::parse_line("vt 1.0 2.0"), Some(ObjModelLine::VertexUv(1.0, 2.0)));
+        assert_eq!(ObjModel::parse_line("vt 1.0 2.0"), Ok(Some(ObjModelLine::VertexUv(1.0, 2.0))));
 
-        assert_eq!(ObjModel::parse_line("vn 1.0 2.0 3.0"), Some(ObjModelLine::VertexNormal(1.0, 2.0, 3.0)));
+        assert_eq!(ObjModel::parse_line("vn 1.0 2.0 3.0"), Ok(Some(ObjModelLine::VertexNormal(1.0, 2.0, 3.0))));
 
-        assert_eq!(ObjModel::parse_line("f 1/2/3 4/5/6 7/8/9"), Some(ObjModelLine::Face(vec![
+        assert_eq!(ObjModel::parse_line("f 1/2/3 4/5/6 7/8/9"), Ok(Some(ObjModelLine::Face(vec![
             VertexIndices { v: 1, vt: Some(2), vn: 3 },
             VertexIndices { v: 4, vt: Some(5), vn: 6 },
             VertexIndices { v: 7, vt: Some(8), vn: 9 },
-        ])));
-        assert_eq!(ObjModel::parse_line("f 1//3 4//6 7//9"), Some(ObjModelLine::Face(vec![
+        ]))));
+        assert_eq!(ObjModel::parse_line("f 1//3 4//6 7//9"), Ok(Some(ObjModelLine::Face(vec![
             VertexIndices { v: 1, vt: None, vn: 3 },
             VertexIndices { v: 4, vt: None, vn: 6 },
             VertexIndices { v: 7, vt: None, vn: 9 },
-        ])));
+        ]))));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(ObjModel::try_parse("v 1.0 2.0 3.0\nv 1.0 x 3.0"), Err("Couldn't parse float \"x\" on line 2".to_string()));
+        assert_eq!(ObjModel::try_parse("vt 1.0"), Err("Invalid texture coordinates on line 1".to_string()));
+        assert!(ObjModel::try_parse("f 1/2").is_err());
+
+        let missing = ObjModel::try_parse("v 0.0 0.0 0.0\nvn 0.0 0.0 1.0\nf 1//1 2//1 1//1").unwrap();
+        assert!(missing.check_indices().is_err());
+        let degenerate = ObjModel::try_parse("v 0.0 0.0 0.0\nvn 0.0 0.0 1.0\nf 1//1 1//1").unwrap();
+        assert!(degenerate.check_indices().is_err());
+        let valid = ObjModel::try_parse("v 0.0 0.0 0.0\nvn 0.0 0.0 1.0\nf 1//1 1//1 1//1").unwrap();
+        assert_eq!(valid.check_indices(), Ok(()));
     }
 
     #[test]
@@ -379,4 +436,28 @@ f 7821//5 528//6 48//7 529//8
         ];
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_poly_mesh() {
+        let obj = ObjModel::parse(r#"
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 1.0 1.0 0.0
+v 0.0 1.0 0.0
+vt 0.0 0.0
+vt 1.0 1.0
+vn 0.0 0.0 1.0
+f 1/1/1 2//1 3/2/1 4//1
+"#);
+        let mesh = obj.poly_mesh();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[2], Point3 { x: 1.0, y: 1.0, z: 0.0 });
+        assert_eq!(mesh.uvs, vec![
+            Point2 { x: 0.0, y: 0.0 },
+            Point2 { x: 1.0, y: 1.0 },
+            Point2 { x: 0.0, y: 0.0 },
+        ]);
+        assert_eq!(mesh.faces, vec![vec![0, 1, 2, 3]]);
+        assert_eq!(mesh.face_uvs, vec![vec![0, 2, 1, 2]]);
+    }
 }
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use vulkano::buffer::BufferAccess;
+use vulkano::framebuffer::Subpass;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::pipeline::GraphicsPipelineCreationError;
+use vulkano::pipeline::blend::AttachmentBlend;
+use vulkano::pipeline::depth_stencil::Compare;
+use vulkano::pipeline::depth_stencil::DepthStencil;
+use vulkano::pipeline::input_assembly::PrimitiveTopology;
+use vulkano::pipeline::raster::CullMode;
+use vulkano::pipeline::raster::DepthBias;
+use vulkano::pipeline::raster::DepthBiasControl;
+use vulkano::pipeline::raster::FrontFace;
+use vulkano::pipeline::raster::PolygonMode;
+use vulkano::pipeline::shader::GraphicsEntryPointAbstract;
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::vertex::SingleBufferDefinition;
+use vulkano::pipeline::vertex::Vertex;
+use vulkano::pipeline::vertex::VertexDefinition;
+use vulkano::pipeline::vertex::VertexSource;
+
+use graphics::Graphics;
+
+/// Configures the fixed function state of a graphics pipeline drawing into
+/// the first subpass of `Graphics::renderpass`.
+///
+/// The defaults match `Graphics::create_pipeline`: filled triangle lists,
+/// no culling, a `Less` depth test with writes, no blending and a dynamic
+/// viewport.
+#[derive(Debug, Clone)]
+pub struct PipelineBuilder {
+    topology: PrimitiveTopology,
+    cull_mode: CullMode,
+    front_face: FrontFace,
+    polygon_mode: PolygonMode,
+    depth_test: bool,
+    depth_write: bool,
+    depth_compare: Compare,
+    blend: AttachmentBlend,
+    dynamic_scissor: bool,
+    line_width: Option<f32>,
+    depth_bias: DepthBiasControl,
+}
+
+impl PipelineBuilder {
+    pub fn new() -> PipelineBuilder {
+        PipelineBuilder {
+            topology: PrimitiveTopology::TriangleList,
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            polygon_mode: PolygonMode::Fill,
+            depth_test: true,
+            depth_write: true,
+            depth_compare: Compare::Less,
+            blend: AttachmentBlend::pass_through(),
+            dynamic_scissor: false,
+            line_width: Some(1.0),
+            depth_bias: DepthBiasControl::Disabled,
+        }
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> PipelineBuilder {
+        self.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullMode) -> PipelineBuilder {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> PipelineBuilder {
+        self.front_face = front_face;
+        self
+    }
+
+    /// `Line` and `Point` need the `fill_mode_non_solid` device feature.
+    pub fn polygon_mode(mut self, polygon_mode: PolygonMode) -> PipelineBuilder {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Draws edges only. Shorthand for `polygon_mode(PolygonMode::Line)`.
+    pub fn wireframe(self) -> PipelineBuilder {
+        self.polygon_mode(PolygonMode::Line)
+    }
+
+    /// Whether fragments are tested against the depth buffer. Without the test
+    /// nothing is written to the depth buffer either.
+    pub fn depth_test(mut self, depth_test: bool) -> PipelineBuilder {
+        self.depth_test = depth_test;
+        self
+    }
+
+    pub fn depth_write(mut self, depth_write: bool) -> PipelineBuilder {
+        self.depth_write = depth_write;
+        self
+    }
+
+    /// Use `Greater` with cameras using reversed Z.
+    pub fn depth_compare(mut self, depth_compare: Compare) -> PipelineBuilder {
+        self.depth_compare = depth_compare;
+        self
+    }
+
+    /// Blend state of the color attachment.
+    pub fn blend(mut self, blend: AttachmentBlend) -> PipelineBuilder {
+        self.blend = blend;
+        self
+    }
+
+    /// Blends with the source alpha, for transparent surfaces.
+    pub fn alpha_blending(self) -> PipelineBuilder {
+        self.blend(AttachmentBlend::alpha_blending())
+    }
+
+    /// Makes the scissor box part of the dynamic state, so it has to be set
+    /// in the `DynamicState` of every draw.
+    pub fn dynamic_scissor(mut self) -> PipelineBuilder {
+        self.dynamic_scissor = true;
+        self
+    }
+
+    /// Widths other than 1 need the `wide_lines` device feature.
+    pub fn line_width(mut self, line_width: f32) -> PipelineBuilder {
+        self.line_width = Some(line_width);
+        self
+    }
+
+    /// Makes the line width part of the dynamic state, so it has to be set in
+    /// the `DynamicState` of every draw.
+    pub fn dynamic_line_width(mut self) -> PipelineBuilder {
+        self.line_width = None;
+        self
+    }
+
+    /// Offsets the depth of fragments by `constant` plus `slope` times the
+    /// depth slope of the polygon, e.g. against shadow acne or z-fighting
+    /// decals. A `clamp` other than 0 limits the offset, and needs the
+    /// `depth_bias_clamp` device feature.
+    pub fn depth_bias(mut self, constant: f32, clamp: f32, slope: f32) -> PipelineBuilder {
+        self.depth_bias = DepthBiasControl::Static(DepthBias { constant_factor: constant, clamp, slope_factor: slope });
+        self
+    }
+
+    /// Makes the depth bias part of the dynamic state, so it has to be set in
+    /// the `DynamicState` of every draw.
+    pub fn dynamic_depth_bias(mut self) -> PipelineBuilder {
+        self.depth_bias = DepthBiasControl::Dynamic;
+        self
+    }
+
+    /// Creates the pipeline for a single vertex buffer of `T`.
+    pub fn build<T, V, F>(&self, graphics: &Graphics, vs: V, fs: F) -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, GraphicsPipelineCreationError>
+        where T: Vertex,
+              V: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        self.build_specialized::<T, V, F>(graphics, vs, (), fs, ())
+    }
+
+    /// Like `build`, with values for the specialization constants of the
+    /// vertex and fragment shaders.
+    pub fn build_specialized<T, V, F>(&self, graphics: &Graphics, vs: V, vs_constants: V::SpecializationConstants, fs: F, fs_constants: F::SpecializationConstants)
+                                      -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, GraphicsPipelineCreationError>
+        where T: Vertex,
+              V: GraphicsEntryPointAbstract,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        self.build_with_input(graphics, SingleBufferDefinition::<T>::new(), vs, vs_constants, fs, fs_constants)
+    }
+
+    /// Creates the pipeline for instanced drawing, with vertices of `T` in
+    /// the first buffer and per-instance data of `I`, e.g. `InstanceData`, in
+    /// the second. Attributes are matched to the shader inputs by name.
+    pub fn build_instanced<T, I, V, F>(&self, graphics: &Graphics, vs: V, fs: F) -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, GraphicsPipelineCreationError>
+        where T: Vertex,
+              I: Vertex,
+              V: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract<SpecializationConstants = ()>,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        self.build_with_input(graphics, OneVertexOneInstanceDefinition::<T, I>::new(), vs, (), fs, ())
+    }
+
+    /// Like `build_specialized`, with any vertex input layout.
+    pub fn build_with_input<D, V, F>(&self, graphics: &Graphics, input: D, vs: V, vs_constants: V::SpecializationConstants, fs: F, fs_constants: F::SpecializationConstants)
+                                     -> Result<Arc<GraphicsPipelineAbstract + Send + Sync>, GraphicsPipelineCreationError>
+        where D: VertexDefinition<V::InputDefinition> + VertexSource<Vec<Arc<BufferAccess + Send + Sync>>> + Send + Sync + 'static,
+              V: GraphicsEntryPointAbstract,
+              V::PipelineLayout: Clone + 'static + Send + Sync,
+              F: GraphicsEntryPointAbstract,
+              F::PipelineLayout: Clone + 'static + Send + Sync {
+        let mut builder = GraphicsPipeline::start()
+            .vertex_input(input)
+            .vertex_shader(vs, vs_constants)
+            .primitive_topology(self.topology)
+            .fragment_shader(fs, fs_constants)
+            .blend_collective(self.blend.clone())
+            .depth_stencil(self.depth_stencil());
+        builder = if self.dynamic_scissor {
+            builder.viewports_scissors_dynamic(1)
+        } else {
+            builder.viewports_dynamic_scissors_irrelevant(1)
+        };
+        builder = match self.cull_mode {
+            CullMode::None => builder.cull_mode_disabled(),
+            CullMode::Front => builder.cull_mode_front(),
+            CullMode::Back => builder.cull_mode_back(),
+            CullMode::FrontAndBack => builder.cull_mode_front_and_back(),
+        };
+        builder = match self.front_face {
+            FrontFace::CounterClockwise => builder.front_face_counter_clockwise(),
+            FrontFace::Clockwise => builder.front_face_clockwise(),
+        };
+        builder = match self.polygon_mode {
+            PolygonMode::Fill => builder.polygon_mode_fill(),
+            PolygonMode::Line => builder.polygon_mode_line(),
+            PolygonMode::Point => builder.polygon_mode_point(),
+        };
+        builder = match self.line_width {
+            Some(width) => builder.line_width(width),
+            None => builder.line_width_dynamic(),
+        };
+        builder = match self.depth_bias {
+            DepthBiasControl::Disabled => builder,
+            DepthBiasControl::Static(bias) => builder.depth_bias(bias.constant_factor, bias.clamp, bias.slope_factor),
+            DepthBiasControl::Dynamic => builder.depth_bias_dynamic(),
+        };
+        let pipeline = builder
+            .render_pass(Subpass::from(graphics.renderpass.clone(), 0).unwrap())
+            .build(graphics.device.clone())?;
+        Ok(Arc::new(pipeline))
+    }
+
+    fn depth_stencil(&self) -> DepthStencil {
+        if !self.depth_test {
+            return DepthStencil::disabled();
+        }
+        DepthStencil {
+            depth_write: self.depth_write,
+            depth_compare: self.depth_compare,
+            .. DepthStencil::simple_depth_test()
+        }
+    }
+}
+
+/// A map that creates each pipeline once per key, and nothing more: it
+/// doesn't know which shaders or constants a pipeline was created from. The
+/// key has to tell apart everything the pipelines differ in, e.g. the
+/// specialization constants when all variants come from one shader pair.
+///
+/// Specialization constant structs with float members can't be hashed; use
+/// a key of their integer or boolean members instead.
+pub struct PipelineVariants<K, P = Arc<GraphicsPipelineAbstract + Send + Sync>> {
+    pipelines: HashMap<K, P>,
+}
+
+impl<K: Eq + Hash, P: Clone> PipelineVariants<K, P> {
+    pub fn new() -> PipelineVariants<K, P> {
+        PipelineVariants { pipelines: HashMap::new() }
+    }
+
+    /// Returns the pipeline created for `key`, calling `create` if there is
+    /// none yet.
+    pub fn get_or_create<C>(&mut self, key: K, create: C) -> P
+        where C: FnOnce(&K) -> P {
+        match self.pipelines.entry(key) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let pipeline = create(entry.key());
+                entry.insert(pipeline).clone()
+            },
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<P> {
+        self.pipelines.get(key).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Drops all variants, e.g. after the render pass was recreated.
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let builder = PipelineBuilder::new();
+        assert_eq!(builder.topology, PrimitiveTopology::TriangleList);
+        assert_eq!(builder.cull_mode, CullMode::None);
+        assert_eq!(builder.front_face, FrontFace::CounterClockwise);
+        assert_eq!(builder.polygon_mode, PolygonMode::Fill);
+        assert_eq!(builder.blend, AttachmentBlend::pass_through());
+        assert!(!builder.dynamic_scissor);
+        assert_eq!(builder.line_width, Some(1.0));
+        match builder.depth_bias {
+            DepthBiasControl::Disabled => (),
+            other => panic!("unexpected depth bias {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_setters() {
+        let builder = PipelineBuilder::new()
+            .topology(PrimitiveTopology::LineStrip)
+            .cull_mode(CullMode::Back)
+            .front_face(FrontFace::Clockwise)
+            .wireframe()
+            .alpha_blending()
+            .dynamic_scissor()
+            .line_width(2.0);
+        assert_eq!(builder.topology, PrimitiveTopology::LineStrip);
+        assert_eq!(builder.cull_mode, CullMode::Back);
+        assert_eq!(builder.front_face, FrontFace::Clockwise);
+        assert_eq!(builder.polygon_mode, PolygonMode::Line);
+        assert_eq!(builder.blend, AttachmentBlend::alpha_blending());
+        assert!(builder.dynamic_scissor);
+        assert_eq!(builder.line_width, Some(2.0));
+        assert_eq!(builder.dynamic_line_width().line_width, None);
+    }
+
+    #[test]
+    fn test_depth_bias() {
+        match PipelineBuilder::new().depth_bias(1.25, 0.0, 1.75).depth_bias {
+            DepthBiasControl::Static(bias) => {
+                assert_eq!(bias.constant_factor, 1.25);
+                assert_eq!(bias.clamp, 0.0);
+                assert_eq!(bias.slope_factor, 1.75);
+            },
+            other => panic!("unexpected depth bias {:?}", other),
+        }
+        match PipelineBuilder::new().depth_bias(1.25, 0.0, 1.75).dynamic_depth_bias().depth_bias {
+            DepthBiasControl::Dynamic => (),
+            other => panic!("unexpected depth bias {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_depth_stencil() {
+        let default = PipelineBuilder::new().depth_stencil();
+        assert!(default.depth_write);
+        assert_eq!(default.depth_compare, Compare::Less);
+
+        let overlay = PipelineBuilder::new().depth_compare(Compare::GreaterOrEqual).depth_write(false).depth_stencil();
+        assert!(!overlay.depth_write);
+        assert_eq!(overlay.depth_compare, Compare::GreaterOrEqual);
+
+        let disabled = PipelineBuilder::new().depth_test(false).depth_stencil();
+        assert!(!disabled.depth_write);
+        assert_eq!(disabled.depth_compare, Compare::Always);
+    }
+
+    #[test]
+    fn test_variants_are_created_once_per_key() {
+        let mut variants: PipelineVariants<(u32, bool), u32> = PipelineVariants::new();
+        let mut created = 0;
+        assert_eq!(variants.get_or_create((4, true), |_| { created += 1; 10 }), 10);
+        assert_eq!(variants.get_or_create((4, true), |_| { created += 1; 20 }), 10);
+        assert_eq!(created, 1);
+
+        assert_eq!(variants.get_or_create((4, false), |&(count, _)| { created += 1; count }), 4);
+        assert_eq!(created, 2);
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants.get(&(4, false)), Some(4));
+        assert_eq!(variants.get(&(5, false)), None);
+
+        variants.clear();
+        assert!(variants.is_empty());
+        assert_eq!(variants.get_or_create((4, true), |_| { created += 1; 30 }), 30);
+        assert_eq!(created, 3);
+    }
+}
@@ -0,0 +1,203 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use vulkano::OomError;
+use vulkano::device::Device;
+use vulkano::instance::PhysicalDevice;
+use vulkano::pipeline::cache::PipelineCache;
+
+/// Length of the header Vulkan puts in front of pipeline cache data.
+const HEADER_LENGTH: usize = 32;
+/// `VK_PIPELINE_CACHE_HEADER_VERSION_ONE`.
+const HEADER_VERSION: u32 = 1;
+
+/// Identifies the device and driver pipeline cache data was created by.
+/// Data is only valid for the exact same device and driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub uuid: [u8; 16],
+}
+
+impl CacheKey {
+    pub fn from_physical(physical: PhysicalDevice) -> CacheKey {
+        CacheKey {
+            vendor_id: physical.pci_vendor_id(),
+            device_id: physical.pci_device_id(),
+            driver_version: physical.driver_version(),
+            uuid: *physical.uuid(),
+        }
+    }
+
+    /// Name of the cache file, so that devices and driver versions don't
+    /// overwrite each other's data.
+    pub fn file_name(&self) -> String {
+        format!("pipelines-{:04x}-{:04x}-{:08x}.bin", self.vendor_id, self.device_id, self.driver_version)
+    }
+
+    /// Whether `data` starts with a header written for this device. Drivers
+    /// are supposed to reject mismatching data themselves, but not all do.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        if data.len() < HEADER_LENGTH {
+            return false;
+        }
+        let word = |i: usize| {
+            (data[i] as u32) | (data[i + 1] as u32) << 8 | (data[i + 2] as u32) << 16 | (data[i + 3] as u32) << 24
+        };
+        word(0) as usize >= HEADER_LENGTH && word(4) == HEADER_VERSION &&
+            word(8) == self.vendor_id && word(12) == self.device_id &&
+            data[16..32] == self.uuid
+    }
+}
+
+/// Error that can happen when saving a `PipelineCacheFile`.
+#[derive(Debug)]
+pub enum PipelineCacheError {
+    Io(io::Error),
+    Oom(OomError),
+}
+
+impl fmt::Display for PipelineCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PipelineCacheError::Io(ref err) => write!(f, "failed to write pipeline cache: {}", err),
+            PipelineCacheError::Oom(ref err) => write!(f, "failed to get pipeline cache data: {}", err),
+        }
+    }
+}
+
+impl error::Error for PipelineCacheError {
+    fn description(&self) -> &str {
+        "failed to save pipeline cache"
+    }
+}
+
+impl From<io::Error> for PipelineCacheError {
+    fn from(err: io::Error) -> PipelineCacheError { PipelineCacheError::Io(err) }
+}
+
+impl From<OomError> for PipelineCacheError {
+    fn from(err: OomError) -> PipelineCacheError { PipelineCacheError::Oom(err) }
+}
+
+/// A pipeline cache persisted in a file of a cache directory.
+///
+/// `Graphics` opens one when it is built, unless disabled with
+/// `GraphicsBuilder::pipeline_cache`, and saves it when dropped.
+pub struct PipelineCacheFile {
+    path: PathBuf,
+    cache: Arc<PipelineCache>,
+}
+
+impl PipelineCacheFile {
+    /// Loads the cache for `device` from `dir`. Starts with an empty cache if
+    /// there is no file yet, or if it was written by another device or driver.
+    pub fn open<P: AsRef<Path>>(device: Arc<Device>, dir: P) -> Result<PipelineCacheFile, OomError> {
+        let key = CacheKey::from_physical(device.physical_device());
+        let path = dir.as_ref().join(key.file_name());
+        let data = match read(&path) {
+            Ok(data) => Some(data),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                warn!("Failed to read pipeline cache {}: {}", path.display(), err);
+                None
+            },
+        };
+        let cache = match data {
+            Some(ref data) if key.matches(data) => {
+                info!("Loaded pipeline cache from {}", path.display());
+                unsafe { PipelineCache::with_data(device, data)? }
+            },
+            Some(_) => {
+                info!("Discarding pipeline cache {} from another device or driver", path.display());
+                PipelineCache::empty(device)?
+            },
+            None => PipelineCache::empty(device)?,
+        };
+        Ok(PipelineCacheFile { path, cache })
+    }
+
+    pub fn cache(&self) -> Arc<PipelineCache> {
+        self.cache.clone()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the cache to its file, creating the directory if needed. The
+    /// data goes to a temporary file first, so that a crash while saving
+    /// can't leave a truncated cache behind.
+    pub fn save(&self) -> Result<(), PipelineCacheError> {
+        let data = self.cache.get_data()?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, &data)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Directory for caches, `$XDG_CACHE_HOME/<application>` or
+/// `~/.cache/<application>`.
+pub fn cache_dir(application: &str) -> Option<PathBuf> {
+    let base = ::std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| ::std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")));
+    base.map(|base| base.join(application))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> CacheKey {
+        CacheKey { vendor_id: 0x10de, device_id: 0x1b80, driver_version: 0x5a0c4000, uuid: [7; 16] }
+    }
+
+    fn header(vendor_id: u32, device_id: u32, uuid: [u8; 16]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &word in &[HEADER_LENGTH as u32, HEADER_VERSION, vendor_id, device_id] {
+            data.extend_from_slice(&[word as u8, (word >> 8) as u8, (word >> 16) as u8, (word >> 24) as u8]);
+        }
+        data.extend_from_slice(&uuid);
+        data.extend_from_slice(&[1, 2, 3]);
+        data
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(key().file_name(), "pipelines-10de-1b80-5a0c4000.bin");
+    }
+
+    #[test]
+    fn test_matches() {
+        let key = key();
+        assert!(key.matches(&header(0x10de, 0x1b80, [7; 16])));
+        assert!(!key.matches(&header(0x1002, 0x1b80, [7; 16])));
+        assert!(!key.matches(&header(0x10de, 0x1b81, [7; 16])));
+        assert!(!key.matches(&header(0x10de, 0x1b80, [8; 16])));
+        assert!(!key.matches(&header(0x10de, 0x1b80, [7; 16])[..20]));
+        assert!(!key.matches(&[]));
+
+        let mut version = header(0x10de, 0x1b80, [7; 16]);
+        version[4] = 2;
+        assert!(!key.matches(&version));
+    }
+}
@@ -0,0 +1,373 @@
+use std::cell::Cell;
+use std::sync::Arc;
+
+use cgmath::*;
+use vulkano::buffer::BufferAccess;
+
+use camera::{Camera, CameraController, SharedViewport};
+
+/// A local transform, applied as scale, then rotation, then translation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Transform {
+        Transform { translation, .. Transform::identity() }
+    }
+
+    /// A transform at `eye` whose -Z axis points at `target`, as for a camera.
+    pub fn looking_at(eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Transform {
+        Transform {
+            translation: eye.to_vec(),
+            rotation: Quaternion::look_at(eye - target, up).invert(),
+            .. Transform::identity()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Light {
+    /// Emits in all directions from the node's origin.
+    Point { intensity: Vector3<f32> },
+
+    /// Emits along the node's -Z axis from infinitely far away.
+    Directional { intensity: Vector3<f32> },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+pub struct Node {
+    pub name: String,
+
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+
+    // Cached world matrix, valid unless `dirty` is set. A clean node always
+    // has a clean parent, so marking a subtree dirty can stop at dirty nodes.
+    world: Cell<Matrix4<f32>>,
+    dirty: Cell<bool>,
+
+    // Attachments
+    pub mesh: Option<Arc<BufferAccess + Send + Sync>>,
+    pub light: Option<Light>,
+    pub camera: Option<Camera>,
+}
+
+impl Node {
+    pub fn transform(&self) -> &Transform {
+        &self.local
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// A hierarchy of nodes with local transforms. World matrices are computed
+/// lazily and cached until a transform above them changes.
+pub struct Scene {
+    nodes: Vec<Node>,
+    viewport: Option<SharedViewport>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene { nodes: vec![], viewport: None }
+    }
+
+    pub fn add_node(&mut self, name: &str, parent: Option<NodeId>, transform: Transform) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            name: name.to_string(),
+            local: transform,
+            parent,
+            children: vec![],
+            world: Cell::new(Matrix4::identity()),
+            dirty: Cell::new(true),
+            mesh: None,
+            light: None,
+            camera: None,
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(id);
+        }
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    /// Gives access to a node's name and attachments. Use `set_transform` and
+    /// `set_parent` to move it.
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> + '_ {
+        self.nodes.iter().enumerate().map(|(i, node)| (NodeId(i), node))
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes().filter(|&(_, node)| node.parent.is_none()).map(|(id, _)| id)
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes().find(|&(_, node)| node.name == name).map(|(id, _)| id)
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) {
+        self.nodes[id.0].local = transform;
+        self.mark_dirty(id);
+    }
+
+    pub fn update_transform<F>(&mut self, id: NodeId, f: F) where F: FnOnce(&mut Transform) {
+        f(&mut self.nodes[id.0].local);
+        self.mark_dirty(id);
+    }
+
+    /// Moves a node under a new parent, or makes it a root. Its local transform
+    /// is kept, so its world transform changes with the parent.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let mut ancestor = parent;
+        while let Some(a) = ancestor {
+            if a == id {
+                panic!("Node can't be parented to its own descendant!");
+            }
+            ancestor = self.nodes[a.0].parent;
+        }
+
+        if let Some(old_parent) = self.nodes[id.0].parent {
+            self.nodes[old_parent.0].children.retain(|&child| child != id);
+        }
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(id);
+        }
+        self.nodes[id.0].parent = parent;
+        self.mark_dirty(id);
+    }
+
+    pub fn world_matrix(&self, id: NodeId) -> Matrix4<f32> {
+        let node = &self.nodes[id.0];
+        if node.dirty.get() {
+            let local = node.local.matrix();
+            let world = match node.parent {
+                Some(parent) => self.world_matrix(parent) * local,
+                None => local,
+            };
+            node.world.set(world);
+            node.dirty.set(false);
+        }
+        node.world.get()
+    }
+
+    /// The view matrix for a camera attached to the given node.
+    pub fn view_matrix(&self, id: NodeId) -> Matrix4<f32> {
+        self.world_matrix(id).invert().expect("Camera node has a degenerate transform!")
+    }
+
+    pub fn meshes(&self) -> impl Iterator<Item = (Matrix4<f32>, &Arc<BufferAccess + Send + Sync>)> + '_ {
+        self.nodes().filter_map(move |(id, node)| {
+            node.mesh.as_ref().map(|mesh| (self.world_matrix(id), mesh))
+        })
+    }
+
+    pub fn lights(&self) -> impl Iterator<Item = (Matrix4<f32>, Light)> + '_ {
+        self.nodes().filter_map(move |(id, node)| {
+            node.light.map(|light| (self.world_matrix(id), light))
+        })
+    }
+
+    pub fn cameras(&self) -> impl Iterator<Item = (NodeId, Camera)> + '_ {
+        self.nodes().filter_map(move |(id, node)| node.camera.map(|camera| (id, self.fit_viewport(camera))))
+    }
+
+    /// The camera attached to the given node, with the aspect ratio of the
+    /// followed viewport.
+    pub fn camera(&self, id: NodeId) -> Camera {
+        let camera = self.nodes[id.0].camera.expect("Node has no camera attached!");
+        self.fit_viewport(camera)
+    }
+
+    /// Updates the aspect ratio of every camera, e.g. after the swapchain is recreated.
+    pub fn set_viewport(&mut self, dimensions: [u32; 2]) {
+        for node in &mut self.nodes {
+            if let Some(ref mut camera) = node.camera {
+                camera.set_viewport(dimensions);
+            }
+        }
+    }
+
+    /// Keeps the aspect ratio of every camera in sync with `viewport`, e.g.
+    /// `Graphics::viewport`, so that resizes need no `set_viewport` calls.
+    /// `camera` and `cameras` always fit the viewport; the cameras stored in
+    /// the nodes are updated by `sync_viewport`, which `update_camera` calls.
+    pub fn follow_viewport(&mut self, viewport: SharedViewport) {
+        self.viewport = Some(viewport);
+        self.sync_viewport();
+    }
+
+    /// Applies the followed viewport to the camera of every node. Call it once
+    /// per frame when reading `Node::camera` directly without `update_camera`.
+    pub fn sync_viewport(&mut self) {
+        if let Some(dimensions) = self.viewport.as_ref().map(|viewport| viewport.dimensions()) {
+            self.set_viewport(dimensions);
+        }
+    }
+
+    fn fit_viewport(&self, mut camera: Camera) -> Camera {
+        if let Some(ref viewport) = self.viewport {
+            camera.set_viewport(viewport.dimensions());
+        }
+        camera
+    }
+
+    /// Advances a camera controller and applies it to the camera attached to
+    /// the given node. Syncs every camera with the followed viewport first.
+    pub fn update_camera<C>(&mut self, id: NodeId, controller: &mut C, dt: f32) where C: CameraController + ?Sized {
+        self.sync_viewport();
+        {
+            let node = &mut self.nodes[id.0];
+            let camera = node.camera.as_mut().expect("Node has no camera attached!");
+            controller.update(dt, &mut node.local, camera);
+        }
+        self.mark_dirty(id);
+    }
+
+    fn mark_dirty(&self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+            if node.dirty.get() {
+                continue;
+            }
+            node.dirty.set(true);
+            stack.extend(node.children.iter().cloned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Matrix4<f32>, expected: Matrix4<f32>) {
+        let actual: [[f32; 4]; 4] = actual.into();
+        let expected: [[f32; 4]; 4] = expected.into();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((actual[i][j] - expected[i][j]).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform_matrix() {
+        let transform = Transform {
+            translation: Vector3::new(1.0, 2.0, 3.0),
+            rotation: Quaternion::from_angle_z(Deg(90.0)),
+            scale: Vector3::new(2.0, 2.0, 2.0),
+        };
+        let point = transform.matrix() * Vector4::new(1.0, 0.0, 0.0, 1.0);
+        assert!((point - Vector4::new(1.0, 4.0, 3.0, 1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn test_looking_at() {
+        let eye = Point3::new(0.0, 0.4, 2.0);
+        let target = Point3::new(0.0, 0.0, 0.0);
+        let up = Vector3::new(0.0, -1.0, 0.0);
+        let transform = Transform::looking_at(eye, target, up);
+        assert_close(transform.matrix(), Matrix4::look_at(eye, target, up).invert().unwrap());
+    }
+
+    #[test]
+    fn test_world_matrix() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("root", None, Transform::from_translation(Vector3::new(1.0, 0.0, 0.0)));
+        let child = scene.add_node("child", Some(root), Transform::from_translation(Vector3::new(0.0, 1.0, 0.0)));
+        let grandchild = scene.add_node("grandchild", Some(child), Transform::identity());
+
+        assert_close(scene.world_matrix(grandchild), Matrix4::from_translation(Vector3::new(1.0, 1.0, 0.0)));
+
+        // Moving the root invalidates everything below it.
+        scene.update_transform(root, |transform| transform.translation.z = 5.0);
+        assert!(scene.node(child).dirty.get());
+        assert!(scene.node(grandchild).dirty.get());
+        assert_close(scene.world_matrix(grandchild), Matrix4::from_translation(Vector3::new(1.0, 1.0, 5.0)));
+
+        // Moving a leaf leaves its parent cached.
+        scene.set_transform(grandchild, Transform::from_translation(Vector3::new(0.0, 0.0, 1.0)));
+        assert!(!scene.node(child).dirty.get());
+        assert_close(scene.world_matrix(grandchild), Matrix4::from_translation(Vector3::new(1.0, 1.0, 6.0)));
+    }
+
+    #[test]
+    fn test_set_parent() {
+        let mut scene = Scene::new();
+        let a = scene.add_node("a", None, Transform::from_translation(Vector3::new(1.0, 0.0, 0.0)));
+        let b = scene.add_node("b", None, Transform::from_translation(Vector3::new(0.0, 2.0, 0.0)));
+        let c = scene.add_node("c", Some(a), Transform::identity());
+        assert_close(scene.world_matrix(c), Matrix4::from_translation(Vector3::new(1.0, 0.0, 0.0)));
+
+        scene.set_parent(c, Some(b));
+        assert_eq!(scene.node(a).children(), &[] as &[NodeId]);
+        assert_eq!(scene.node(b).children(), &[c]);
+        assert_close(scene.world_matrix(c), Matrix4::from_translation(Vector3::new(0.0, 2.0, 0.0)));
+        assert_eq!(scene.roots().collect::<Vec<_>>(), vec![a, b]);
+        assert_eq!(scene.find("c"), Some(c));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_parent_cycle() {
+        let mut scene = Scene::new();
+        let a = scene.add_node("a", None, Transform::identity());
+        let b = scene.add_node("b", Some(a), Transform::identity());
+        scene.set_parent(a, Some(b));
+    }
+
+    #[test]
+    fn test_follow_viewport() {
+        let mut scene = Scene::new();
+        let camera_node = scene.add_node("camera", None, Transform::identity());
+        scene.node_mut(camera_node).camera = Some(Camera::perspective(Rad(1.0), 0.1, 10.0));
+
+        let viewport = SharedViewport::new([200, 100]);
+        scene.follow_viewport(viewport.clone());
+        assert_eq!(scene.camera(camera_node).aspect, 2.0);
+
+        viewport.set([100, 400]);
+        assert_eq!(scene.camera(camera_node).aspect, 0.25);
+        assert_eq!(scene.cameras().next().unwrap().1.aspect, 0.25);
+
+        // The stored camera catches up once synced.
+        assert_eq!(scene.node(camera_node).camera.unwrap().aspect, 2.0);
+        scene.sync_viewport();
+        assert_eq!(scene.node(camera_node).camera.unwrap().aspect, 0.25);
+    }
+}
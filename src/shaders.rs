@@ -0,0 +1,320 @@
+// Shaders used by the library itself, as opposed to the ones applications
+// bring for their own pipelines.
+
+/// Draws a cube around the camera, at the far plane.
+pub mod skybox_vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 position;
+
+layout(location = 0) out vec3 v_direction;
+
+layout(push_constant) uniform PushConstants {
+    // Projection times the rotation of the view, without its translation.
+    mat4 view_projection;
+    // 1.0, or 0.0 with reversed-Z.
+    float far_depth;
+} push_constants;
+
+void main() {
+    vec4 clip = push_constants.view_projection * vec4(position, 1.0);
+    gl_Position = vec4(clip.xy, push_constants.far_depth * clip.w, clip.w);
+    v_direction = position;
+}
+"]
+    struct Dummy;
+}
+
+pub mod skybox_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec3 v_direction;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform samplerCube skybox;
+
+void main() {
+    f_color = vec4(texture(skybox, normalize(v_direction)).rgb, 1.0);
+}
+"]
+    struct Dummy;
+}
+
+/// Resamples an equirectangular panorama into every face of a cubemap, one
+/// invocation per texel. Dispatch with 6 workgroups along z.
+pub mod equirectangular_cs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) uniform sampler2D panorama;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly imageCube cubemap;
+
+const float PI = 3.14159265359;
+
+// Direction through the center of a texel of a face, following the cube map
+// face selection table of the Vulkan spec.
+vec3 direction(ivec3 texel, int size) {
+    vec2 uv = (vec2(texel.xy) + 0.5) / float(size) * 2.0 - 1.0;
+    switch (texel.z) {
+        case 0: return vec3(1.0, -uv.y, -uv.x);
+        case 1: return vec3(-1.0, -uv.y, uv.x);
+        case 2: return vec3(uv.x, 1.0, uv.y);
+        case 3: return vec3(uv.x, -1.0, -uv.y);
+        case 4: return vec3(uv.x, -uv.y, 1.0);
+        default: return vec3(-uv.x, -uv.y, -1.0);
+    }
+}
+
+void main() {
+    int size = imageSize(cubemap).x;
+    ivec3 texel = ivec3(gl_GlobalInvocationID);
+    if (texel.x >= size || texel.y >= size) {
+        return;
+    }
+    vec3 d = normalize(direction(texel, size));
+    // Longitude wraps around the panorama, latitude runs from +Y at the top
+    // to -Y at the bottom.
+    vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+    imageStore(cubemap, texel, vec4(textureLod(panorama, uv, 0.0).rgb, 1.0));
+}
+"]
+    struct Dummy;
+}
+
+/// Convolves an environment cubemap with a cosine lobe around every texel's
+/// direction, giving the irradiance that reaches a diffuse surface.
+pub mod irradiance_cs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) uniform samplerCube environment;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly imageCube irradiance;
+
+const float PI = 3.14159265359;
+const float STEP = 0.025;
+
+vec3 direction(ivec3 texel, int size) {
+    vec2 uv = (vec2(texel.xy) + 0.5) / float(size) * 2.0 - 1.0;
+    switch (texel.z) {
+        case 0: return vec3(1.0, -uv.y, -uv.x);
+        case 1: return vec3(-1.0, -uv.y, uv.x);
+        case 2: return vec3(uv.x, 1.0, uv.y);
+        case 3: return vec3(uv.x, -1.0, -uv.y);
+        case 4: return vec3(uv.x, -uv.y, 1.0);
+        default: return vec3(-uv.x, -uv.y, -1.0);
+    }
+}
+
+void main() {
+    int size = imageSize(irradiance).x;
+    ivec3 texel = ivec3(gl_GlobalInvocationID);
+    if (texel.x >= size || texel.y >= size) {
+        return;
+    }
+    vec3 n = normalize(direction(texel, size));
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 right = normalize(cross(up, n));
+    up = cross(n, right);
+
+    // Riemann sum over the hemisphere, weighted by cos(theta) for Lambert
+    // and sin(theta) for the area of each step.
+    vec3 sum = vec3(0.0);
+    float count = 0.0;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += STEP) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += STEP) {
+            vec3 t = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 l = t.x * right + t.y * up + t.z * n;
+            sum += textureLod(environment, l, 0.0).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    imageStore(irradiance, texel, vec4(PI * sum / count, 1.0));
+}
+"]
+    struct Dummy;
+}
+
+/// Prefilters an environment cubemap with the GGX distribution for one
+/// roughness, for one mip level of the specular cubemap. Mirrors
+/// `ibl::importance_sample_ggx`. Each sample reads the environment level whose
+/// texels cover about the solid angle of the sample, so environments with a
+/// mip chain filter without bright speckles.
+pub mod prefilter_cs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) uniform samplerCube environment;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly imageCube prefiltered;
+
+layout(push_constant) uniform PushConstants {
+    float roughness;
+} push_constants;
+
+const float PI = 3.14159265359;
+const uint SAMPLE_COUNT = 1024u;
+
+vec3 direction(ivec3 texel, int size) {
+    vec2 uv = (vec2(texel.xy) + 0.5) / float(size) * 2.0 - 1.0;
+    switch (texel.z) {
+        case 0: return vec3(1.0, -uv.y, -uv.x);
+        case 1: return vec3(-1.0, -uv.y, uv.x);
+        case 2: return vec3(uv.x, 1.0, uv.y);
+        case 3: return vec3(uv.x, -1.0, -uv.y);
+        case 4: return vec3(uv.x, -uv.y, 1.0);
+        default: return vec3(-uv.x, -uv.y, -1.0);
+    }
+}
+
+float radical_inverse(uint bits) {
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return float(bits) * 2.3283064365386963e-10;
+}
+
+vec3 importance_sample_ggx(vec2 xi, vec3 n, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0 * PI * xi.x;
+    float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    vec3 h = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+    vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, n));
+    vec3 bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float d = n_dot_h * n_dot_h * (a * a - 1.0) + 1.0;
+    return a * a / (PI * d * d);
+}
+
+void main() {
+    int size = imageSize(prefiltered).x;
+    ivec3 texel = ivec3(gl_GlobalInvocationID);
+    if (texel.x >= size || texel.y >= size) {
+        return;
+    }
+    // Assume the view direction is the reflection direction is the normal.
+    vec3 n = normalize(direction(texel, size));
+    vec3 v = n;
+
+    float environment_size = float(textureSize(environment, 0).x);
+    float texel_solid_angle = 4.0 * PI / (6.0 * environment_size * environment_size);
+
+    vec3 sum = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec2 xi = vec2(float(i) / float(SAMPLE_COUNT), radical_inverse(i));
+        vec3 h = importance_sample_ggx(xi, n, push_constants.roughness);
+        vec3 l = normalize(2.0 * dot(v, h) * h - v);
+        float n_dot_l = dot(n, l);
+        if (n_dot_l > 0.0) {
+            // With v == n, the PDF of l is D(h) * n.h / (4 * v.h) = D(h) / 4.
+            float n_dot_h = max(dot(n, h), 0.0);
+            float pdf = distribution_ggx(n_dot_h, push_constants.roughness) / 4.0 + 0.0001;
+            float sample_solid_angle = 1.0 / (float(SAMPLE_COUNT) * pdf);
+            float lod = push_constants.roughness == 0.0 ? 0.0 : 0.5 * log2(sample_solid_angle / texel_solid_angle);
+            sum += textureLod(environment, l, max(lod, 0.0)).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    imageStore(prefiltered, texel, vec4(sum / weight, 1.0));
+}
+"]
+    struct Dummy;
+}
+
+/// Integrates the split-sum approximation of the GGX BRDF into a lookup table:
+/// x is n.v and y is roughness, red is the scale and green the bias to apply
+/// to F0. Mirrors `ibl::integrate_brdf`.
+pub mod brdf_lut_cs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+layout(set = 0, binding = 0, rgba16f) uniform writeonly image2D lut;
+
+const float PI = 3.14159265359;
+const uint SAMPLE_COUNT = 1024u;
+
+float radical_inverse(uint bits) {
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return float(bits) * 2.3283064365386963e-10;
+}
+
+vec3 importance_sample_ggx(vec2 xi, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0 * PI * xi.x;
+    float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+float geometry_schlick_ggx(float n_dot_x, float roughness) {
+    float k = roughness * roughness / 2.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+void main() {
+    ivec2 size = imageSize(lut);
+    ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+    if (texel.x >= size.x || texel.y >= size.y) {
+        return;
+    }
+    float n_dot_v = (float(texel.x) + 0.5) / float(size.x);
+    float roughness = (float(texel.y) + 0.5) / float(size.y);
+    // The normal is +Z.
+    vec3 v = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    float scale = 0.0;
+    float bias = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec2 xi = vec2(float(i) / float(SAMPLE_COUNT), radical_inverse(i));
+        vec3 h = importance_sample_ggx(xi, roughness);
+        vec3 l = normalize(2.0 * dot(v, h) * h - v);
+        float n_dot_l = max(l.z, 0.0);
+        float n_dot_h = max(h.z, 0.0);
+        float v_dot_h = max(dot(v, h), 0.0);
+        if (n_dot_l > 0.0) {
+            float g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            float g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            float fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * g_vis;
+            bias += fresnel * g_vis;
+        }
+    }
+    imageStore(lut, texel, vec4(scale / float(SAMPLE_COUNT), bias / float(SAMPLE_COUNT), 0.0, 1.0));
+}
+"]
+    struct Dummy;
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use cgmath::*;
+use vulkano;
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::DynamicState;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::image::ImageViewAccess;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::pipeline::depth_stencil::Compare;
+use vulkano::pipeline::depth_stencil::DepthStencil;
+use vulkano::framebuffer::Subpass;
+
+use camera::Camera;
+use graphics::Graphics;
+use shaders::skybox_fs;
+use shaders::skybox_vs;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyboxVertex {
+    pub position: (f32, f32, f32),
+}
+impl_vertex!(SkyboxVertex, position);
+
+/// The 36 vertices of a unit cube around the origin, as a triangle list.
+pub fn cube_vertices() -> Vec<SkyboxVertex> {
+    // Corners, indexed by their bits: x is bit 0, y bit 1 and z bit 2.
+    let corner = |i: usize| SkyboxVertex {
+        position: (
+            if i & 1 != 0 { 1.0 } else { -1.0 },
+            if i & 2 != 0 { 1.0 } else { -1.0 },
+            if i & 4 != 0 { 1.0 } else { -1.0 },
+        ),
+    };
+    let faces = [
+        [1, 3, 7, 5], [0, 4, 6, 2], // +X, -X
+        [2, 6, 7, 3], [0, 1, 5, 4], // +Y, -Y
+        [4, 5, 7, 6], [0, 2, 3, 1], // +Z, -Z
+    ];
+    faces.iter()
+        .flat_map(|face| vec![face[0], face[1], face[2], face[0], face[2], face[3]])
+        .map(corner)
+        .collect()
+}
+
+/// Draws a cubemap behind everything else. Draw it after opaque geometry, in
+/// the same render pass: it is drawn at the far plane, only where nothing
+/// has been drawn yet, and without writing depth.
+pub struct Skybox {
+    pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[SkyboxVertex]>>,
+    set: Arc<DescriptorSet + Send + Sync>,
+    reversed_z: bool,
+}
+
+impl Skybox {
+    /// `reversed_z` must match the cameras the skybox is drawn with.
+    pub fn new(graphics: &Graphics, cubemap: Arc<ImageViewAccess + Send + Sync>, reversed_z: bool) -> Skybox {
+        let vs = skybox_vs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+        let fs = skybox_fs::Shader::load(graphics.device.clone()).expect("failed to create shader module");
+
+        // The skybox is drawn exactly at the depth the buffer is cleared to,
+        // so it has to pass the test where the depths are equal.
+        let depth_compare = if reversed_z { Compare::GreaterOrEqual } else { Compare::LessOrEqual };
+        let pipeline: Arc<GraphicsPipelineAbstract + Send + Sync> = Arc::new(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<SkyboxVertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .depth_stencil(DepthStencil {
+                depth_write: false,
+                depth_compare,
+                .. DepthStencil::simple_depth_test()
+            })
+            .render_pass(Subpass::from(graphics.renderpass.clone(), 0).unwrap())
+            .build(graphics.device.clone())
+            .expect("failed to create skybox pipeline"));
+
+        let vertices = CpuAccessibleBuffer::from_iter(
+            graphics.device.clone(),
+            vulkano::buffer::BufferUsage::vertex_buffer(),
+            cube_vertices().into_iter()).expect("failed to create buffer");
+
+        let set = Arc::new(PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_sampled_image(cubemap, graphics.sampler.clone()).expect("failed to add cubemap")
+            .build().expect("failed to build skybox set"));
+
+        Skybox { pipeline, vertices, set, reversed_z }
+    }
+
+    /// Records drawing the skybox, as seen with `view` and `camera`, into a
+    /// command buffer inside the render pass.
+    pub fn draw(&self, command_buffer: AutoCommandBufferBuilder, dynamic_state: &DynamicState,
+                view: Matrix4<f32>, camera: &Camera) -> AutoCommandBufferBuilder {
+        debug_assert_eq!(camera.reversed_z, self.reversed_z, "skybox and camera disagree on reversed-Z");
+        // The skybox is infinitely far away, so only the rotation of the view matters.
+        let mut rotation = view;
+        rotation.w = Vector4::unit_w();
+        let push_constants = skybox_vs::ty::PushConstants {
+            view_projection: (camera.projection() * rotation).into(),
+            far_depth: camera.depth_clear_value(),
+        };
+        command_buffer.draw(
+            self.pipeline.clone(),
+            dynamic_state,
+            vec![self.vertices.clone()],
+            self.set.clone(),
+            push_constants).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cube_vertices() {
+        let vertices = cube_vertices();
+        assert_eq!(vertices.len(), 36);
+        // Every triangle lies in one face of the cube.
+        for triangle in vertices.chunks(3) {
+            let (a, b, c) = (triangle[0].position, triangle[1].position, triangle[2].position);
+            assert!((a.0 == b.0 && b.0 == c.0) || (a.1 == b.1 && b.1 == c.1) || (a.2 == b.2 && b.2 == c.2));
+        }
+        // Each face is covered by two triangles with distinct corners.
+        for face in vertices.chunks(6) {
+            let mut corners: Vec<_> = face.iter().map(|vertex| vertex.position).collect();
+            corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            corners.dedup();
+            assert_eq!(corners.len(), 4);
+        }
+    }
+}
@@ -0,0 +1,696 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use vulkano::format::Format;
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+// Opcodes.
+const OP_NAME: u16 = 5;
+const OP_TYPE_BOOL: u16 = 20;
+const OP_TYPE_INT: u16 = 21;
+const OP_TYPE_FLOAT: u16 = 22;
+const OP_TYPE_VECTOR: u16 = 23;
+const OP_TYPE_MATRIX: u16 = 24;
+const OP_TYPE_IMAGE: u16 = 25;
+const OP_TYPE_SAMPLER: u16 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u16 = 27;
+const OP_TYPE_ARRAY: u16 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u16 = 29;
+const OP_TYPE_STRUCT: u16 = 30;
+const OP_TYPE_POINTER: u16 = 32;
+const OP_CONSTANT: u16 = 43;
+const OP_SPEC_CONSTANT_TRUE: u16 = 48;
+const OP_SPEC_CONSTANT_FALSE: u16 = 49;
+const OP_SPEC_CONSTANT: u16 = 50;
+const OP_VARIABLE: u16 = 59;
+const OP_DECORATE: u16 = 71;
+const OP_MEMBER_DECORATE: u16 = 72;
+
+// Decorations.
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_NON_WRITABLE: u32 = 24;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes.
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+/// Error that can happen when reflecting a SPIR-V module.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReflectError {
+    /// Not SPIR-V, or truncated.
+    InvalidModule(&'static str),
+    /// A valid module using something we can't describe to vulkano.
+    Unsupported(String),
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReflectError::InvalidModule(reason) => write!(f, "invalid SPIR-V module: {}", reason),
+            ReflectError::Unsupported(ref what) => write!(f, "unsupported shader interface: {}", what),
+        }
+    }
+}
+
+impl error::Error for ReflectError {
+    fn description(&self) -> &str {
+        "failed to reflect shader"
+    }
+}
+
+/// A shader input or output, like `layout(location = 1) in vec3 normal`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterfaceVariable {
+    pub name: String,
+    pub location: u32,
+    /// Matrices and arrays take one location per column or element.
+    pub locations: u32,
+    pub format: Format,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageDimensions {
+    Dim1d,
+    Dim2d,
+    Dim3d,
+    Cube,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageType {
+    pub dimensions: ImageDimensions,
+    pub arrayed: bool,
+    pub multisampled: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DescriptorKind {
+    UniformBuffer,
+    StorageBuffer,
+    Sampler,
+    /// A `sampler2D` and the like.
+    CombinedImageSampler(ImageType),
+    /// A `texture2D` and the like, sampled with a separate sampler.
+    SampledImage(ImageType),
+    StorageImage(ImageType),
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+    InputAttachment { multisampled: bool },
+}
+
+/// A resource bound through a descriptor set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Descriptor {
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    pub kind: DescriptorKind,
+    /// Array size, 1 if the binding isn't an array.
+    pub count: u32,
+    pub readonly: bool,
+}
+
+/// A specialization constant, like `layout(constant_id = 3) const int count = 4`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecializationConstant {
+    pub name: String,
+    pub id: u32,
+    /// Size of the value in bytes. Booleans take 4, like `VkBool32`.
+    pub size: usize,
+}
+
+/// The interface of a shader's entry point, as needed to build pipelines.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reflection {
+    /// Sorted by location.
+    pub inputs: Vec<InterfaceVariable>,
+    /// Sorted by location.
+    pub outputs: Vec<InterfaceVariable>,
+    /// Sorted by set, then binding.
+    pub descriptors: Vec<Descriptor>,
+    /// Size of the push constant block in bytes, 0 without one.
+    pub push_constants_size: usize,
+    /// Sorted by id.
+    pub specialization_constants: Vec<SpecializationConstant>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, columns: u32 },
+    Image { dim: u32, arrayed: bool, multisampled: bool, sampled: u32 },
+    Sampler,
+    SampledImage { image: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Decorations {
+    flags: Vec<u32>,
+    location: Option<u32>,
+    binding: Option<u32>,
+    set: Option<u32>,
+    array_stride: Option<u32>,
+    spec_id: Option<u32>,
+}
+
+impl Decorations {
+    fn has(&self, decoration: u32) -> bool {
+        self.flags.contains(&decoration)
+    }
+}
+
+#[derive(Default)]
+struct MemberDecorations {
+    built_in: bool,
+    non_writable: bool,
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    // Id, pointer type and storage class of every global variable.
+    variables: Vec<(u32, u32, u32)>,
+    // Id and type of every scalar specialization constant.
+    spec_constants: Vec<(u32, u32)>,
+}
+
+/// Reflects the inputs, outputs, descriptors, push constants and
+/// specialization constants of a SPIR-V module with a single entry point.
+/// Built-in variables are left out.
+pub fn reflect(words: &[u32]) -> Result<Reflection, ReflectError> {
+    let module = parse(words)?;
+    let mut reflection = Reflection::default();
+
+    for &(id, pointer, storage) in &module.variables {
+        let pointee = match module.types.get(&pointer) {
+            Some(&Type::Pointer { pointee }) => pointee,
+            _ => return Err(ReflectError::InvalidModule("variable isn't a pointer")),
+        };
+        let name = module.names.get(&id).cloned().unwrap_or_default();
+        let decorations = module.decorations.get(&id);
+        match storage {
+            STORAGE_INPUT | STORAGE_OUTPUT => {
+                if decorations.map_or(false, |decorations| decorations.has(DECORATION_BUILT_IN)) || module.has_built_in_members(pointee) {
+                    continue;
+                }
+                let location = decorations.and_then(|decorations| decorations.location)
+                    .ok_or_else(|| ReflectError::Unsupported(format!("{} has no location", name)))?;
+                let (format, locations) = module.interface_format(pointee)
+                    .ok_or_else(|| ReflectError::Unsupported(format!("type of {}", name)))?;
+                let variable = InterfaceVariable { name, location, locations, format };
+                if storage == STORAGE_INPUT {
+                    reflection.inputs.push(variable);
+                } else {
+                    reflection.outputs.push(variable);
+                }
+            },
+            STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                let (set, binding) = match decorations {
+                    Some(&Decorations { set: Some(set), binding: Some(binding), .. }) => (set, binding),
+                    _ => return Err(ReflectError::Unsupported(format!("{} has no set and binding", name))),
+                };
+                let (element, count) = match module.types.get(&pointee) {
+                    Some(&Type::Array { element, length }) => (element, length),
+                    Some(&Type::RuntimeArray { .. }) => return Err(ReflectError::Unsupported(format!("{} is a runtime array", name))),
+                    _ => (pointee, 1),
+                };
+                let kind = module.descriptor_kind(element, storage)
+                    .ok_or_else(|| ReflectError::Unsupported(format!("type of {}", name)))?;
+                let readonly = match kind {
+                    DescriptorKind::StorageBuffer | DescriptorKind::StorageImage(_) | DescriptorKind::StorageTexelBuffer => {
+                        decorations.map_or(false, |decorations| decorations.has(DECORATION_NON_WRITABLE))
+                            || module.has_non_writable_members(element)
+                    },
+                    _ => true,
+                };
+                reflection.descriptors.push(Descriptor { name, set, binding, kind, count, readonly });
+            },
+            STORAGE_PUSH_CONSTANT => {
+                reflection.push_constants_size = module.size_of(pointee)
+                    .ok_or_else(|| ReflectError::Unsupported(format!("layout of {}", name)))?;
+            },
+            _ => (),
+        }
+    }
+
+    for &(id, ty) in &module.spec_constants {
+        let name = module.names.get(&id).cloned().unwrap_or_default();
+        // Constants without an id are computed from other constants, and
+        // can't be set directly.
+        let spec_id = match module.decorations.get(&id).and_then(|decorations| decorations.spec_id) {
+            Some(spec_id) => spec_id,
+            None => continue,
+        };
+        let size = module.size_of(ty)
+            .ok_or_else(|| ReflectError::Unsupported(format!("type of {}", name)))?;
+        reflection.specialization_constants.push(SpecializationConstant { name, id: spec_id, size });
+    }
+
+    reflection.inputs.sort_by_key(|variable| variable.location);
+    reflection.outputs.sort_by_key(|variable| variable.location);
+    reflection.descriptors.sort_by_key(|descriptor| (descriptor.set, descriptor.binding));
+    reflection.specialization_constants.sort_by_key(|constant| constant.id);
+    Ok(reflection)
+}
+
+fn parse(words: &[u32]) -> Result<Module, ReflectError> {
+    if words.len() < HEADER_WORDS || words[0] != MAGIC {
+        return Err(ReflectError::InvalidModule("bad header"));
+    }
+    let mut module = Module::default();
+    let mut position = HEADER_WORDS;
+    while position < words.len() {
+        let count = (words[position] >> 16) as usize;
+        let opcode = (words[position] & 0xffff) as u16;
+        if count == 0 || position + count > words.len() {
+            return Err(ReflectError::InvalidModule("truncated instruction"));
+        }
+        let operands = &words[position + 1..position + count];
+        position += count;
+
+        if operands.is_empty() {
+            continue;
+        }
+        let id = operands[0];
+        let ty = match opcode {
+            OP_NAME if operands.len() >= 2 => {
+                module.names.insert(id, string(&operands[1..]));
+                None
+            },
+            OP_TYPE_BOOL => Some(Type::Bool),
+            OP_TYPE_INT if operands.len() >= 3 => Some(Type::Int { width: operands[1], signed: operands[2] != 0 }),
+            OP_TYPE_FLOAT if operands.len() >= 2 => Some(Type::Float { width: operands[1] }),
+            OP_TYPE_VECTOR if operands.len() >= 3 => Some(Type::Vector { component: operands[1], count: operands[2] }),
+            OP_TYPE_MATRIX if operands.len() >= 3 => Some(Type::Matrix { column: operands[1], columns: operands[2] }),
+            OP_TYPE_IMAGE if operands.len() >= 8 => Some(Type::Image {
+                dim: operands[2],
+                arrayed: operands[4] != 0,
+                multisampled: operands[5] != 0,
+                sampled: operands[6],
+            }),
+            OP_TYPE_SAMPLER => Some(Type::Sampler),
+            OP_TYPE_SAMPLED_IMAGE if operands.len() >= 2 => Some(Type::SampledImage { image: operands[1] }),
+            OP_TYPE_ARRAY if operands.len() >= 3 => {
+                let length = *module.constants.get(&operands[2])
+                    .ok_or(ReflectError::InvalidModule("array length isn't a constant"))?;
+                Some(Type::Array { element: operands[1], length })
+            },
+            OP_TYPE_RUNTIME_ARRAY if operands.len() >= 2 => Some(Type::RuntimeArray { element: operands[1] }),
+            OP_TYPE_STRUCT => Some(Type::Struct { members: operands[1..].to_vec() }),
+            OP_TYPE_POINTER if operands.len() >= 3 => Some(Type::Pointer { pointee: operands[2] }),
+            // Only 32-bit integer constants matter, for array lengths.
+            OP_CONSTANT if operands.len() >= 3 => {
+                module.constants.insert(operands[1], operands[2]);
+                None
+            },
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE | OP_SPEC_CONSTANT if operands.len() >= 2 => {
+                module.spec_constants.push((operands[1], operands[0]));
+                None
+            },
+            // Function variables, in storage class 7, aren't part of the interface.
+            OP_VARIABLE if operands.len() >= 3 => {
+                if operands[2] != 7 {
+                    module.variables.push((operands[1], operands[0], operands[2]));
+                }
+                None
+            },
+            OP_DECORATE if operands.len() >= 2 => {
+                let decorations = module.decorations.entry(id).or_insert_with(Decorations::default);
+                let value = operands.get(2).cloned();
+                match operands[1] {
+                    DECORATION_LOCATION => decorations.location = value,
+                    DECORATION_BINDING => decorations.binding = value,
+                    DECORATION_DESCRIPTOR_SET => decorations.set = value,
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = value,
+                    DECORATION_SPEC_ID => decorations.spec_id = value,
+                    decoration => decorations.flags.push(decoration),
+                }
+                None
+            },
+            OP_MEMBER_DECORATE if operands.len() >= 3 => {
+                let decorations = module.member_decorations.entry((id, operands[1])).or_insert_with(MemberDecorations::default);
+                let value = operands.get(3).cloned();
+                match operands[2] {
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    DECORATION_NON_WRITABLE => decorations.non_writable = true,
+                    DECORATION_OFFSET => decorations.offset = value,
+                    DECORATION_MATRIX_STRIDE => decorations.matrix_stride = value,
+                    _ => (),
+                }
+                None
+            },
+            _ => None,
+        };
+        if let Some(ty) = ty {
+            module.types.insert(id, ty);
+        }
+    }
+    Ok(module)
+}
+
+// Decodes a nul-terminated UTF-8 literal, packed four bytes per word.
+fn string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words.iter()
+        .flat_map(|word| vec![*word as u8, (word >> 8) as u8, (word >> 16) as u8, (word >> 24) as u8])
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    // Whether `ty` is a block like `gl_PerVertex`.
+    fn has_built_in_members(&self, ty: u32) -> bool {
+        match self.types.get(&ty) {
+            Some(&Type::Struct { ref members }) => (0..members.len() as u32).any(|member| {
+                self.member_decorations.get(&(ty, member)).map_or(false, |decorations| decorations.built_in)
+            }),
+            _ => false,
+        }
+    }
+
+    // Whether `ty` is a block whose members are all `NonWritable`, which is
+    // how glslang declares `readonly buffer` blocks.
+    fn has_non_writable_members(&self, ty: u32) -> bool {
+        match self.types.get(&ty) {
+            Some(&Type::Struct { ref members }) => !members.is_empty() && (0..members.len() as u32).all(|member| {
+                self.member_decorations.get(&(ty, member)).map_or(false, |decorations| decorations.non_writable)
+            }),
+            _ => false,
+        }
+    }
+
+    // The format of each location of an input or output, and how many
+    // locations it takes.
+    fn interface_format(&self, ty: u32) -> Option<(Format, u32)> {
+        match *self.types.get(&ty)? {
+            Type::Matrix { column, columns } => self.interface_format(column).map(|(format, _)| (format, columns)),
+            Type::Array { element, length } => self.interface_format(element).map(|(format, locations)| (format, locations * length)),
+            Type::Vector { component, count } => self.scalar_format(component, count).map(|format| (format, 1)),
+            _ => self.scalar_format(ty, 1).map(|format| (format, 1)),
+        }
+    }
+
+    fn scalar_format(&self, ty: u32, count: u32) -> Option<Format> {
+        let format = match (self.types.get(&ty)?, count) {
+            (&Type::Float { width: 32 }, 1) => Format::R32Sfloat,
+            (&Type::Float { width: 32 }, 2) => Format::R32G32Sfloat,
+            (&Type::Float { width: 32 }, 3) => Format::R32G32B32Sfloat,
+            (&Type::Float { width: 32 }, 4) => Format::R32G32B32A32Sfloat,
+            (&Type::Float { width: 64 }, 1) => Format::R64Sfloat,
+            (&Type::Float { width: 64 }, 2) => Format::R64G64Sfloat,
+            (&Type::Float { width: 64 }, 3) => Format::R64G64B64Sfloat,
+            (&Type::Float { width: 64 }, 4) => Format::R64G64B64A64Sfloat,
+            (&Type::Int { width: 32, signed: true }, 1) => Format::R32Sint,
+            (&Type::Int { width: 32, signed: true }, 2) => Format::R32G32Sint,
+            (&Type::Int { width: 32, signed: true }, 3) => Format::R32G32B32Sint,
+            (&Type::Int { width: 32, signed: true }, 4) => Format::R32G32B32A32Sint,
+            (&Type::Int { width: 32, signed: false }, 1) => Format::R32Uint,
+            (&Type::Int { width: 32, signed: false }, 2) => Format::R32G32Uint,
+            (&Type::Int { width: 32, signed: false }, 3) => Format::R32G32B32Uint,
+            (&Type::Int { width: 32, signed: false }, 4) => Format::R32G32B32A32Uint,
+            _ => return None,
+        };
+        Some(format)
+    }
+
+    fn descriptor_kind(&self, ty: u32, storage: u32) -> Option<DescriptorKind> {
+        let kind = match *self.types.get(&ty)? {
+            Type::Sampler => DescriptorKind::Sampler,
+            Type::SampledImage { image } => match self.image_kind(image)? {
+                DescriptorKind::SampledImage(image) => DescriptorKind::CombinedImageSampler(image),
+                DescriptorKind::UniformTexelBuffer => DescriptorKind::UniformTexelBuffer,
+                _ => return None,
+            },
+            Type::Image { .. } => self.image_kind(ty)?,
+            Type::Struct { .. } => {
+                let decorations = self.decorations.get(&ty);
+                let has = |decoration| decorations.map_or(false, |decorations: &Decorations| decorations.has(decoration));
+                if storage == STORAGE_STORAGE_BUFFER || has(DECORATION_BUFFER_BLOCK) {
+                    DescriptorKind::StorageBuffer
+                } else if storage == STORAGE_UNIFORM && has(DECORATION_BLOCK) {
+                    DescriptorKind::UniformBuffer
+                } else {
+                    return None;
+                }
+            },
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn image_kind(&self, ty: u32) -> Option<DescriptorKind> {
+        let (dim, arrayed, multisampled, sampled) = match *self.types.get(&ty)? {
+            Type::Image { dim, arrayed, multisampled, sampled } => (dim, arrayed, multisampled, sampled),
+            _ => return None,
+        };
+        let dimensions = match dim {
+            0 => ImageDimensions::Dim1d,
+            1 => ImageDimensions::Dim2d,
+            2 => ImageDimensions::Dim3d,
+            3 => ImageDimensions::Cube,
+            // Buffer.
+            5 => return Some(if sampled == 2 { DescriptorKind::StorageTexelBuffer } else { DescriptorKind::UniformTexelBuffer }),
+            // SubpassData.
+            6 => return Some(DescriptorKind::InputAttachment { multisampled }),
+            _ => return None,
+        };
+        let image = ImageType { dimensions, arrayed, multisampled };
+        // 1 is sampled, 2 is storage and 0 is only known at runtime, which
+        // glslang never emits.
+        Some(if sampled == 2 { DescriptorKind::StorageImage(image) } else { DescriptorKind::SampledImage(image) })
+    }
+
+    // Size of a type laid out as in a block, from its offset and stride decorations.
+    fn size_of(&self, ty: u32) -> Option<usize> {
+        let size = match *self.types.get(&ty)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width as usize / 8,
+            Type::Vector { component, count } => self.size_of(component)? * count as usize,
+            Type::Array { element, length } => {
+                let stride = self.decorations.get(&ty).and_then(|decorations| decorations.array_stride);
+                stride.map(|stride| stride as usize).or_else(|| self.size_of(element))? * length as usize
+            },
+            Type::Struct { ref members } => {
+                let mut size = 0;
+                for (index, &member) in members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(ty, index as u32))?;
+                    let member_size = match (self.types.get(&member)?, decorations.matrix_stride) {
+                        (&Type::Matrix { columns, .. }, Some(stride)) => (columns * stride) as usize,
+                        _ => self.size_of(member)?,
+                    };
+                    size = size.max(decorations.offset? as usize + member_size);
+                }
+                size
+            },
+            _ => return None,
+        };
+        Some(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(opcode: u16, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![(operands.len() as u32 + 1) << 16 | opcode as u32];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn literal(value: &str) -> Vec<u32> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        while bytes.len() % 4 != 0 {
+            bytes.push(0);
+        }
+        bytes.chunks(4).map(|b| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24).collect()
+    }
+
+    fn name(id: u32, value: &str) -> Vec<u32> {
+        let mut operands = vec![id];
+        operands.extend(literal(value));
+        op(OP_NAME, &operands)
+    }
+
+    // A module like a vertex shader declaring:
+    //
+    //     layout(location = 0) in vec3 position;
+    //     layout(location = 1) in mat4 instance;
+    //     layout(location = 0) out vec4 color;
+    //     out gl_PerVertex { vec4 gl_Position; };
+    //     layout(set = 1, binding = 0) uniform Data { mat4 world; } uniforms;
+    //     layout(set = 0, binding = 1) uniform samplerCube maps[2];
+    //     layout(set = 0, binding = 0) uniform sampler2D color_map;
+    //     layout(push_constant) uniform PushConstants { mat4 transform; float scale; };
+    //     layout(set = 2, binding = 0) readonly buffer Instances { mat4 worlds[]; } instances;
+    //     layout(set = 2, binding = 1) buffer Output { float values[]; } results;
+    //     layout(constant_id = 3) const uint count = 4;
+    //     layout(constant_id = 0) const bool enabled = true;
+    //
+    // and a specialization constant without an id, like the result of an
+    // `OpSpecConstantOp`.
+    fn module() -> Vec<u32> {
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 100, 0];
+        let instructions = vec![
+            name(20, "position"), name(21, "instance"), name(22, "color"), name(23, ""),
+            name(24, "uniforms"), name(25, "maps"), name(26, "color_map"), name(27, ""),
+            name(40, "count"), name(41, "enabled"), name(42, "derived"),
+            name(43, "instances"), name(44, "results"),
+            op(OP_DECORATE, &[20, DECORATION_LOCATION, 0]),
+            op(OP_DECORATE, &[21, DECORATION_LOCATION, 1]),
+            op(OP_DECORATE, &[22, DECORATION_LOCATION, 0]),
+            op(OP_MEMBER_DECORATE, &[9, 0, DECORATION_BUILT_IN, 0]),
+            op(OP_DECORATE, &[10, DECORATION_BLOCK]),
+            op(OP_MEMBER_DECORATE, &[10, 0, DECORATION_OFFSET, 0]),
+            op(OP_MEMBER_DECORATE, &[10, 0, DECORATION_MATRIX_STRIDE, 16]),
+            op(OP_DECORATE, &[24, DECORATION_DESCRIPTOR_SET, 1]),
+            op(OP_DECORATE, &[24, DECORATION_BINDING, 0]),
+            op(OP_DECORATE, &[25, DECORATION_DESCRIPTOR_SET, 0]),
+            op(OP_DECORATE, &[25, DECORATION_BINDING, 1]),
+            op(OP_DECORATE, &[26, DECORATION_DESCRIPTOR_SET, 0]),
+            op(OP_DECORATE, &[26, DECORATION_BINDING, 0]),
+            op(OP_DECORATE, &[15, DECORATION_BLOCK]),
+            op(OP_MEMBER_DECORATE, &[15, 0, DECORATION_OFFSET, 0]),
+            op(OP_MEMBER_DECORATE, &[15, 0, DECORATION_MATRIX_STRIDE, 16]),
+            op(OP_MEMBER_DECORATE, &[15, 1, DECORATION_OFFSET, 64]),
+            op(OP_DECORATE, &[17, DECORATION_BUFFER_BLOCK]),
+            op(OP_DECORATE, &[18, DECORATION_ARRAY_STRIDE, 64]),
+            op(OP_MEMBER_DECORATE, &[17, 0, DECORATION_NON_WRITABLE]),
+            op(OP_MEMBER_DECORATE, &[17, 0, DECORATION_OFFSET, 0]),
+            op(OP_MEMBER_DECORATE, &[17, 0, DECORATION_MATRIX_STRIDE, 16]),
+            op(OP_DECORATE, &[43, DECORATION_DESCRIPTOR_SET, 2]),
+            op(OP_DECORATE, &[43, DECORATION_BINDING, 0]),
+            op(OP_DECORATE, &[19, DECORATION_BUFFER_BLOCK]),
+            op(OP_DECORATE, &[14, DECORATION_ARRAY_STRIDE, 4]),
+            op(OP_MEMBER_DECORATE, &[19, 0, DECORATION_OFFSET, 0]),
+            op(OP_DECORATE, &[44, DECORATION_DESCRIPTOR_SET, 2]),
+            op(OP_DECORATE, &[44, DECORATION_BINDING, 1]),
+            op(OP_DECORATE, &[40, DECORATION_SPEC_ID, 3]),
+            op(OP_DECORATE, &[41, DECORATION_SPEC_ID, 0]),
+            op(OP_TYPE_FLOAT, &[1, 32]),
+            op(OP_TYPE_VECTOR, &[2, 1, 3]),
+            op(OP_TYPE_VECTOR, &[3, 1, 4]),
+            op(OP_TYPE_MATRIX, &[4, 3, 4]),
+            op(OP_TYPE_INT, &[5, 32, 0]),
+            op(OP_CONSTANT, &[5, 6, 2]),
+            op(OP_TYPE_IMAGE, &[7, 1, 1, 0, 0, 0, 1, 0]),
+            op(OP_TYPE_SAMPLED_IMAGE, &[8, 7]),
+            op(OP_TYPE_STRUCT, &[9, 3]),
+            op(OP_TYPE_STRUCT, &[10, 4]),
+            op(OP_TYPE_IMAGE, &[11, 1, 3, 0, 0, 0, 1, 0]),
+            op(OP_TYPE_SAMPLED_IMAGE, &[12, 11]),
+            op(OP_TYPE_ARRAY, &[13, 12, 6]),
+            op(OP_TYPE_STRUCT, &[15, 4, 1]),
+            op(OP_TYPE_BOOL, &[16]),
+            op(OP_TYPE_RUNTIME_ARRAY, &[18, 4]),
+            op(OP_TYPE_STRUCT, &[17, 18]),
+            op(OP_TYPE_RUNTIME_ARRAY, &[14, 1]),
+            op(OP_TYPE_STRUCT, &[19, 14]),
+            op(OP_SPEC_CONSTANT, &[5, 40, 4]),
+            op(OP_SPEC_CONSTANT_TRUE, &[16, 41]),
+            op(OP_SPEC_CONSTANT, &[1, 42, 0x3f80_0000]),
+            op(OP_TYPE_POINTER, &[30, STORAGE_INPUT, 2]),
+            op(OP_TYPE_POINTER, &[31, STORAGE_INPUT, 4]),
+            op(OP_TYPE_POINTER, &[32, STORAGE_OUTPUT, 3]),
+            op(OP_TYPE_POINTER, &[33, STORAGE_OUTPUT, 9]),
+            op(OP_TYPE_POINTER, &[34, STORAGE_UNIFORM, 10]),
+            op(OP_TYPE_POINTER, &[35, STORAGE_UNIFORM_CONSTANT, 13]),
+            op(OP_TYPE_POINTER, &[36, STORAGE_UNIFORM_CONSTANT, 8]),
+            op(OP_TYPE_POINTER, &[37, STORAGE_PUSH_CONSTANT, 15]),
+            op(OP_TYPE_POINTER, &[38, STORAGE_UNIFORM, 17]),
+            op(OP_TYPE_POINTER, &[39, STORAGE_UNIFORM, 19]),
+            op(OP_VARIABLE, &[30, 20, STORAGE_INPUT]),
+            op(OP_VARIABLE, &[31, 21, STORAGE_INPUT]),
+            op(OP_VARIABLE, &[32, 22, STORAGE_OUTPUT]),
+            op(OP_VARIABLE, &[33, 23, STORAGE_OUTPUT]),
+            op(OP_VARIABLE, &[34, 24, STORAGE_UNIFORM]),
+            op(OP_VARIABLE, &[35, 25, STORAGE_UNIFORM_CONSTANT]),
+            op(OP_VARIABLE, &[36, 26, STORAGE_UNIFORM_CONSTANT]),
+            op(OP_VARIABLE, &[37, 27, STORAGE_PUSH_CONSTANT]),
+            op(OP_VARIABLE, &[38, 43, STORAGE_UNIFORM]),
+            op(OP_VARIABLE, &[39, 44, STORAGE_UNIFORM]),
+        ];
+        for instruction in instructions {
+            words.extend(instruction);
+        }
+        words
+    }
+
+    #[test]
+    fn test_reflect_interface() {
+        let reflection = reflect(&module()).unwrap();
+        assert_eq!(reflection.inputs, vec![
+            InterfaceVariable { name: "position".to_string(), location: 0, locations: 1, format: Format::R32G32B32Sfloat },
+            InterfaceVariable { name: "instance".to_string(), location: 1, locations: 4, format: Format::R32G32B32A32Sfloat },
+        ]);
+        // gl_PerVertex is left out.
+        assert_eq!(reflection.outputs, vec![
+            InterfaceVariable { name: "color".to_string(), location: 0, locations: 1, format: Format::R32G32B32A32Sfloat },
+        ]);
+        assert_eq!(reflection.push_constants_size, 68);
+    }
+
+    #[test]
+    fn test_reflect_descriptors() {
+        let reflection = reflect(&module()).unwrap();
+        let image = |dimensions| ImageType { dimensions, arrayed: false, multisampled: false };
+        let summary: Vec<_> = reflection.descriptors.iter()
+            .map(|descriptor| (descriptor.name.as_str(), descriptor.set, descriptor.binding, descriptor.kind, descriptor.count, descriptor.readonly))
+            .collect();
+        assert_eq!(summary, vec![
+            ("color_map", 0, 0, DescriptorKind::CombinedImageSampler(image(ImageDimensions::Dim2d)), 1, true),
+            ("maps", 0, 1, DescriptorKind::CombinedImageSampler(image(ImageDimensions::Cube)), 2, true),
+            ("uniforms", 1, 0, DescriptorKind::UniformBuffer, 1, true),
+            // `readonly` blocks only have `NonWritable` on their members.
+            ("instances", 2, 0, DescriptorKind::StorageBuffer, 1, true),
+            ("results", 2, 1, DescriptorKind::StorageBuffer, 1, false),
+        ]);
+    }
+
+    #[test]
+    fn test_reflect_specialization_constants() {
+        let reflection = reflect(&module()).unwrap();
+        assert_eq!(reflection.specialization_constants, vec![
+            SpecializationConstant { name: "enabled".to_string(), id: 0, size: 4 },
+            SpecializationConstant { name: "count".to_string(), id: 3, size: 4 },
+        ]);
+    }
+
+    #[test]
+    fn test_reflect_rejects_garbage() {
+        assert_eq!(reflect(&[1, 2, 3]), Err(ReflectError::InvalidModule("bad header")));
+        let mut truncated = module();
+        truncated.push(10 << 16 | OP_NAME as u32);
+        assert_eq!(reflect(&truncated), Err(ReflectError::InvalidModule("truncated instruction")));
+    }
+}
@@ -0,0 +1,499 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image;
+use png;
+use png::HasParameters;
+#[cfg(feature = "exr")]
+use openexr;
+use vulkano::format::Format;
+use vulkano::instance::PhysicalDevice;
+
+/// How the channels of a texture are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Gamma encoded colors, like albedo maps. Sampling decodes them to linear.
+    Srgb,
+    /// Data that is already linear, like normal, roughness and occlusion maps.
+    Linear,
+}
+
+/// Whether the hardware converts between sRGB and linear when reading or
+/// writing `format`.
+pub fn is_srgb(format: Format) -> bool {
+    match format {
+        Format::R8Srgb | Format::R8G8Srgb |
+        Format::R8G8B8Srgb | Format::B8G8R8Srgb |
+        Format::R8G8B8A8Srgb | Format::B8G8R8A8Srgb |
+        Format::A8B8G8R8SrgbPack32 => true,
+        _ => false,
+    }
+}
+
+/// Number of levels in a full mip chain for an image of the given size.
+pub fn mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Size of a mip level, never smaller than 1x1.
+pub fn mip_dimensions(width: u32, height: u32, level: u32) -> [u32; 2] {
+    [(width >> level).max(1), (height >> level).max(1)]
+}
+
+/// Whether mipmaps of `format` can be generated with linear blits. These are
+/// the formats the spec requires to support `BLIT_SRC`, `BLIT_DST` and
+/// `SAMPLED_IMAGE_FILTER_LINEAR` with optimal tiling; anything else is
+/// downsampled on the CPU.
+pub fn can_blit(format: Format) -> bool {
+    match format {
+        Format::R8G8B8A8Unorm | Format::R8G8B8A8Srgb |
+        Format::B8G8R8A8Unorm | Format::B8G8R8A8Srgb |
+        Format::R8Unorm | Format::R8G8Unorm |
+        Format::R16Sfloat | Format::R16G16Sfloat | Format::R16G16B16A16Sfloat => true,
+        _ => false,
+    }
+}
+
+/// Whether images of `format` with optimal tiling can be sampled on `physical`.
+pub fn can_sample(physical: PhysicalDevice, format: Format) -> bool {
+    format.properties(physical).optimal_tiling_features.sampled_image
+}
+
+/// A channel of a texel, as stored in a texture.
+pub trait Channel: Copy + Send + Sync + 'static {
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Channel for u8 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 255.0
+    }
+
+    fn from_f32(value: f32) -> u8 {
+        (value.max(0.0).min(1.0) * 255.0).round() as u8
+    }
+}
+
+impl Channel for u16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 65535.0
+    }
+
+    fn from_f32(value: f32) -> u16 {
+        (value.max(0.0).min(1.0) * 65535.0).round() as u16
+    }
+}
+
+impl Channel for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> f32 {
+        value
+    }
+}
+
+/// The bits of a half precision float.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Half(pub u16);
+
+impl Channel for Half {
+    fn to_f32(self) -> f32 {
+        let sign = if self.0 & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = ((self.0 >> 10) & 0x1f) as i32;
+        let mantissa = (self.0 & 0x3ff) as f32;
+        sign * match exponent {
+            0 => mantissa * 2f32.powi(-24),
+            0x1f if mantissa == 0.0 => ::std::f32::INFINITY,
+            0x1f => ::std::f32::NAN,
+            _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+        }
+    }
+
+    fn from_f32(value: f32) -> Half {
+        let bits = value.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let mantissa = bits & 0x7f_ffff;
+        if exponent == 0xff {
+            // Infinity stays infinity, NaN stays NaN.
+            return Half(sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 });
+        }
+        let exponent = exponent - 127 + 15;
+        if exponent >= 0x1f {
+            return Half(sign | 0x7c00);
+        }
+        if exponent <= 0 {
+            if exponent < -10 {
+                return Half(sign);
+            }
+            // Subnormal: shift the mantissa, with its implicit bit, into place.
+            let mantissa = mantissa | 0x80_0000;
+            let shift = (14 - exponent) as u32;
+            let rounded = (mantissa + (1 << (shift - 1))) >> shift;
+            return Half(sign | rounded as u16);
+        }
+        // Round to nearest; a carry into the exponent is still correct.
+        let rounded = ((exponent as u32) << 10 | mantissa >> 13) + ((mantissa >> 12) & 1);
+        Half(sign | rounded as u16)
+    }
+}
+
+/// Halves an image using a box filter. Odd rows and columns are folded into
+/// the last texel.
+pub fn downsample<T: Channel>(width: u32, height: u32, channels: usize, data: &[T]) -> Vec<T> {
+    let new_dimensions = mip_dimensions(width, height, 1);
+    let (new_width, new_height) = (new_dimensions[0], new_dimensions[1]);
+    let texel = |x: u32, y: u32, c: usize| data[(y * width + x) as usize * channels + c].to_f32();
+    let mut result = Vec::with_capacity((new_width * new_height) as usize * channels);
+    for y in 0..new_height {
+        // Which source rows and columns fall into this texel.
+        let ys = 2 * y .. if y + 1 == new_height { height } else { 2 * y + 2 };
+        for x in 0..new_width {
+            let xs = 2 * x .. if x + 1 == new_width { width } else { 2 * x + 2 };
+            for c in 0..channels {
+                let mut sum = 0.0;
+                for sy in ys.clone() {
+                    for sx in xs.clone() {
+                        sum += texel(sx, sy, c);
+                    }
+                }
+                let count = (ys.end - ys.start) * (xs.end - xs.start);
+                result.push(T::from_f32(sum / count as f32));
+            }
+        }
+    }
+    result
+}
+
+/// Every level of the mip chain of an image, starting with the image itself.
+pub fn generate_mipmaps<T: Channel>(width: u32, height: u32, channels: usize, data: Vec<T>) -> Vec<Vec<T>> {
+    let mut levels = vec![data];
+    for level in 1..mip_levels(width, height) {
+        let dimensions = mip_dimensions(width, height, level - 1);
+        let next = downsample(dimensions[0], dimensions[1], channels, &levels[levels.len() - 1]);
+        levels.push(next);
+    }
+    levels
+}
+
+/// Texels decoded from an image file, at the precision of the source.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageData {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F16(Vec<Half>),
+    F32(Vec<f32>),
+}
+
+/// An image with 1, 2 or 4 channels per texel. RGB sources get an opaque
+/// alpha channel, since few devices can sample three channel formats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedImage {
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    pub data: ImageData,
+}
+
+/// Loads an image file, keeping the precision of the source: Radiance `.hdr`
+/// files as half floats, OpenEXR files as floats (with the `exr` feature),
+/// 16-bit PNGs as 16-bit and everything else as 8-bit.
+pub fn load_image(filename: &str) -> LoadedImage {
+    try_load_image(filename).unwrap_or_else(|err| panic!("Failed to load {}: {}!", filename, err))
+}
+
+/// Like `load_image`, but returns why the file couldn't be loaded.
+pub fn try_load_image(filename: &str) -> Result<LoadedImage, String> {
+    let extension = Path::new(filename).extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    match extension.as_ref().map(|extension| extension.as_str()) {
+        Some("hdr") => load_hdr(filename),
+        Some("exr") => load_exr(filename),
+        Some("png") => load_png(filename),
+        _ => load_ldr(filename),
+    }
+}
+
+fn load_ldr(filename: &str) -> Result<LoadedImage, String> {
+    let image = image::open(filename).map_err(|err| format!("failed to load image: {}", err))?;
+    Ok(match image {
+        image::DynamicImage::ImageLuma8(image) => LoadedImage {
+            width: image.width(),
+            height: image.height(),
+            channels: 1,
+            data: ImageData::U8(image.into_raw()),
+        },
+        image => {
+            let image = image.to_rgba();
+            LoadedImage {
+                width: image.width(),
+                height: image.height(),
+                channels: 4,
+                data: ImageData::U8(image.into_raw()),
+            }
+        },
+    })
+}
+
+fn load_hdr(filename: &str) -> Result<LoadedImage, String> {
+    let file = BufReader::new(File::open(filename).map_err(|err| format!("failed to open image: {}", err))?);
+    let decoder = image::hdr::HDRDecoder::new(file).map_err(|err| format!("failed to decode HDR image: {}", err))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr().map_err(|err| format!("failed to decode HDR image: {}", err))?;
+    let rgb: Vec<Half> = pixels.iter().flat_map(|pixel| pixel.data.to_vec()).map(Half::from_f32).collect();
+    Ok(LoadedImage {
+        width: metadata.width,
+        height: metadata.height,
+        channels: 4,
+        data: ImageData::F16(expand_rgb(&rgb, Half::from_f32(1.0))),
+    })
+}
+
+fn load_png(filename: &str) -> Result<LoadedImage, String> {
+    let mut decoder = png::Decoder::new(File::open(filename).map_err(|err| format!("failed to open image: {}", err))?);
+    // Unlike the default (and the `image` crate), keep 16-bit samples. Images
+    // that would need expanding are never 16-bit and get loaded by `image`.
+    decoder.set(png::Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info().map_err(|err| format!("failed to decode PNG image: {}", err))?;
+    if info.bit_depth != png::BitDepth::Sixteen {
+        return load_ldr(filename);
+    }
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        png::ColorType::Indexed => return load_ldr(filename),
+    };
+    let mut bytes = vec![0; info.buffer_size()];
+    reader.next_frame(&mut bytes).map_err(|err| format!("failed to decode PNG image: {}", err))?;
+    // PNG stores 16-bit samples big endian.
+    let data: Vec<u16> = bytes.chunks(2).map(|pair| (pair[0] as u16) << 8 | pair[1] as u16).collect();
+    let (channels, data) = if channels == 3 { (4, expand_rgb(&data, 0xffff)) } else { (channels, data) };
+    Ok(LoadedImage { width: info.width, height: info.height, channels, data: ImageData::U16(data) })
+}
+
+#[cfg(feature = "exr")]
+fn load_exr(filename: &str) -> Result<LoadedImage, String> {
+    let mut file = File::open(filename).map_err(|err| format!("failed to open image: {}", err))?;
+    let mut input = openexr::InputFile::new(&mut file).map_err(|err| format!("failed to decode EXR image: {}", err))?;
+    let (width, height) = input.header().data_dimensions();
+    let mut pixels = vec![(0.0f32, 0.0f32, 0.0f32, 1.0f32); (width * height) as usize];
+    {
+        let mut frame_buffer = openexr::FrameBufferMut::new(width, height);
+        frame_buffer.insert_channels(&[("R", 0.0), ("G", 0.0), ("B", 0.0), ("A", 1.0)], &mut pixels);
+        input.read_pixels(&mut frame_buffer).map_err(|err| format!("failed to decode EXR image: {}", err))?;
+    }
+    let data = pixels.iter().flat_map(|&(r, g, b, a)| vec![r, g, b, a]).collect();
+    Ok(LoadedImage { width, height, channels: 4, data: ImageData::F32(data) })
+}
+
+#[cfg(not(feature = "exr"))]
+fn load_exr(_filename: &str) -> Result<LoadedImage, String> {
+    Err("OpenEXR support requires the exr feature".to_string())
+}
+
+// Adds an alpha channel to RGB data.
+fn expand_rgb<T: Copy>(rgb: &[T], alpha: T) -> Vec<T> {
+    let mut rgba = Vec::with_capacity(rgb.len() / 3 * 4);
+    for texel in rgb.chunks(3) {
+        rgba.extend_from_slice(texel);
+        rgba.push(alpha);
+    }
+    rgba
+}
+
+/// Converts an image into texels that can be uploaded as is, and returns the
+/// format to upload them as. There are no 16-bit sRGB formats, so 16-bit sRGB
+/// color is decoded to linear on the way. Sampling the 16-bit unorm formats
+/// is optional in Vulkan; if `can_sample` rejects one, the data becomes half
+/// floats instead, whose formats always work.
+pub fn prepare_texture<F>(image: LoadedImage, color_space: ColorSpace, can_sample: F) -> (LoadedImage, Format)
+    where F: Fn(Format) -> bool {
+    let format = texture_format(&image, color_space);
+    match image.data {
+        ImageData::U16(data) => {
+            let srgb = color_space == ColorSpace::Srgb && image.channels == 4;
+            // Alpha is always linear.
+            let linear = |i: usize, value: u16| if srgb && i % 4 != 3 { srgb_to_linear(value.to_f32()) } else { value.to_f32() };
+            if can_sample(format) {
+                let data = if srgb {
+                    data.iter().enumerate().map(|(i, &value)| u16::from_f32(linear(i, value))).collect()
+                } else {
+                    data
+                };
+                (LoadedImage { data: ImageData::U16(data), .. image }, format)
+            } else {
+                let data = data.iter().enumerate().map(|(i, &value)| Half::from_f32(linear(i, value))).collect();
+                let image = LoadedImage { data: ImageData::F16(data), .. image };
+                let format = texture_format(&image, color_space);
+                (image, format)
+            }
+        },
+        data => (LoadedImage { data, .. image }, format),
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The format to upload an image as. Only 8-bit RGBA color data has an sRGB
+/// format here; everything with more precision is assumed to be linear.
+pub fn texture_format(image: &LoadedImage, color_space: ColorSpace) -> Format {
+    match (&image.data, image.channels) {
+        (&ImageData::U8(_), 1) => Format::R8Unorm,
+        (&ImageData::U8(_), 2) => Format::R8G8Unorm,
+        (&ImageData::U8(_), 4) if color_space == ColorSpace::Srgb => Format::R8G8B8A8Srgb,
+        (&ImageData::U8(_), 4) => Format::R8G8B8A8Unorm,
+        (&ImageData::U16(_), 1) => Format::R16Unorm,
+        (&ImageData::U16(_), 2) => Format::R16G16Unorm,
+        (&ImageData::U16(_), 4) => Format::R16G16B16A16Unorm,
+        (&ImageData::F16(_), 1) => Format::R16Sfloat,
+        (&ImageData::F16(_), 2) => Format::R16G16Sfloat,
+        (&ImageData::F16(_), 4) => Format::R16G16B16A16Sfloat,
+        (&ImageData::F32(_), 1) => Format::R32Sfloat,
+        (&ImageData::F32(_), 2) => Format::R32G32Sfloat,
+        (&ImageData::F32(_), 4) => Format::R32G32B32A32Sfloat,
+        (_, channels) => panic!("Unsupported number of channels: {}!", channels),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mip_levels() {
+        assert_eq!(mip_levels(1, 1), 1);
+        assert_eq!(mip_levels(2, 2), 2);
+        assert_eq!(mip_levels(1024, 1024), 11);
+        assert_eq!(mip_levels(1024, 3), 11);
+        assert_eq!(mip_levels(5, 3), 3);
+        assert_eq!(mip_dimensions(1024, 3, 2), [256, 1]);
+        assert_eq!(mip_dimensions(5, 3, 2), [1, 1]);
+    }
+
+    #[test]
+    fn test_is_srgb() {
+        assert!(is_srgb(Format::B8G8R8A8Srgb));
+        assert!(is_srgb(Format::R8G8B8A8Srgb));
+        assert!(!is_srgb(Format::B8G8R8A8Unorm));
+        assert!(!is_srgb(Format::R16G16B16A16Sfloat));
+    }
+
+    #[test]
+    fn test_half() {
+        for &(value, bits) in &[(0.0, 0x0000), (1.0, 0x3c00), (0.5, 0x3800), (-2.0, 0xc000),
+                                (65504.0, 0x7bff), (2f32.powi(-24), 0x0001), (2f32.powi(-14), 0x0400)] {
+            assert_eq!(Half::from_f32(value), Half(bits));
+            assert_eq!(Half(bits).to_f32(), value);
+        }
+        assert_eq!(Half::from_f32(1.0e6), Half(0x7c00));
+        assert_eq!(Half::from_f32(1.0e-9), Half(0x0000));
+        assert_eq!(Half::from_f32(1.0 + 1.0 / 4096.0), Half(0x3c00));
+        assert_eq!(Half::from_f32(1.0 + 3.0 / 2048.0), Half(0x3c02));
+        assert_eq!(Half(0x7c00).to_f32(), ::std::f32::INFINITY);
+        assert!(Half::from_f32(::std::f32::NAN).to_f32().is_nan());
+    }
+
+    #[test]
+    fn test_texture_format() {
+        let image = |channels, data| LoadedImage { width: 1, height: 1, channels, data };
+        assert_eq!(texture_format(&image(4, ImageData::U8(vec![0; 4])), ColorSpace::Srgb), Format::R8G8B8A8Srgb);
+        assert_eq!(texture_format(&image(4, ImageData::U8(vec![0; 4])), ColorSpace::Linear), Format::R8G8B8A8Unorm);
+        assert_eq!(texture_format(&image(1, ImageData::U16(vec![0])), ColorSpace::Linear), Format::R16Unorm);
+        assert_eq!(texture_format(&image(4, ImageData::U16(vec![0; 4])), ColorSpace::Srgb), Format::R16G16B16A16Unorm);
+        assert_eq!(texture_format(&image(4, ImageData::F16(vec![Half(0); 4])), ColorSpace::Srgb), Format::R16G16B16A16Sfloat);
+        assert_eq!(texture_format(&image(4, ImageData::F32(vec![0.0; 4])), ColorSpace::Linear), Format::R32G32B32A32Sfloat);
+        assert_eq!(expand_rgb(&[1, 2, 3, 4, 5, 6], 9), vec![1, 2, 3, 9, 4, 5, 6, 9]);
+    }
+
+    #[test]
+    fn test_try_load_image() {
+        assert!(try_load_image("missing.png").is_err());
+        assert!(try_load_image("missing.hdr").is_err());
+        assert!(try_load_image("missing.jpg").is_err());
+    }
+
+    #[test]
+    fn test_prepare_texture() {
+        let image = |channels, data| LoadedImage { width: 1, height: 1, channels, data };
+        let half = |image: LoadedImage| match image.data {
+            ImageData::F16(data) => data.iter().map(|value| value.to_f32()).collect::<Vec<_>>(),
+            data => panic!("{:?} isn't half floats", data),
+        };
+
+        // Without 16-bit unorm support, 16-bit data becomes half floats.
+        let (srgb, format) = prepare_texture(image(4, ImageData::U16(vec![65535, 32768, 0, 32768])), ColorSpace::Srgb, |_| false);
+        assert_eq!(format, Format::R16G16B16A16Sfloat);
+        let srgb = half(srgb);
+        assert_eq!(srgb[0], 1.0);
+        assert!((srgb[1] - 0.214).abs() < 1e-3);
+        assert_eq!(srgb[2], 0.0);
+        assert!((srgb[3] - 0.5).abs() < 1e-3);
+
+        let (linear, format) = prepare_texture(image(1, ImageData::U16(vec![32768])), ColorSpace::Srgb, |_| false);
+        assert_eq!(format, Format::R16Sfloat);
+        assert!((half(linear)[0] - 0.5).abs() < 1e-3);
+
+        // With it, the 16-bit data is kept, decoded to linear if it's sRGB.
+        let unorm = image(1, ImageData::U16(vec![12345]));
+        assert_eq!(prepare_texture(unorm.clone(), ColorSpace::Srgb, |_| true), (unorm, Format::R16Unorm));
+        let (srgb, format) = prepare_texture(image(4, ImageData::U16(vec![65535, 32768, 0, 32768])), ColorSpace::Srgb, |_| true);
+        assert_eq!(format, Format::R16G16B16A16Unorm);
+        assert_eq!(srgb.data, ImageData::U16(vec![65535, 14028, 0, 32768]));
+
+        let bytes = image(4, ImageData::U8(vec![1, 2, 3, 4]));
+        assert_eq!(prepare_texture(bytes.clone(), ColorSpace::Srgb, |_| false), (bytes, Format::R8G8B8A8Srgb));
+    }
+
+    #[test]
+    fn test_downsample_precision() {
+        assert_eq!(downsample(2, 1, 1, &[0u16, 65535]), vec![32768]);
+        assert_eq!(downsample(2, 1, 1, &[1.0f32, 100.0]), vec![50.5]);
+        assert_eq!(downsample(2, 1, 1, &[Half::from_f32(1.0), Half::from_f32(3.0)]), vec![Half::from_f32(2.0)]);
+    }
+
+    #[test]
+    fn test_downsample() {
+        let data: Vec<u8> = vec![
+            0, 10,   20, 30,
+            40, 50,  60, 70,
+        ];
+        assert_eq!(downsample(2, 2, 2, &data), vec![30, 40]);
+
+        // The odd column and row fold into the last texel.
+        let data: Vec<u8> = vec![
+            0, 4, 8,
+            0, 4, 8,
+            3, 3, 3,
+        ];
+        assert_eq!(downsample(3, 3, 1, &data), vec![4]);
+        let data: Vec<u8> = vec![
+            0, 2, 9, 9, 1,
+            2, 4, 9, 9, 2,
+        ];
+        assert_eq!(downsample(5, 2, 1, &data), vec![2, 7]);
+    }
+
+    #[test]
+    fn test_generate_mipmaps() {
+        let data: Vec<u8> = (0..4 * 2 * 4).map(|i| (i * 7) as u8).collect();
+        let levels = generate_mipmaps(4, 2, 4, data.clone());
+        assert_eq!(levels.len(), 3);
+        assert_eq!(levels[0], data);
+        assert_eq!(levels[1].len(), 2 * 1 * 4);
+        assert_eq!(levels[2].len(), 1 * 1 * 4);
+        assert_eq!(levels[2], downsample(2, 1, 4, &levels[1]));
+    }
+}
@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use vulkano::buffer::BufferUsage;
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::buffer::ImmutableBuffer;
+use vulkano::buffer::TypedBufferAccess;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::command_buffer::CommandBuffer;
+use vulkano::device::Device;
+use vulkano::device::Queue;
+use vulkano::format::AcceptsPixels;
+use vulkano::format::Format;
+use vulkano::image::Dimensions;
+use vulkano::image::ImageLayout;
+use vulkano::image::ImageUsage;
+use vulkano::image::ImageViewAccess;
+use vulkano::image::ImmutableImage;
+use vulkano::image::MipmapsCount;
+use vulkano::image::StorageImage;
+use vulkano::instance::QueueFamily;
+use vulkano::sampler::Filter;
+use vulkano::sync::GpuFuture;
+
+use compressed;
+use compressed::CompressedTexture;
+use compressed::TextureError;
+use texture;
+use texture::Channel;
+use texture::ColorSpace;
+use texture::ImageData;
+use texture::LoadedImage;
+
+/// A texture file decoded on the CPU, ready to upload.
+pub enum DecodedTexture {
+    /// A DDS or KTX2 file, with the mip chain and faces stored in it.
+    Compressed(CompressedTexture),
+    /// Any other image, converted by `texture::prepare_texture`.
+    Image(LoadedImage, Format),
+}
+
+impl DecodedTexture {
+    /// Decodes a texture file the way `Graphics::load_texture` does, for
+    /// `device`.
+    pub fn load(filename: &str, color_space: ColorSpace, device: &Device) -> Result<DecodedTexture, TextureError> {
+        let lowercase = filename.to_lowercase();
+        if lowercase.ends_with(".dds") || lowercase.ends_with(".ktx2") {
+            return DecodedTexture::compressed(filename, color_space, device);
+        }
+        let physical = device.physical_device();
+        let image = texture::try_load_image(filename).map_err(TextureError::Image)?;
+        let (image, format) = texture::prepare_texture(image, color_space, |format| texture::can_sample(physical, format));
+        Ok(DecodedTexture::Image(image, format))
+    }
+
+    /// Reads a DDS or KTX2 file. Fails if `device` can't sample its format.
+    pub fn compressed(filename: &str, color_space: ColorSpace, device: &Device) -> Result<DecodedTexture, TextureError> {
+        let loaded = compressed::load_compressed(filename, color_space)?;
+        if !compressed::is_supported(loaded.format, device) {
+            return Err(TextureError::UnsupportedByDevice(loaded.format));
+        }
+        Ok(DecodedTexture::Compressed(loaded))
+    }
+}
+
+/// Uploads textures and buffers through `queue`. They are shared with the
+/// family of `graphics_queue` too, if it differs, so they can be used on both
+/// queues without transferring ownership.
+///
+/// The returned futures complete once the upload is done.
+#[derive(Clone)]
+pub struct Uploader {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub graphics_queue: Arc<Queue>,
+}
+
+impl Uploader {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>, graphics_queue: Arc<Queue>) -> Uploader {
+        Uploader { device, queue, graphics_queue }
+    }
+
+    /// Uploads a decoded texture. Images get a full mip chain, compressed
+    /// textures the levels stored in their file.
+    pub fn upload_texture(&self, texture: DecodedTexture) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>) {
+        match texture {
+            DecodedTexture::Compressed(loaded) => {
+                let dimensions = if loaded.faces == 6 {
+                    Dimensions::Cubemap { size: loaded.width }
+                } else {
+                    Dimensions::Dim2d { width: loaded.width, height: loaded.height }
+                };
+                self.upload_levels(dimensions, loaded.format, loaded.levels)
+            },
+            DecodedTexture::Image(image, format) => match image.data {
+                ImageData::U8(data) => self.upload_image(image.width, image.height, format, data),
+                ImageData::U16(data) => self.upload_image(image.width, image.height, format, data),
+                ImageData::F16(data) => self.upload_image(image.width, image.height, format, data),
+                ImageData::F32(data) => self.upload_image(image.width, image.height, format, data),
+            },
+        }
+    }
+
+    /// Uploads a 2D image along with its full mip chain. The chain is
+    /// generated with blits if the format and queue allow, and on the CPU
+    /// otherwise.
+    pub fn upload_image<T>(&self, width: u32, height: u32, format: Format, data: Vec<T>) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>)
+        where T: Channel, Format: AcceptsPixels<T> {
+        if texture::can_blit(format) && self.queue.family().supports_graphics() {
+            return self.upload_blitted(width, height, format, data);
+        }
+        let channels = data.len() / (width * height) as usize;
+        let levels = texture::generate_mipmaps(width, height, channels, data).into_iter()
+            .map(|level| vec![level])
+            .collect();
+        self.upload_levels(Dimensions::Dim2d { width, height }, format, levels)
+    }
+
+    /// Uploads every face of every level, indexed like `CompressedTexture::levels`.
+    pub fn upload_levels<T>(&self, dimensions: Dimensions, format: Format, levels: Vec<Vec<Vec<T>>>) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>)
+        where T: Send + Sync + 'static, Format: AcceptsPixels<T> {
+        let (tex, init) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            dimensions,
+            format,
+            MipmapsCount::Specific(levels.len() as u32),
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                .. ImageUsage::none()
+            },
+            ImageLayout::ShaderReadOnlyOptimal,
+            self.queue_families()).unwrap();
+        let init = Arc::new(init);
+
+        let mut command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family()).unwrap();
+        for (level, faces) in levels.into_iter().enumerate() {
+            let level_dimensions = texture::mip_dimensions(dimensions.width(), dimensions.height(), level as u32);
+            for (face, data) in faces.into_iter().enumerate() {
+                let buffer = CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::transfer_source(),
+                    data.into_iter()).expect("failed to create buffer");
+                command_buffer = command_buffer.copy_buffer_to_image_dimensions(
+                    buffer, init.clone(), [0, 0, 0], [level_dimensions[0], level_dimensions[1], 1], face as u32, 1, level as u32).unwrap();
+            }
+        }
+
+        let future = command_buffer.build().unwrap()
+            .execute(self.queue.clone()).unwrap();
+        (tex, Box::new(future))
+    }
+
+    /// Copies `data` into a device-local buffer through a staging buffer.
+    pub fn upload_buffer<T>(&self, data: Vec<T>, usage: BufferUsage) -> (Arc<ImmutableBuffer<[T]>>, Box<GpuFuture>)
+        where T: Send + Sync + 'static {
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            data.into_iter()).expect("failed to create buffer");
+        let usage = BufferUsage { transfer_destination: true, .. usage };
+        // Safe, since the buffer isn't used before the copy below has finished.
+        let (buffer, init) = unsafe {
+            ImmutableBuffer::uninitialized_array(self.device.clone(), staging.len(), usage, self.queue_families())
+                .expect("failed to create buffer")
+        };
+        let future = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family()).unwrap()
+            .copy_buffer(staging, init).unwrap()
+            .build().unwrap()
+            .execute(self.queue.clone()).unwrap();
+        (buffer, Box::new(future))
+    }
+
+    fn upload_blitted<T>(&self, width: u32, height: u32, format: Format, data: Vec<T>) -> (Arc<ImageViewAccess + Send + Sync>, Box<GpuFuture>)
+        where T: Channel, Format: AcceptsPixels<T> {
+        let levels = texture::mip_levels(width, height);
+        let (tex, init) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            Dimensions::Dim2d { width, height },
+            format,
+            MipmapsCount::Specific(levels),
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                .. ImageUsage::none()
+            },
+            ImageLayout::ShaderReadOnlyOptimal,
+            self.queue_families()).unwrap();
+        let init = Arc::new(init);
+
+        let mut command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family()).unwrap();
+        let corner = |level: u32| {
+            let dimensions = texture::mip_dimensions(width, height, level);
+            [dimensions[0] as i32, dimensions[1] as i32, 1]
+        };
+
+        // Vulkano tracks access to whole images, so blitting from one level
+        // of `tex` to the next would conflict with itself. Instead, each level
+        // is first generated in a scratch image of its own.
+        let scratch: Vec<_> = (0..levels).map(|level| {
+            let dimensions = texture::mip_dimensions(width, height, level);
+            StorageImage::with_usage(
+                self.device.clone(),
+                Dimensions::Dim2d { width: dimensions[0], height: dimensions[1] },
+                format,
+                ImageUsage {
+                    transfer_source: true,
+                    transfer_destination: true,
+                    .. ImageUsage::none()
+                },
+                Some(self.queue.family())).unwrap()
+        }).collect();
+
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            data.into_iter()).expect("failed to create buffer");
+        command_buffer = command_buffer.copy_buffer_to_image(buffer, scratch[0].clone()).unwrap();
+
+        for level in 0..levels {
+            if level > 0 {
+                command_buffer = command_buffer.blit_image(
+                    scratch[level as usize - 1].clone(), [0, 0, 0], corner(level - 1), 0, 0,
+                    scratch[level as usize].clone(), [0, 0, 0], corner(level), 0, 0,
+                    1, Filter::Linear).unwrap();
+            }
+            command_buffer = command_buffer.blit_image(
+                scratch[level as usize].clone(), [0, 0, 0], corner(level), 0, 0,
+                init.clone(), [0, 0, 0], corner(level), 0, level,
+                1, Filter::Nearest).unwrap();
+        }
+
+        let future = command_buffer.build().unwrap()
+            .execute(self.queue.clone()).unwrap();
+        (tex, Box::new(future))
+    }
+
+    // The upload queue's family, and the graphics queue's if it differs.
+    fn queue_families(&self) -> Vec<QueueFamily> {
+        let mut families = vec![self.queue.family()];
+        if self.graphics_queue.family().id() != self.queue.family().id() {
+            families.push(self.graphics_queue.family());
+        }
+        families
+    }
+}